@@ -0,0 +1,532 @@
+//! Automatic test pattern generation for combinational chips, by PODEM.
+//!
+//! The chip is flattened and turned into a netlist of lines, one per pin:
+//! inputs, gate outputs, and the gate inputs and chip outputs they fan out
+//! to, so a fault on one branch of a fanout is told apart from one on the
+//! stem. For each fault not yet detected PODEM searches the input space,
+//! assigning one input at a time, for a vector that makes an output differ.
+//! Every vector found is fault-simulated to drop the other faults it
+//! detects, and a final pass in reverse order keeps only the vectors that
+//! detect something the later ones do not. A fault whose search space is
+//! exhausted is redundant: no vector can detect it.
+
+use crate::circuit::Chip;
+use crate::edit;
+use crate::error::LgsimError;
+use crate::fault::{self, Fault, FaultSite, Ports};
+use crate::gate::Gate;
+use crate::runner::TestVector;
+use crate::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Backtracks PODEM may make for one fault before giving up on it.
+pub const BACKTRACK_LIMIT: usize = 10_000;
+
+/// A three-valued signal: `None` is unknown.
+type Value = Option<bool>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// The input port at this position.
+    Input(usize),
+    /// A pin nothing drives, which simulates as 0.
+    Zero,
+    /// A pin driven by another line.
+    Wire(usize),
+    And(Vec<usize>),
+    Not(usize),
+}
+
+/// How a pin gets its value, before lines are numbered.
+#[derive(Debug, Clone)]
+enum Def {
+    Input(usize),
+    Wire(PinId),
+    And(Vec<PinId>),
+    Not(PinId),
+}
+
+/// A combinational circuit as lines in topological order: each line reads
+/// only lines before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Netlist {
+    lines: Vec<Line>,
+    /// The pin each line is.
+    pins: Vec<PinId>,
+    inputs: usize,
+    outputs: Vec<usize>,
+}
+
+impl Netlist {
+    /// Builds the netlist of a flattened chip. Fails on gates other than AND
+    /// and NOT (apart from Source and Output gates used as ports) and on
+    /// feedback loops.
+    pub fn new(flat: &Chip, ports: &Ports) -> Result<Self, LgsimError> {
+        let mut defs: HashMap<PinId, Def> = HashMap::new();
+        for (k, pin) in ports.inputs.iter().enumerate() {
+            defs.insert(*pin, Def::Input(k));
+        }
+        let mut ids: Vec<&GateId> = flat.gates.keys().collect();
+        ids.sort();
+        for id in ids {
+            let gate = &flat.gates[id];
+            match gate {
+                Gate::And(g) => {
+                    defs.insert(g.output[0], Def::And(g.input.clone()));
+                }
+                Gate::Not(g) => {
+                    defs.insert(g.output[0], Def::Not(g.input[0]));
+                }
+                Gate::Source(_) | Gate::Output(_) => {}
+                other => return Err(LgsimError::UnsupportedGate(other.gate_type())),
+            }
+            for pin in gate.input() {
+                if let Some(driver) = flat.driver_of(pin) {
+                    defs.insert(*pin, Def::Wire(driver));
+                }
+            }
+        }
+        for pin in &ports.outputs {
+            if !defs.contains_key(pin)
+                && let Some(driver) = flat.driver_of(pin)
+            {
+                defs.insert(*pin, Def::Wire(driver));
+            }
+        }
+
+        let mut net = Self {
+            lines: Vec::new(),
+            pins: Vec::new(),
+            inputs: ports.inputs.len(),
+            outputs: Vec::new(),
+        };
+        let mut index = HashMap::new();
+        let mut visiting = HashSet::new();
+        for pin in &ports.inputs {
+            net.visit(*pin, &defs, &mut index, &mut visiting)?;
+        }
+        let mut pins: Vec<PinId> = defs.keys().copied().collect();
+        pins.sort();
+        for pin in pins {
+            net.visit(pin, &defs, &mut index, &mut visiting)?;
+        }
+        for pin in &ports.outputs {
+            let line = net.visit(*pin, &defs, &mut index, &mut visiting)?;
+            net.outputs.push(line);
+        }
+        Ok(net)
+    }
+
+    /// Numbers `pin` after every line it reads, returning its line.
+    fn visit(
+        &mut self,
+        pin: PinId,
+        defs: &HashMap<PinId, Def>,
+        index: &mut HashMap<PinId, usize>,
+        visiting: &mut HashSet<PinId>,
+    ) -> Result<usize, LgsimError> {
+        if let Some(&line) = index.get(&pin) {
+            return Ok(line);
+        }
+        if !visiting.insert(pin) {
+            return Err(LgsimError::CombinationalLoop(pin));
+        }
+        let line = match defs.get(&pin) {
+            None => Line::Zero,
+            Some(Def::Input(k)) => Line::Input(*k),
+            Some(Def::Wire(p)) => Line::Wire(self.visit(*p, defs, index, visiting)?),
+            Some(Def::Not(p)) => Line::Not(self.visit(*p, defs, index, visiting)?),
+            Some(Def::And(ps)) => Line::And(
+                ps.iter()
+                    .map(|p| self.visit(*p, defs, index, visiting))
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+        visiting.remove(&pin);
+        self.lines.push(line);
+        self.pins.push(pin);
+        index.insert(pin, self.lines.len() - 1);
+        Ok(self.lines.len() - 1)
+    }
+
+    /// Values of every line for `inputs`, with `fault` (a line and the value
+    /// it is stuck at) injected if given.
+    fn eval(&self, inputs: &[Value], fault: Option<(usize, bool)>) -> Vec<Value> {
+        let mut values: Vec<Value> = Vec::with_capacity(self.lines.len());
+        for (i, line) in self.lines.iter().enumerate() {
+            let value = match line {
+                Line::Input(k) => inputs[*k],
+                Line::Zero => Some(false),
+                Line::Wire(d) => values[*d],
+                Line::Not(d) => values[*d].map(|v| !v),
+                Line::And(ins) => {
+                    if ins.iter().any(|&k| values[k] == Some(false)) {
+                        Some(false)
+                    } else if ins.iter().all(|&k| values[k] == Some(true)) {
+                        Some(true)
+                    } else {
+                        None
+                    }
+                }
+            };
+            values.push(match fault {
+                Some((line, stuck)) if line == i => Some(stuck),
+                _ => value,
+            });
+        }
+        values
+    }
+
+    /// Whether an output is known to differ between the good and faulty
+    /// circuits.
+    fn differs(&self, good: &[Value], bad: &[Value]) -> bool {
+        self.outputs
+            .iter()
+            .any(|&o| good[o].is_some() && bad[o].is_some() && good[o] != bad[o])
+    }
+
+    fn detects(&self, inputs: &[Value], fault: (usize, bool)) -> bool {
+        self.differs(&self.eval(inputs, None), &self.eval(inputs, Some(fault)))
+    }
+}
+
+/// Whether line `k` is still X in the good or the faulty circuit.
+fn unknown(good: &[Value], bad: &[Value], k: usize) -> bool {
+    good[k].is_none() || bad[k].is_none()
+}
+
+enum Search {
+    Found,
+    Failed,
+    Aborted,
+}
+
+struct Podem<'a> {
+    net: &'a Netlist,
+    fault: (usize, bool),
+    assignment: Vec<Value>,
+    backtracks: usize,
+}
+
+impl Podem<'_> {
+    fn search(&mut self) -> Search {
+        let good = self.net.eval(&self.assignment, None);
+        let bad = self.net.eval(&self.assignment, Some(self.fault));
+        if self.net.differs(&good, &bad) {
+            return Search::Found;
+        }
+        let Some((line, value)) = self.objective(&good, &bad) else {
+            return Search::Failed;
+        };
+        let Some((input, value)) = self.backtrace(line, value, &good, &bad) else {
+            return Search::Failed;
+        };
+        for value in [value, !value] {
+            self.assignment[input] = Some(value);
+            match self.search() {
+                Search::Failed => {}
+                done => return done,
+            }
+            self.backtracks += 1;
+            if self.backtracks > BACKTRACK_LIMIT {
+                return Search::Aborted;
+            }
+        }
+        self.assignment[input] = None;
+        Search::Failed
+    }
+
+    /// The next line value to aim for: the fault line at the opposite of its
+    /// stuck value until the fault is activated, then a non-controlling 1 on
+    /// an unknown input of a gate on the D-frontier. A line is unknown if it
+    /// is in either circuit.
+    fn objective(&self, good: &[Value], bad: &[Value]) -> Option<(usize, bool)> {
+        let (line, stuck) = self.fault;
+        match good[line] {
+            None => return Some((line, !stuck)),
+            Some(v) if v == stuck => return None,
+            Some(_) => {}
+        }
+        let is_d = |k: usize| good[k].is_some() && bad[k].is_some() && good[k] != bad[k];
+        self.net
+            .lines
+            .iter()
+            .enumerate()
+            .find_map(|(i, l)| match l {
+                Line::And(ins) if unknown(good, bad, i) => {
+                    if !ins.iter().any(|&k| is_d(k)) {
+                        return None;
+                    }
+                    ins.iter()
+                        .find(|&&k| unknown(good, bad, k))
+                        .map(|&k| (k, true))
+                }
+                _ => None,
+            })
+    }
+
+    /// Follows unknown lines back from the objective to an unassigned input
+    /// and the value to try on it.
+    fn backtrace(
+        &self,
+        mut line: usize,
+        mut value: bool,
+        good: &[Value],
+        bad: &[Value],
+    ) -> Option<(usize, bool)> {
+        loop {
+            match &self.net.lines[line] {
+                Line::Input(k) => return Some((*k, value)),
+                Line::Zero => return None,
+                Line::Wire(d) => line = *d,
+                Line::Not(d) => {
+                    line = *d;
+                    value = !value;
+                }
+                Line::And(ins) => line = *ins.iter().find(|&&k| unknown(good, bad, k))?,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atpg {
+    /// Test vectors with the outputs the fault-free chip gives.
+    pub vectors: Vec<TestVector>,
+    pub detected: Vec<FaultSite>,
+    /// Faults no vector can detect.
+    pub redundant: Vec<FaultSite>,
+    /// Faults given up on after `BACKTRACK_LIMIT` backtracks.
+    pub aborted: Vec<FaultSite>,
+}
+
+impl Atpg {
+    /// Detected faults as a percentage of the detectable ones.
+    pub fn efficiency(&self) -> f64 {
+        let detectable = self.detected.len() + self.aborted.len();
+        if detectable == 0 {
+            return 100.0;
+        }
+        100.0 * self.detected.len() as f64 / detectable as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Detected,
+    Redundant,
+    Aborted,
+}
+
+/// Generates test vectors for every stuck-at fault of the combinational
+/// `chip`, driving and observing it through `ports`.
+pub fn generate(chip: &Chip, ports: &Ports) -> Result<Atpg, LgsimError> {
+    let flat = edit::flatten(chip)?;
+    let net = Netlist::new(&flat, ports)?;
+    let faults: Vec<(usize, bool)> = (0..net.lines.len())
+        .flat_map(|l| [(l, false), (l, true)])
+        .collect();
+    let mut status = vec![Status::Pending; faults.len()];
+    let mut tests: Vec<Vec<Value>> = Vec::new();
+    for i in 0..faults.len() {
+        if status[i] != Status::Pending {
+            continue;
+        }
+        let mut podem = Podem {
+            net: &net,
+            fault: faults[i],
+            assignment: vec![None; net.inputs],
+            backtracks: 0,
+        };
+        match podem.search() {
+            Search::Found => {
+                // Unknown inputs cannot undo a detection, so any fill works.
+                let test: Vec<Value> = podem
+                    .assignment
+                    .iter()
+                    .map(|v| Some(v.unwrap_or(false)))
+                    .collect();
+                for (j, &fault) in faults.iter().enumerate() {
+                    if status[j] == Status::Pending && net.detects(&test, fault) {
+                        status[j] = Status::Detected;
+                    }
+                }
+                tests.push(test);
+            }
+            Search::Failed => status[i] = Status::Redundant,
+            Search::Aborted => status[i] = Status::Aborted,
+        }
+    }
+
+    let mut covered = vec![false; faults.len()];
+    let mut kept = Vec::new();
+    for test in tests.into_iter().rev() {
+        let mut useful = false;
+        for (j, &fault) in faults.iter().enumerate() {
+            if status[j] == Status::Detected && !covered[j] && net.detects(&test, fault) {
+                covered[j] = true;
+                useful = true;
+            }
+        }
+        if useful {
+            kept.push(test);
+        }
+    }
+    kept.reverse();
+
+    let vectors = kept
+        .iter()
+        .map(|test| {
+            let good = net.eval(test, None);
+            TestVector {
+                inputs: test
+                    .iter()
+                    .map(|v| v.unwrap_or(false) as PinValue)
+                    .collect(),
+                outputs: net
+                    .outputs
+                    .iter()
+                    .map(|&o| good[o].map(PinValue::from))
+                    .collect(),
+            }
+        })
+        .collect();
+    let mut atpg = Atpg {
+        vectors,
+        detected: Vec::new(),
+        redundant: Vec::new(),
+        aborted: Vec::new(),
+    };
+    for (&(line, stuck), status) in faults.iter().zip(status) {
+        let pin = net.pins[line];
+        let site = FaultSite {
+            path: fault::locate(chip, &pin).unwrap_or_default(),
+            pin,
+            fault: if stuck {
+                Fault::StuckAt1
+            } else {
+                Fault::StuckAt0
+            },
+        };
+        match status {
+            Status::Detected => atpg.detected.push(site),
+            Status::Redundant => atpg.redundant.push(site),
+            Status::Aborted | Status::Pending => atpg.aborted.push(site),
+        }
+    }
+    Ok(atpg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+
+    /// `y = (a AND NOT a) OR b`. Returns the chip and the AND's output.
+    fn redundant() -> (Chip, PinId) {
+        let mut b = ChipBuilder::new();
+        let (a, c) = (b.input(), b.input());
+        let not_a = b.not(a);
+        let never = b.and(a, not_a);
+        let y = b.or(never, c);
+        b.output(y);
+        (b.build().unwrap(), never.0)
+    }
+
+    /// A half adder inside a chip instance.
+    fn nested_half_adder() -> Chip {
+        let half_adder = crate::circuit! {
+            inputs: [a, b];
+            let s = xor(a, b);
+            let c = and(a, b);
+            outputs: [s, c];
+        }
+        .unwrap();
+        let mut b = ChipBuilder::new();
+        let (x, y) = (b.input(), b.input());
+        for out in b.instance(&half_adder, &[x, y]) {
+            b.output(out);
+        }
+        b.build().unwrap()
+    }
+
+    fn inputs(atpg: &Atpg) -> Vec<Vec<PinValue>> {
+        atpg.vectors.iter().map(|v| v.inputs.clone()).collect()
+    }
+
+    #[test]
+    fn finds_redundant_faults() {
+        let (chip, never) = redundant();
+        let ports = Ports::of_chip(&chip);
+        let atpg = generate(&chip, &ports).unwrap();
+        assert!(
+            atpg.redundant
+                .iter()
+                .any(|f| f.pin == never && f.fault == Fault::StuckAt0)
+        );
+        assert!(atpg.aborted.is_empty());
+        assert_eq!(atpg.efficiency(), 100.0);
+
+        // Not even every input combination detects a redundant fault.
+        let all: Vec<Vec<PinValue>> = (0..4).map(|k| vec![k & 1, k >> 1]).collect();
+        let report = fault::simulate_faults(&chip, &ports, atpg.redundant.clone(), &all).unwrap();
+        assert_eq!(report.detected(), 0);
+    }
+
+    #[test]
+    fn every_vector_detects_faults() {
+        for chip in [nested_half_adder(), redundant().0] {
+            let ports = Ports::of_chip(&chip);
+            let atpg = generate(&chip, &ports).unwrap();
+            assert!(!atpg.detected.is_empty());
+            assert!(atpg.aborted.is_empty());
+
+            let vectors = inputs(&atpg);
+            let report =
+                fault::simulate_faults(&chip, &ports, atpg.detected.clone(), &vectors).unwrap();
+            assert_eq!(report.coverage(), 100.0);
+            // Compaction leaves no vector that detects nothing on its own.
+            for vector in &vectors {
+                let report = fault::simulate_faults(
+                    &chip,
+                    &ports,
+                    atpg.detected.clone(),
+                    std::slice::from_ref(vector),
+                )
+                .unwrap();
+                assert!(report.detected() > 0, "{:?}", vector);
+            }
+
+            // The expected outputs are the fault-free chip's.
+            let mut runner = crate::runner::Runner::new(chip, None).unwrap();
+            for v in &atpg.vectors {
+                assert_eq!(runner.apply(v).unwrap(), v.outputs);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_feedback_and_unsupported_gates() {
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let latch = b.gate(crate::gate::GateType::And);
+        b.connect(a, latch.input(0));
+        b.connect(latch.output(0), latch.input(1));
+        b.output(latch.output(0));
+        let chip = b.build().unwrap();
+        assert!(matches!(
+            generate(&chip, &Ports::of_chip(&chip)),
+            Err(LgsimError::CombinationalLoop(_))
+        ));
+
+        let mut b = ChipBuilder::new();
+        let r = b.gate(crate::gate::GateType::Register);
+        b.output(r.output(0));
+        let chip = b.build().unwrap();
+        assert!(matches!(
+            generate(&chip, &Ports::of_chip(&chip)),
+            Err(LgsimError::UnsupportedGate(_))
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::{Gate, GateType};
+use crate::pin::PinType;
+use crate::types::{ChipId, GateId, PinId};
+
+/// A pin inside the chip being built. Handles that come out of `input()` or a
+/// gate output are drivers; handles from `GateHandle::input` are sinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PinHandle(pub PinId);
+
+impl PinHandle {
+    /// Stands for a pin picked past the end of a gate's pins. Wiring it makes
+    /// `build` fail with `NoSuchPin`.
+    const MISSING: PinHandle = PinHandle(PinId(usize::MAX));
+}
+
+/// A gate placed in the chip being built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateHandle {
+    pub id: GateId,
+    pub inputs: Vec<PinHandle>,
+    pub outputs: Vec<PinHandle>,
+}
+
+impl GateHandle {
+    /// Input `idx`. A position the gate does not have is reported by the
+    /// builder's `build` once the handle is wired.
+    pub fn input(&self, idx: usize) -> PinHandle {
+        self.inputs.get(idx).copied().unwrap_or(PinHandle::MISSING)
+    }
+
+    /// Output `idx`, reported like `input` if the gate does not have it.
+    pub fn output(&self, idx: usize) -> PinHandle {
+        self.outputs.get(idx).copied().unwrap_or(PinHandle::MISSING)
+    }
+}
+
+/// Describes a chip in code instead of on the canvas.
+///
+/// ```
+/// use lgsim::builder::ChipBuilder;
+///
+/// let mut b = ChipBuilder::new();
+/// let (a, c_in) = (b.input(), b.input());
+/// let s = b.xor(a, c_in);
+/// let c = b.and(a, c_in);
+/// b.output(s);
+/// b.output(c);
+/// let half_adder = b.build().unwrap();
+/// assert_eq!(half_adder.input.len(), 2);
+/// ```
+pub struct ChipBuilder {
+    chip: Chip,
+    // First failure, reported by `build` so descriptions can stay chained.
+    error: Option<LgsimError>,
+}
+
+impl Default for ChipBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChipBuilder {
+    pub fn new() -> Self {
+        Self {
+            chip: Chip::new(ChipId(0)),
+            error: None,
+        }
+    }
+
+    /// Adds a shell input pin and returns it as a driver.
+    pub fn input(&mut self) -> PinHandle {
+        PinHandle(self.chip.add_shell_pin(PinType::ChipInput))
+    }
+
+    /// Adds a shell output pin driven by `src`.
+    pub fn output(&mut self, src: PinHandle) -> PinHandle {
+        let out = PinHandle(self.chip.add_shell_pin(PinType::ChipOutput));
+        self.connect(src, out);
+        out
+    }
+
+    /// Places an unconnected gate.
+    pub fn gate(&mut self, gtype: GateType) -> GateHandle {
+        let gate = Gate::new(gtype, vec![], &mut self.chip.ids);
+        self.place(gate)
+    }
+
+    /// Places a fresh instance of `template`.
+    pub fn chip(&mut self, template: &Chip) -> GateHandle {
+        let copy = match template.deep_copy(&mut self.chip.ids) {
+            Ok(copy) => copy,
+            Err(e) => {
+                self.fail(e);
+                Chip::new(self.chip.ids.chip())
+            }
+        };
+        self.place(Gate::Chip(copy))
+    }
+
+    pub fn connect(&mut self, from: PinHandle, to: PinHandle) {
+        if from == PinHandle::MISSING || to == PinHandle::MISSING {
+            return self.fail(LgsimError::NoSuchPin);
+        }
+        if let Err(e) = self.chip.connect_pins(from.0, to.0) {
+            self.fail(e);
+        }
+    }
+
+    /// Places an instance of `template`, wires `inputs` to its shell inputs in
+    /// order and returns its shell outputs. There must be one input handle
+    /// per shell input; a mismatch is reported by `build`.
+    pub fn instance(&mut self, template: &Chip, inputs: &[PinHandle]) -> Vec<PinHandle> {
+        let handle = self.chip(template);
+        if let Err(e) = self.wire_inputs(&handle, inputs) {
+            self.fail(e);
+        }
+        handle.outputs
+    }
+
+    pub fn and(&mut self, a: PinHandle, b: PinHandle) -> PinHandle {
+        let g = self.gate(GateType::And);
+        if let Err(e) = self.wire_inputs(&g, &[a, b]) {
+            self.fail(e);
+        }
+        g.output(0)
+    }
+
+    pub fn not(&mut self, a: PinHandle) -> PinHandle {
+        let g = self.gate(GateType::Not);
+        if let Err(e) = self.wire_inputs(&g, &[a]) {
+            self.fail(e);
+        }
+        g.output(0)
+    }
+
+    pub fn nand(&mut self, a: PinHandle, b: PinHandle) -> PinHandle {
+        let x = self.and(a, b);
+        self.not(x)
+    }
+
+    pub fn or(&mut self, a: PinHandle, b: PinHandle) -> PinHandle {
+        let na = self.not(a);
+        let nb = self.not(b);
+        self.nand(na, nb)
+    }
+
+    pub fn nor(&mut self, a: PinHandle, b: PinHandle) -> PinHandle {
+        let x = self.or(a, b);
+        self.not(x)
+    }
+
+    pub fn xor(&mut self, a: PinHandle, b: PinHandle) -> PinHandle {
+        let nb = self.not(b);
+        let na = self.not(a);
+        let l = self.and(a, nb);
+        let r = self.and(na, b);
+        self.or(l, r)
+    }
+
+    pub fn build(self) -> Result<Chip, LgsimError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.chip),
+        }
+    }
+
+    fn fail(&mut self, e: LgsimError) {
+        self.error.get_or_insert(e);
+    }
+
+    fn place(&mut self, gate: Gate) -> GateHandle {
+        let handle = GateHandle {
+            id: gate.id(),
+            inputs: gate.input().iter().map(|&p| PinHandle(p)).collect(),
+            outputs: gate.output().iter().map(|&p| PinHandle(p)).collect(),
+        };
+        self.chip.add_gate(gate);
+        handle
+    }
+
+    fn wire_inputs(&mut self, gate: &GateHandle, inputs: &[PinHandle]) -> Result<(), LgsimError> {
+        if inputs.len() != gate.inputs.len() {
+            return Err(LgsimError::InputCountMismatch {
+                expected: gate.inputs.len(),
+                given: inputs.len(),
+            });
+        }
+        for (&src, &dst) in inputs.iter().zip(gate.inputs.iter()) {
+            self.connect(src, dst);
+        }
+        Ok(())
+    }
+}
+
+/// Shorthand for a straight-line `ChipBuilder` description. Each `let` calls
+/// the builder method of the same name, so nested expressions are not
+/// supported.
+///
+/// ```
+/// let half_adder = lgsim::circuit! {
+///     inputs: [a, b];
+///     let s = xor(a, b);
+///     let c = and(a, b);
+///     outputs: [s, c];
+/// }
+/// .unwrap();
+/// assert_eq!(half_adder.output.len(), 2);
+/// ```
+#[macro_export]
+macro_rules! circuit {
+    (
+        inputs: [$($input:ident),* $(,)?];
+        $(let $name:ident = $op:ident($($arg:expr),* $(,)?);)*
+        outputs: [$($output:expr),* $(,)?] $(;)?
+    ) => {{
+        let mut __builder = $crate::builder::ChipBuilder::new();
+        $(let $input = __builder.input();)*
+        $(let $name = __builder.$op($($arg),*);)*
+        $(__builder.output($output);)*
+        __builder.build()
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn half_adder() -> Chip {
+        crate::circuit! {
+            inputs: [a, b];
+            let s = xor(a, b);
+            let c = and(a, b);
+            outputs: [s, c];
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn instance_wires_every_input() {
+        let template = half_adder();
+        let mut b = ChipBuilder::new();
+        let (x, y) = (b.input(), b.input());
+        let out = b.instance(&template, &[x, y]);
+        assert_eq!(out.len(), 2);
+        b.output(out[0]);
+        let chip = b.build().unwrap();
+        let inner = chip.gates.values().find_map(|g| match g {
+            Gate::Chip(c) => Some(c),
+            _ => None,
+        });
+        for pin in &inner.unwrap().input {
+            assert!(chip.driver_of(pin).is_some());
+        }
+    }
+
+    #[test]
+    fn instance_reports_wrong_input_count() {
+        let template = half_adder();
+        let mut b = ChipBuilder::new();
+        let x = b.input();
+        // The handles still come back, so the description can carry on.
+        let few = b.instance(&template, &[x]);
+        assert_eq!(few.len(), 2);
+        b.output(few[0]);
+        b.instance(&template, &[x, x, x]);
+        // The first mismatch is the one reported.
+        assert_eq!(
+            b.build(),
+            Err(LgsimError::InputCountMismatch {
+                expected: 2,
+                given: 1
+            })
+        );
+    }
+
+    #[test]
+    fn bad_pin_positions_are_reported() {
+        let mut b = ChipBuilder::new();
+        let x = b.input();
+        let not = b.gate(GateType::Not);
+        b.connect(x, not.input(1));
+        assert_eq!(b.build(), Err(LgsimError::NoSuchPin));
+
+        let mut b = ChipBuilder::new();
+        let not = b.gate(GateType::Not);
+        b.output(not.output(3));
+        assert_eq!(b.build(), Err(LgsimError::NoSuchPin));
+    }
+}
@@ -0,0 +1,123 @@
+use eframe::egui::{Pos2, Rect, Vec2};
+
+pub const GRID_SIZE: f32 = 20.0;
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 4.0;
+
+/// Maps board ("world") coordinates, which is what `VisualNode.pos` and the
+/// global I/O positions are stored in, to screen coordinates on the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Screen offset of the world origin from the canvas' top-left corner.
+    pub pan: Vec2,
+    pub zoom: f32,
+    /// Screen rect of the canvas, refreshed every frame.
+    pub viewport: Rect,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+            viewport: Rect::ZERO,
+        }
+    }
+}
+
+impl Camera {
+    pub fn to_screen(&self, world: Pos2) -> Pos2 {
+        self.viewport.min + self.pan + world.to_vec2() * self.zoom
+    }
+
+    pub fn to_world(&self, screen: Pos2) -> Pos2 {
+        ((screen - self.viewport.min - self.pan) / self.zoom).to_pos2()
+    }
+
+    pub fn rect_to_screen(&self, world: Rect) -> Rect {
+        Rect::from_min_max(self.to_screen(world.min), self.to_screen(world.max))
+    }
+
+    /// Zooms by `factor`, keeping the world point under `anchor` in place.
+    pub fn zoom_around(&mut self, anchor: Pos2, factor: f32) {
+        let world = self.to_world(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = anchor - self.viewport.min - world.to_vec2() * self.zoom;
+    }
+
+    /// Centers `world` in the viewport at the largest zoom that shows all of it.
+    pub fn fit(&mut self, world: Rect) {
+        if world.width() <= 0.0 || world.height() <= 0.0 {
+            return;
+        }
+        let zoom_x = self.viewport.width() / world.width();
+        let zoom_y = self.viewport.height() / world.height();
+        self.zoom = zoom_x.min(zoom_y).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = self.viewport.size() / 2.0 - world.center().to_vec2() * self.zoom;
+    }
+}
+
+pub fn snap_to_grid(pos: Pos2) -> Pos2 {
+    Pos2::new(
+        (pos.x / GRID_SIZE).round() * GRID_SIZE,
+        (pos.y / GRID_SIZE).round() * GRID_SIZE,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera {
+            pan: Vec2::new(30.0, -10.0),
+            zoom: 2.0,
+            viewport: Rect::from_min_size(Pos2::new(100.0, 50.0), Vec2::new(800.0, 600.0)),
+        }
+    }
+
+    #[test]
+    fn screen_and_world_are_inverse() {
+        let camera = camera();
+        let world = Pos2::new(12.5, -40.0);
+        assert_eq!(camera.to_screen(world), Pos2::new(155.0, -40.0));
+        assert_eq!(camera.to_world(camera.to_screen(world)), world);
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_in_place() {
+        let mut camera = camera();
+        let anchor = Pos2::new(400.0, 300.0);
+        let world = camera.to_world(anchor);
+        camera.zoom_around(anchor, 1.5);
+        assert_eq!(camera.zoom, 3.0);
+        assert!((camera.to_screen(world) - anchor).length() < 1e-3);
+
+        camera.zoom_around(anchor, 100.0);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        camera.zoom_around(anchor, 0.0001);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        assert!((camera.to_screen(world) - anchor).length() < 1e-3);
+    }
+
+    #[test]
+    fn fit_centers_the_whole_rect() {
+        let mut camera = camera();
+        let world = Rect::from_min_max(Pos2::new(-100.0, 0.0), Pos2::new(300.0, 100.0));
+        camera.fit(world);
+        // The width limits the zoom: 800 pixels for 400 units.
+        assert_eq!(camera.zoom, 2.0);
+        assert_eq!(camera.to_screen(world.center()), camera.viewport.center());
+        assert!(camera.viewport.contains_rect(camera.rect_to_screen(world)));
+
+        let before = camera;
+        camera.fit(Rect::from_min_max(Pos2::ZERO, Pos2::new(0.0, 50.0)));
+        assert_eq!(camera, before);
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_grid_point() {
+        assert_eq!(snap_to_grid(Pos2::new(29.0, -31.0)), Pos2::new(20.0, -40.0));
+        assert_eq!(snap_to_grid(Pos2::new(31.0, 9.0)), Pos2::new(40.0, 0.0));
+    }
+}
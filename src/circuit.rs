@@ -1,36 +1,110 @@
-use crate::{gate::*, pin::*, types::*};
+use crate::{error::LgsimError, gate::*, pin::*, types::*};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, PartialEq)]
+/// The gates of a chip in the order a delta cycle evaluates them, and the
+/// gate each gate input pin belongs to, so a wire's sink is found without
+/// searching every gate.
+struct Schedule {
+    order: Vec<GateId>,
+    sinks: HashMap<PinId, GateId>,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Chip {
-    pub id: usize,
+    pub id: ChipId,
+    /// Name of the library template this chip is, or is an instance of.
+    pub name: Option<String>,
     pub gates: Gates,
     pub connections: Connections,
-    pub pins: HashMap<usize, Pin>,
-    pub input: Vec<usize>,
-    pub output: Vec<usize>,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
+    pub output: Vec<PinId>,
+    pub ids: IdAllocator,
+    /// Pins of this level held at a stuck value by fault simulation.
+    pub faults: HashMap<PinId, PinValue>,
+}
+
+impl Clone for Chip {
+    /// Copies the chip with its IDs unchanged. The copy counts IDs on its
+    /// own, and the instances nested in it share its allocator, as they do
+    /// the original's.
+    fn clone(&self) -> Self {
+        let mut copy = Chip {
+            id: self.id,
+            name: self.name.clone(),
+            gates: self.gates.clone(),
+            connections: self.connections.clone(),
+            pins: self.pins.clone(),
+            input: self.input.clone(),
+            output: self.output.clone(),
+            ids: self.ids.clone(),
+            faults: self.faults.clone(),
+        };
+        let ids = copy.ids.share();
+        copy.share_ids(&ids);
+        copy
+    }
 }
 
 impl Chip {
-    pub fn new(id: usize) -> Chip {
+    pub fn new(id: ChipId) -> Chip {
         Chip {
             id,
+            name: None,
             gates: Gates::new(),
             connections: HashMap::new(),
             pins: HashMap::new(),
             input: vec![],
             output: vec![],
+            ids: IdAllocator::default(),
+            faults: HashMap::new(),
+        }
+    }
+
+    /// Makes every instance nested in this chip allocate from `ids`.
+    fn share_ids(&mut self, ids: &IdAllocator) {
+        for gate in self.gates.values_mut() {
+            if let Gate::Chip(inner) = gate {
+                inner.ids = ids.share();
+                inner.share_ids(ids);
+            }
         }
     }
 
-    pub fn add_gate(&mut self, gate: Gate) -> usize {
+    pub fn add_gate(&mut self, gate: Gate) -> GateId {
         let id = gate.id();
         self.gates.insert(id, gate);
         id
     }
 
-    pub fn add_shell_pin(&mut self, kind: PinType) -> usize {
-        let pin = Pin::new(kind, self.id, 0);
+    /// Removes a gate together with every wire touching its pins.
+    pub fn remove_gate(&mut self, id: &GateId) -> Option<Gate> {
+        let gate = self.gates.remove(id)?;
+        for pin in gate.input().iter().chain(gate.output()) {
+            self.connections.remove(pin);
+            self.disconnect(pin);
+        }
+        Some(gate)
+    }
+
+    /// Every wire as a `(driver, sink)` pair, in a stable order.
+    pub fn wires(&self) -> Vec<(PinId, PinId)> {
+        let mut wires: Vec<(PinId, PinId)> = self
+            .connections
+            .iter()
+            .flat_map(|(src, dests)| dests.iter().map(move |dest| (*src, *dest)))
+            .collect();
+        wires.sort();
+        wires
+    }
+
+    pub fn add_shell_pin(&mut self, kind: PinType) -> PinId {
+        let pin = Pin::new(kind, self.id.into(), 0, &mut self.ids);
+        self.insert_shell_pin(pin)
+    }
+
+    fn insert_shell_pin(&mut self, pin: Pin) -> PinId {
+        let kind = pin.kind;
         self.pins.insert(pin.id, pin);
         if kind == PinType::ChipInput {
             self.input.push(pin.id);
@@ -40,110 +114,609 @@ impl Chip {
         pin.id
     }
 
-    pub fn connect_pins(&mut self, from_pin: usize, to_pin: usize) {
-        for connections in self.connections.values_mut() {
-            connections.retain(|&x| x != to_pin);
+    /// Looks a pin up among this chip's shell pins and the pins of its gates.
+    pub fn find_pin(&self, id: &PinId) -> Option<&Pin> {
+        self.pins
+            .get(id)
+            .or_else(|| self.gates.values().find_map(|g| g.pins().get(id)))
+    }
+
+    /// The gate on this level that holds `id`, on itself or on a gate nested
+    /// inside it.
+    pub fn top_gate_of(&self, id: &PinId) -> Option<GateId> {
+        fn holds(gate: &Gate, id: &PinId) -> bool {
+            gate.pins().contains_key(id)
+                || matches!(gate, Gate::Chip(c) if c.gates.values().any(|g| holds(g, id)))
         }
+        self.gates
+            .iter()
+            .find(|(_, g)| holds(g, id))
+            .map(|(gid, _)| *gid)
+    }
+
+    /// Whether `id` can drive a wire inside this chip. Shell inputs and gate
+    /// outputs drive; shell outputs and gate inputs are driven. A nested
+    /// chip's shell pins are gate pins from this chip's point of view.
+    pub fn is_driver(&self, id: &PinId) -> Result<bool, LgsimError> {
+        if let Some(pin) = self.pins.get(id) {
+            return Ok(pin.kind == PinType::ChipInput);
+        }
+        let pin = self.find_pin(id).ok_or(LgsimError::UnknownPin(*id))?;
+        Ok(matches!(
+            pin.kind,
+            PinType::GateOutput | PinType::ChipOutput
+        ))
+    }
+
+    pub fn driver_of(&self, id: &PinId) -> Option<PinId> {
+        self.connections
+            .iter()
+            .find(|(_, dests)| dests.contains(id))
+            .map(|(src, _)| *src)
+    }
+
+    /// Checks whether a wire from `from_pin` to `to_pin` would be legal
+    /// without changing anything. A second driver on `to_pin` is reported as
+    /// `DuplicateDriver` so callers can offer `replace_driver` instead.
+    pub fn check_connection(&self, from_pin: PinId, to_pin: PinId) -> Result<(), LgsimError> {
+        if from_pin == to_pin {
+            return Err(LgsimError::SelfConnection(from_pin));
+        }
+        if !self.is_driver(&from_pin)? || self.is_driver(&to_pin)? {
+            return Err(LgsimError::WrongDirection {
+                from: from_pin,
+                to: to_pin,
+            });
+        }
+        match self.driver_of(&to_pin) {
+            Some(existing) if existing == from_pin => Err(LgsimError::AlreadyConnected {
+                from: from_pin,
+                to: to_pin,
+            }),
+            Some(existing) => Err(LgsimError::DuplicateDriver {
+                pin: to_pin,
+                existing,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn connect_pins(&mut self, from_pin: PinId, to_pin: PinId) -> Result<(), LgsimError> {
+        self.check_connection(from_pin, to_pin)?;
         self.connections
             .entry(from_pin)
             .or_insert(vec![])
             .push(to_pin);
+        Ok(())
     }
 
-    pub fn set_pin(&mut self, id: &usize, val: Option<PinValue>) {
+    /// Like `connect_pins`, but removes the wire from an existing driver of
+    /// `to_pin` first. Only call this once the user has agreed to lose it.
+    pub fn replace_driver(&mut self, from_pin: PinId, to_pin: PinId) -> Result<(), LgsimError> {
+        match self.check_connection(from_pin, to_pin) {
+            Ok(()) => {}
+            Err(LgsimError::DuplicateDriver { .. }) => self.disconnect(&to_pin),
+            Err(e) => return Err(e),
+        }
+        self.connect_pins(from_pin, to_pin)
+    }
+
+    /// Removes the wire driving `to_pin`, if any.
+    pub fn disconnect(&mut self, to_pin: &PinId) {
+        for dests in self.connections.values_mut() {
+            dests.retain(|x| x != to_pin);
+        }
+        self.connections.retain(|_, dests| !dests.is_empty());
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
         if let Some(p) = self.pins.get_mut(id) {
-            p.val = val;
+            p.val = self.faults.get(id).map_or(val, |&v| Some(v));
+        }
+    }
+
+    /// Clocks the chip and runs delta cycles until it has had the chance to
+    /// settle. Returns whether it did: false means a feedback loop, here or
+    /// in a nested chip, was still changing after the last delta cycle.
+    pub fn simulate(&mut self) -> Result<bool, LgsimError> {
+        let schedule = self.schedule();
+        self.clock_with(&schedule)?;
+        // A delta cycle that changes nothing has reached a fixed point.
+        for _ in 0..self.gates.len() + 2 {
+            if self.delta_with(&schedule)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// One delta cycle: evaluates every gate once, in ID order, passing each
+    /// one's outputs on before the next. `simulate` repeats this until the
+    /// circuit settles.
+    pub fn delta(&mut self) -> Result<(), LgsimError> {
+        let schedule = self.schedule();
+        self.delta_with(&schedule).map(|_| ())
+    }
+
+    /// `delta`, returning whether it left every pin of this level as it was
+    /// and every nested chip settled.
+    fn delta_with(&mut self, schedule: &Schedule) -> Result<bool, LgsimError> {
+        let mut quiet = true;
+        for &gid in &schedule.order {
+            quiet &= self.evaluate(gid, schedule)?;
+        }
+        Ok(quiet)
+    }
+
+    /// Evaluates one gate and passes its outputs to the pins they drive.
+    pub fn evaluate_gate(&mut self, gid: GateId) -> Result<(), LgsimError> {
+        let schedule = self.schedule();
+        self.evaluate(gid, &schedule).map(|_| ())
+    }
+
+    /// `evaluate_gate`, returning false if it changed a pin or the gate is a
+    /// nested chip that did not settle.
+    fn evaluate(&mut self, gid: GateId, schedule: &Schedule) -> Result<bool, LgsimError> {
+        self.apply_faults(gid);
+        let gate = self
+            .gates
+            .get_mut(&gid)
+            .ok_or(LgsimError::UnknownGate(gid))?;
+        let before = output_values(gate)?;
+        let result = gate.evaluate()?;
+        let settled = !matches!(gate, Gate::Chip(_)) || result;
+        self.apply_faults(gid);
+        let after = output_values(&self.gates[&gid])?;
+        // Inputs only change through wires, so a gate whose outputs stay put
+        // leaves the circuit as it found it unless a wire changes a pin.
+        let unchanged = after == before;
+        let quiet = self.propagate_internal(after, schedule);
+        Ok(settled && unchanged && quiet)
+    }
+
+    /// Works out the evaluation order and the gate each wire sink belongs
+    /// to, once for every delta cycle of a run.
+    fn schedule(&self) -> Schedule {
+        let mut order: Vec<GateId> = self.gates.keys().copied().collect();
+        order.sort();
+        let sinks = self
+            .gates
+            .iter()
+            .flat_map(|(id, g)| g.input().iter().map(move |p| (*p, *id)))
+            .collect();
+        Schedule { order, sinks }
+    }
+
+    /// Sets the sink `pin` of a wire on this level to `val`, or to its stuck
+    /// value if it has a fault. Returns false if that changed the pin.
+    fn drive(&mut self, pin: PinId, val: Option<PinValue>, schedule: &Schedule) -> bool {
+        let val = self.faults.get(&pin).map_or(val, |&v| Some(v));
+        if let Some(gate) = schedule
+            .sinks
+            .get(&pin)
+            .and_then(|gid| self.gates.get_mut(gid))
+        {
+            let before = gate.pins().get(&pin).map(|p| p.val);
+            gate.set_pin(&pin, val);
+            gate.pins().get(&pin).map(|p| p.val) == before
+        } else if self.output.contains(&pin) {
+            // Shell inputs may be wired straight through to outputs.
+            let before = self.pins.get(&pin).map(|p| p.val);
+            self.set_pin(&pin, val);
+            self.pins.get(&pin).map(|p| p.val) == before
+        } else {
+            true
         }
     }
 
-    pub fn simulate(&mut self) -> bool {
-        for &in_pin in &self.input {
-            let val = self.pins.get(&in_pin).unwrap().val;
-            if let Some(targets) = self.connections.get(&in_pin) {
+    /// Holds the faulty pins of gate `gid` at their stuck values: inputs
+    /// before it is evaluated, outputs before they are passed on.
+    fn apply_faults(&mut self, gid: GateId) {
+        if self.faults.is_empty() {
+            return;
+        }
+        if let Some(gate) = self.gates.get_mut(&gid) {
+            for (pin, &val) in &self.faults {
+                if gate.pins().contains_key(pin) {
+                    gate.set_pin(pin, Some(val));
+                }
+            }
+        }
+    }
+
+    /// Value of every pin on this level (the shell and its gates, not the
+    /// inside of nested chips), to see what a step changed.
+    pub fn pin_values(&self) -> HashMap<PinId, Option<PinValue>> {
+        let shell = self.pins.values();
+        let gates = self.gates.values().flat_map(|g| g.pins().values());
+        shell.chain(gates).map(|p| (p.id, p.val)).collect()
+    }
+
+    /// Passes the shell inputs in and lets every clocked gate, here and in
+    /// nested chips, act on a rising edge before any of them drives a new
+    /// output, so a register fed by another register samples its old value.
+    /// Gates without inputs (board sources and input devices) may drive
+    /// clocks, so they are settled first.
+    pub fn clock(&mut self) -> Result<(), LgsimError> {
+        let schedule = self.schedule();
+        self.clock_with(&schedule)
+    }
+
+    fn clock_with(&mut self, schedule: &Schedule) -> Result<(), LgsimError> {
+        for in_pin in self.input.clone() {
+            let val = self
+                .pins
+                .get(&in_pin)
+                .ok_or(LgsimError::UnknownPin(in_pin))?
+                .val;
+            if let Some(targets) = self.connections.get(&in_pin).cloned() {
                 for target in targets {
-                    for gate in self.gates.values_mut() {
-                        if gate.input().contains(target) {
-                            gate.set_pin(target, val);
-                        }
-                    }
+                    self.drive(target, val, schedule);
                 }
             }
         }
 
-        let loops = self.gates.len() + 2;
-        for _ in 0..loops {
-            let ids: Vec<usize> = self.gates.keys().cloned().collect();
-            for gid in ids {
-                self.gates.get_mut(&gid).unwrap().evaluate();
-                self.propagate_internal(gid);
+        let drivers: Vec<GateId> = self
+            .gates
+            .iter()
+            .filter(|(_, g)| g.input().is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        for gid in drivers {
+            self.evaluate(gid, schedule)?;
+        }
+        let ids: Vec<GateId> = self.gates.keys().copied().collect();
+        for gid in ids {
+            self.apply_faults(gid);
+        }
+        for gate in self.gates.values_mut() {
+            gate.clock()?;
+        }
+        Ok(())
+    }
+
+    /// Calls `f` on every gate of this chip and of the chips nested in it.
+    pub fn for_each_gate(&self, f: &mut dyn FnMut(&Gate)) {
+        for gate in self.gates.values() {
+            if let Gate::Chip(inner) = gate {
+                inner.for_each_gate(f);
             }
+            f(gate);
         }
-        true
     }
 
-    fn propagate_internal(&mut self, gate_id: usize) {
-        let outputs = self.gates.get(&gate_id).unwrap().output().to_vec();
-        if outputs.is_empty() {
-            return;
+    pub fn for_each_gate_mut(&mut self, f: &mut dyn FnMut(&mut Gate)) {
+        for gate in self.gates.values_mut() {
+            if let Gate::Chip(inner) = gate {
+                inner.for_each_gate_mut(f);
+            }
+            f(gate);
         }
-        let val = self
-            .gates
-            .get(&gate_id)
-            .unwrap()
-            .pins()
-            .get(&outputs[0])
-            .unwrap()
-            .val;
+    }
+
+    /// The gate at `path`: gates from this chip down, every one but the last
+    /// a chip instance.
+    pub fn gate_at(&self, path: &[GateId]) -> Option<&Gate> {
+        let (last, parents) = path.split_last()?;
+        let mut chip = self;
+        for id in parents {
+            match chip.gates.get(id)? {
+                Gate::Chip(inner) => chip = inner,
+                _ => return None,
+            }
+        }
+        chip.gates.get(last)
+    }
 
-        for out_pin in outputs {
+    pub fn gate_at_mut(&mut self, path: &[GateId]) -> Option<&mut Gate> {
+        let (last, parents) = path.split_last()?;
+        let mut chip = self;
+        for id in parents {
+            match chip.gates.get_mut(id)? {
+                Gate::Chip(inner) => chip = inner,
+                _ => return None,
+            }
+        }
+        chip.gates.get_mut(last)
+    }
+
+    /// Passes `outputs`, a gate's output pins with their values, on along
+    /// their wires. Returns false if that changed a pin.
+    fn propagate_internal(
+        &mut self,
+        outputs: Vec<(PinId, Option<PinValue>)>,
+        schedule: &Schedule,
+    ) -> bool {
+        let mut quiet = true;
+        for (out_pin, val) in outputs {
             if let Some(targets) = self.connections.get(&out_pin).cloned() {
                 for target_pin in targets {
-                    let mut found_gate = false;
-                    for gate in self.gates.values_mut() {
-                        if gate.input().contains(&target_pin) {
-                            gate.set_pin(&target_pin, val);
-                            found_gate = true;
-                        }
-                    }
-                    if !found_gate {
-                        if self.output.contains(&target_pin) {
-                            self.set_pin(&target_pin, val);
-                        }
-                    }
+                    quiet &= self.drive(target_pin, val, schedule);
                 }
             }
         }
+        quiet
     }
 
-    pub fn deep_copy(&self) -> Chip {
-        // FIX: Use global counter, not a local static!
-        let new_chip_id = crate::pin::next_uuid();
+    /// Copies this chip with every ID re-allocated from `ids`, which belongs
+    /// to the chip the copy is going to be placed in. Gates and connections
+    /// are visited in ID order so the same source always produces the same
+    /// copy.
+    pub fn deep_copy(&self, ids: &mut IdAllocator) -> Result<Chip, LgsimError> {
+        let new_chip_id = ids.chip();
         let mut new_chip = Chip::new(new_chip_id);
-        let mut id_map: HashMap<usize, usize> = HashMap::new();
+        new_chip.name = self.name.clone();
+        let mut id_map: HashMap<PinId, PinId> = HashMap::new();
 
         for &old_id in &self.input {
-            let new_id = new_chip.add_shell_pin(PinType::ChipInput);
-            id_map.insert(old_id, new_id);
+            let pin = Pin::new(PinType::ChipInput, new_chip_id.into(), 0, ids);
+            id_map.insert(old_id, new_chip.insert_shell_pin(pin));
         }
         for &old_id in &self.output {
-            let new_id = new_chip.add_shell_pin(PinType::ChipOutput);
-            id_map.insert(old_id, new_id);
+            let pin = Pin::new(PinType::ChipOutput, new_chip_id.into(), 0, ids);
+            id_map.insert(old_id, new_chip.insert_shell_pin(pin));
         }
 
-        for gate in self.gates.values() {
-            let new_gate = gate.clone_with_new_ids(&mut id_map);
+        let mut gate_ids: Vec<&GateId> = self.gates.keys().collect();
+        gate_ids.sort();
+        for gid in gate_ids {
+            let new_gate = self.gates[gid].clone_with_new_ids(&mut id_map, ids)?;
             new_chip.add_gate(new_gate);
         }
 
-        for (src, dests) in &self.connections {
+        let mut sources: Vec<&PinId> = self.connections.keys().collect();
+        sources.sort();
+        for src in sources {
+            let dests = &self.connections[src];
             if let Some(&new_src) = id_map.get(src) {
                 for dst in dests {
                     if let Some(&new_dest) = id_map.get(dst) {
-                        new_chip.connect_pins(new_src, new_dest);
+                        new_chip.connect_pins(new_src, new_dest)?;
                     }
                 }
             }
         }
-        new_chip
+        // The copy's internals came from the parent's allocator; sharing it
+        // keeps later additions on either side from reusing an ID.
+        new_chip.ids = ids.share();
+        Ok(new_chip)
+    }
+}
+
+/// The output pins of `gate` with their values.
+fn output_values(gate: &Gate) -> Result<Vec<(PinId, Option<PinValue>)>, LgsimError> {
+    gate.output()
+        .iter()
+        .map(|p| {
+            let pin = gate.pins().get(p).ok_or(LgsimError::UnknownPin(*p))?;
+            Ok((*p, pin.val))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+    use crate::runner::load_chip;
+
+    fn half_adder() -> Chip {
+        crate::circuit! {
+            inputs: [a, b];
+            let s = xor(a, b);
+            let c = and(a, b);
+            outputs: [s, c];
+        }
+        .unwrap()
+    }
+
+    /// Two half adders chained through an instance each.
+    fn chained() -> Chip {
+        let template = half_adder();
+        let mut b = ChipBuilder::new();
+        let (x, y) = (b.input(), b.input());
+        let first = b.instance(&template, &[x, y]);
+        let second = b.instance(&template, &[first[0], first[1]]);
+        b.output(second[0]);
+        b.output(second[1]);
+        b.build().unwrap()
+    }
+
+    /// Every ID in `chip`, each recorded once by the level that owns it.
+    fn collect_ids(chip: &Chip, ids: &mut Vec<usize>) {
+        ids.extend(chip.pins.keys().map(|p| p.0));
+        for (id, gate) in &chip.gates {
+            ids.push(id.0);
+            match gate {
+                Gate::Chip(inner) => collect_ids(inner, ids),
+                _ => ids.extend(gate.input().iter().chain(gate.output()).map(|p| p.0)),
+            }
+        }
+    }
+
+    #[test]
+    fn same_construction_gives_same_ids() {
+        assert_eq!(chained(), chained());
+        assert_eq!(half_adder(), half_adder());
+    }
+
+    /// Allocates in `chip`, in one of its instances and in `chip` again, and
+    /// checks that no ID was handed out twice.
+    fn allocate_around_instance(chip: &mut Chip) {
+        let instance = *chip
+            .gates
+            .iter()
+            .find(|(_, g)| matches!(g, Gate::Chip(_)))
+            .unwrap()
+            .0;
+        let outer = chip.add_shell_pin(PinType::ChipInput);
+        let Some(Gate::Chip(inner)) = chip.gates.get_mut(&instance) else {
+            unreachable!();
+        };
+        let nested = inner.add_shell_pin(PinType::ChipInput);
+        let later = chip.add_shell_pin(PinType::ChipOutput);
+        assert_ne!(outer, nested);
+        assert_ne!(nested, later);
+
+        let mut ids = Vec::new();
+        collect_ids(chip, &mut ids);
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+
+    #[test]
+    fn instance_allocates_from_parent() {
+        allocate_around_instance(&mut chained());
+    }
+
+    #[test]
+    fn clones_allocate_on_their_own() {
+        let original = chained();
+        let mut copy = original.clone();
+        assert_eq!(copy, original);
+        allocate_around_instance(&mut copy);
+        // Nothing allocated in the copy reached the original's counter.
+        assert_eq!(original, chained());
+
+        fn send<T: Send>() {}
+        send::<Chip>();
+    }
+
+    #[test]
+    fn simulate_reports_settling() {
+        let mut chip = chained();
+        assert_eq!(chip.simulate(), Ok(true));
+
+        // A NOT gate feeding itself never settles, on its own or nested.
+        let mut b = ChipBuilder::new();
+        let ring = b.gate(crate::gate::GateType::Not);
+        b.connect(ring.output(0), ring.input(0));
+        b.output(ring.output(0));
+        let mut ring = b.build().unwrap();
+        assert_eq!(ring.simulate(), Ok(false));
+
+        let mut b = ChipBuilder::new();
+        let out = b.instance(&ring, &[]);
+        b.output(out[0]);
+        let mut outer = b.build().unwrap();
+        assert_eq!(outer.simulate(), Ok(false));
+    }
+
+    #[test]
+    fn connections_are_validated() {
+        let mut chip = Chip::new(ChipId(0));
+        let input = chip.add_shell_pin(PinType::ChipInput);
+        let other = chip.add_shell_pin(PinType::ChipInput);
+        let output = chip.add_shell_pin(PinType::ChipOutput);
+        let not = Gate::new(crate::gate::GateType::Not, vec![], &mut chip.ids);
+        let (a, y) = (not.input()[0], not.output()[0]);
+        chip.add_gate(not);
+
+        assert_eq!(chip.connect_pins(a, a), Err(LgsimError::SelfConnection(a)));
+        // Wires run from shell inputs and gate outputs to gate inputs and
+        // shell outputs.
+        assert_eq!(
+            chip.connect_pins(a, y),
+            Err(LgsimError::WrongDirection { from: a, to: y })
+        );
+        assert_eq!(
+            chip.connect_pins(output, a),
+            Err(LgsimError::WrongDirection {
+                from: output,
+                to: a
+            })
+        );
+        assert_eq!(
+            chip.connect_pins(input, PinId(999)),
+            Err(LgsimError::UnknownPin(PinId(999)))
+        );
+
+        chip.connect_pins(input, a).unwrap();
+        chip.connect_pins(y, output).unwrap();
+        assert_eq!(
+            chip.connect_pins(input, a),
+            Err(LgsimError::AlreadyConnected { from: input, to: a })
+        );
+        assert_eq!(
+            chip.connect_pins(other, a),
+            Err(LgsimError::DuplicateDriver {
+                pin: a,
+                existing: input,
+            })
+        );
+        assert_eq!(chip.wires(), [(input, a), (y, output)]);
+
+        chip.replace_driver(other, a).unwrap();
+        assert_eq!(chip.driver_of(&a), Some(other));
+        assert!(
+            chip.connections
+                .get(&input)
+                .is_none_or(|dests| dests.is_empty())
+        );
+        assert_eq!(
+            chip.replace_driver(a, y),
+            Err(LgsimError::WrongDirection { from: a, to: y })
+        );
+    }
+
+    #[test]
+    fn loading_allocates_fresh_ids() {
+        let library = |labels: [usize; 8]| {
+            let [a, b, y, and_a, and_b, and_y, not_a, not_y] = labels;
+            format!(
+                "lgsim-library 1\nlibrary \"t\" 1\n\ntemplate \"nand\" \"\"\n\
+                 chip \"nand\" in {a} {b} out {y}\n  and in {and_a} {and_b} out {and_y}\n  \
+                 not in {not_a} out {not_y}\n  wire {a} {and_a}\n  wire {b} {and_b}\n  \
+                 wire {and_y} {not_a}\n  wire {not_y} {y}\nend\n"
+            )
+        };
+        let small = load_chip(&library([1, 2, 3, 4, 5, 6, 7, 8]), "nand").unwrap();
+        let large = load_chip(&library([901, 902, 903, 904, 905, 906, 907, 908]), "nand").unwrap();
+        assert_eq!(small, large);
+        let mut ids = Vec::new();
+        collect_ids(&small, &mut ids);
+        assert!(ids.iter().all(|&id| id < 20));
+    }
+
+    #[test]
+    fn gate_at_reaches_nested_memories() {
+        // A template holding a 16x8 ROM, addressed through its inputs.
+        let mut b = ChipBuilder::new();
+        let rom = b.gate(crate::gate::GateType::Rom);
+        for k in 0..4 {
+            let address = b.input();
+            b.connect(address, rom.input(k));
+        }
+        for k in 0..8 {
+            b.output(rom.output(k));
+        }
+        let template = b.build().unwrap();
+        let mut b = ChipBuilder::new();
+        let address: Vec<_> = (0..4).map(|_| b.input()).collect();
+        for out in b.instance(&template, &address) {
+            b.output(out);
+        }
+        let mut board = b.build().unwrap();
+
+        let instance = *board.gates.keys().next().unwrap();
+        let Some(Gate::Chip(inner)) = board.gate_at(&[instance]) else {
+            unreachable!();
+        };
+        let path = [instance, *inner.gates.keys().next().unwrap()];
+        let Some(Gate::Memory(m)) = board.gate_at_mut(&path) else {
+            panic!("no memory at {:?}", path);
+        };
+        m.write(5, 0xc3);
+        for (k, pin) in board.input.clone().iter().enumerate() {
+            board.set_pin(pin, Some((5 >> k & 1) as PinValue));
+        }
+        board.simulate().unwrap();
+        assert_eq!(crate::pin::read_bus(&board.pins, &board.output), 0xc3);
+
+        assert!(board.gate_at(&[]).is_none());
+        assert!(board.gate_at(&[path[1]]).is_none());
+        assert!(board.gate_at(&[path[1], instance]).is_none());
     }
 }
@@ -0,0 +1,667 @@
+//! A small reference CPU for lab projects, its assembler and disassembler.
+//!
+//! The CPU is an 8-bit accumulator machine with separate program and data
+//! memories. Each instruction is one 16-bit word, the opcode in the high
+//! byte and an 8-bit operand in the low byte, and executes in a single clock
+//! cycle. Registers are the accumulator `A`, the program counter `PC` and the
+//! zero and carry flags `Z` and `C`.
+//!
+//! | Opcode | Assembly | Effect |
+//! |--------|----------|--------|
+//! | 00 | `NOP` | nothing |
+//! | 01 | `LDI n` | `A = n` |
+//! | 02 | `LDA m` | `A = mem[m]` |
+//! | 03 | `STA m` | `mem[m] = A` |
+//! | 04 | `ADD m` | `A = A + mem[m]`, `C` = carry |
+//! | 05 | `SUB m` | `A = A - mem[m]`, `C` = borrow |
+//! | 06 | `AND m` | `A = A & mem[m]` |
+//! | 07 | `OR m` | `A = A \| mem[m]` |
+//! | 08 | `XOR m` | `A = A ^ mem[m]` |
+//! | 09 | `ADDI n` | `A = A + n`, `C` = carry |
+//! | 0A | `SUBI n` | `A = A - n`, `C` = borrow |
+//! | 0B | `JMP a` | `PC = a` |
+//! | 0C | `JZ a` | `PC = a` if `Z` |
+//! | 0D | `JNZ a` | `PC = a` unless `Z` |
+//! | 0E | `JC a` | `PC = a` if `C` |
+//! | 0F | `IN` | if `ready`, `A` = input port and `Z = 0`; otherwise `Z = 1` |
+//! | 10 | `OUT` | pulses `strobe` with `A` on the output port |
+//! | 11 | `SHL` | `A = A << 1`, `C` = the bit shifted out |
+//! | 12 | `SHR` | `A = A >> 1`, `C` = the bit shifted out |
+//! | 13 | `HLT` | stops until reset |
+//!
+//! Every instruction that writes `A`, except `IN`, sets `Z` when the result
+//! is zero; `IN` uses `Z` to tell whether a byte was read at all.
+//! Unknown opcodes act as `NOP`.
+//!
+//! Assembly has one instruction per line. `label:` names the next
+//! instruction, `name = value` defines a constant, `;` starts a comment and
+//! `.word n` places a raw 16-bit word. Numbers are decimal, `0x` hex, `0b`
+//! binary or a character in single quotes.
+
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::Gate;
+use crate::memory::{MemoryGate, MemoryKind};
+use crate::pin::*;
+use crate::types::*;
+use std::collections::HashMap;
+
+/// Program memory size in words and data memory size in bytes.
+pub const MEMORY_WORDS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub opcode: u8,
+    pub has_operand: bool,
+}
+
+const fn op(mnemonic: &'static str, opcode: u8, has_operand: bool) -> Instruction {
+    Instruction {
+        mnemonic,
+        opcode,
+        has_operand,
+    }
+}
+
+pub const INSTRUCTIONS: [Instruction; 20] = [
+    op("NOP", 0x00, false),
+    op("LDI", 0x01, true),
+    op("LDA", 0x02, true),
+    op("STA", 0x03, true),
+    op("ADD", 0x04, true),
+    op("SUB", 0x05, true),
+    op("AND", 0x06, true),
+    op("OR", 0x07, true),
+    op("XOR", 0x08, true),
+    op("ADDI", 0x09, true),
+    op("SUBI", 0x0A, true),
+    op("JMP", 0x0B, true),
+    op("JZ", 0x0C, true),
+    op("JNZ", 0x0D, true),
+    op("JC", 0x0E, true),
+    op("IN", 0x0F, false),
+    op("OUT", 0x10, false),
+    op("SHL", 0x11, false),
+    op("SHR", 0x12, false),
+    op("HLT", 0x13, false),
+];
+
+const STA: u8 = 0x03;
+const IN: u8 = 0x0F;
+const OUT: u8 = 0x10;
+
+fn instruction(opcode: u8) -> Option<&'static Instruction> {
+    INSTRUCTIONS.iter().find(|i| i.opcode == opcode)
+}
+
+/// Assembly text of one program word.
+pub fn disassemble(word: u64) -> String {
+    let (opcode, operand) = ((word >> 8) as u8, word as u8);
+    match instruction(opcode) {
+        Some(i) if word >> 16 == 0 && i.has_operand => {
+            format!("{} 0x{:02X}", i.mnemonic, operand)
+        }
+        Some(i) if word >> 16 == 0 && operand == 0 => i.mnemonic.to_string(),
+        _ => format!(".word 0x{:04X}", word),
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()
+    } else if let Some(c) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        let mut chars = c.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii() => Some(c as u64),
+            _ => None,
+        }
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Strips a `;` comment, leaving a `';'` character literal alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Assembles `source` into program words. Errors name the 1-based line.
+pub fn assemble(source: &str) -> Result<Vec<u64>, LgsimError> {
+    let err = |line: usize, message: String| LgsimError::Parse { line, message };
+
+    // First pass: addresses of labels and values of constants.
+    let mut symbols: HashMap<String, u64> = HashMap::new();
+    let mut statements: Vec<(usize, &str, Option<&str>)> = Vec::new();
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = strip_comment(raw).trim();
+        // A quoted ':' or '=' operand is not a label or constant.
+        if let Some((name, value)) = text.split_once('=')
+            && is_name(name.trim())
+        {
+            let name = name.trim();
+            let value = value.trim();
+            let value = parse_number(value)
+                .or_else(|| symbols.get(value).copied())
+                .ok_or_else(|| err(line, format!("\"{}\" is not a number", value)))?;
+            if symbols.insert(name.to_string(), value).is_some() {
+                return Err(err(line, format!("\"{}\" is defined twice", name)));
+            }
+            continue;
+        }
+        if let Some((label, rest)) = text.split_once(':')
+            && is_name(label.trim())
+        {
+            let label = label.trim();
+            let address = statements.len() as u64;
+            if symbols.insert(label.to_string(), address).is_some() {
+                return Err(err(line, format!("\"{}\" is defined twice", label)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, operand) = match text.split_once(char::is_whitespace) {
+            Some((m, o)) => (m, Some(o.trim())),
+            None => (text, None),
+        };
+        statements.push((line, mnemonic, operand));
+    }
+    if statements.len() > MEMORY_WORDS {
+        let line = statements[MEMORY_WORDS].0;
+        return Err(err(
+            line,
+            format!("the program is longer than {} words", MEMORY_WORDS),
+        ));
+    }
+
+    // Second pass: encode.
+    let value = |line: usize, text: &str, max: u64| {
+        let v = parse_number(text)
+            .or_else(|| symbols.get(text).copied())
+            .ok_or_else(|| err(line, format!("\"{}\" is not a number or label", text)))?;
+        if v > max {
+            return Err(err(
+                line,
+                format!("{} does not fit in {} bits", text, max.count_ones()),
+            ));
+        }
+        Ok(v)
+    };
+    let mut words = Vec::with_capacity(statements.len());
+    for (line, mnemonic, operand) in statements {
+        if mnemonic.eq_ignore_ascii_case(".word") {
+            let text = operand.ok_or_else(|| err(line, ".word needs a value".to_string()))?;
+            words.push(value(line, text, 0xFFFF)?);
+            continue;
+        }
+        let i = INSTRUCTIONS
+            .iter()
+            .find(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .ok_or_else(|| err(line, format!("unknown instruction \"{}\"", mnemonic)))?;
+        let operand = match (i.has_operand, operand) {
+            (true, Some(text)) => value(line, text, 0xFF)?,
+            (true, None) => return Err(err(line, format!("{} needs an operand", i.mnemonic))),
+            (false, Some(_)) => {
+                return Err(err(line, format!("{} takes no operand", i.mnemonic)));
+            }
+            (false, None) => 0,
+        };
+        words.push((i.opcode as u64) << 8 | operand);
+    }
+    Ok(words)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuGate {
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    /// Inputs `i0..i15` (the instruction at `PC`), `m0..m7` (data memory at
+    /// the operand address), `p0..p7` (input port), `ready`, `reset`, `clk`.
+    pub input: Vec<PinId>,
+    /// Outputs `pc0..pc7`, `addr0..addr7` (the operand), `a0..a7` (the
+    /// accumulator, for both data memory and the output port), `we` (high
+    /// during `STA`), `strobe` (high during `OUT`), `ack` (high during `IN`)
+    /// and `halt`.
+    pub output: Vec<PinId>,
+    pub pc: u8,
+    pub a: u8,
+    pub zero: bool,
+    pub carry: bool,
+    pub halted: bool,
+    last_clock: PinValue,
+}
+
+impl CpuGate {
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let (pins, input, output) = gate_pins(id, 35, 28, ids);
+        Self {
+            id,
+            pins,
+            input,
+            output,
+            pc: 0,
+            a: 0,
+            zero: false,
+            carry: false,
+            halted: false,
+            last_clock: 0,
+        }
+    }
+
+    fn bus(&self, from: usize, len: usize) -> u64 {
+        read_bus(&self.pins, &self.input[from..from + len])
+    }
+
+    /// The instruction word on `i0..i15`.
+    pub fn instruction(&self) -> u64 {
+        self.bus(0, 16)
+    }
+
+    fn set_a(&mut self, value: u8) {
+        self.a = value;
+        self.zero = value == 0;
+    }
+
+    fn execute(&mut self) {
+        let word = self.instruction();
+        let (opcode, operand) = ((word >> 8) as u8, word as u8);
+        let memory = self.bus(16, 8) as u8;
+        let mut next = self.pc.wrapping_add(1);
+        match opcode {
+            0x01 => self.set_a(operand),
+            0x02 => self.set_a(memory),
+            0x04 | 0x09 => {
+                let rhs = if opcode == 0x04 { memory } else { operand };
+                let (sum, carry) = self.a.overflowing_add(rhs);
+                self.carry = carry;
+                self.set_a(sum);
+            }
+            0x05 | 0x0A => {
+                let rhs = if opcode == 0x05 { memory } else { operand };
+                let (diff, borrow) = self.a.overflowing_sub(rhs);
+                self.carry = borrow;
+                self.set_a(diff);
+            }
+            0x06 => self.set_a(self.a & memory),
+            0x07 => self.set_a(self.a | memory),
+            0x08 => self.set_a(self.a ^ memory),
+            0x0B => next = operand,
+            0x0C if self.zero => next = operand,
+            0x0D if !self.zero => next = operand,
+            0x0E if self.carry => next = operand,
+            IN => {
+                if self.bus(32, 1) == 1 {
+                    self.a = self.bus(24, 8) as u8;
+                    self.zero = false;
+                } else {
+                    self.zero = true;
+                }
+            }
+            0x11 => {
+                self.carry = self.a & 0x80 != 0;
+                self.set_a(self.a << 1);
+            }
+            0x12 => {
+                self.carry = self.a & 1 != 0;
+                self.set_a(self.a >> 1);
+            }
+            0x13 => {
+                self.halted = true;
+                next = self.pc;
+            }
+            _ => {}
+        }
+        self.pc = next;
+    }
+
+    /// Executes the current instruction on a rising clock edge, or resets
+    /// while `reset` is high. See `MemoryGate::clock`.
+    pub fn clock(&mut self) {
+        let clock = self.bus(34, 1) as PinValue;
+        if self.bus(33, 1) == 1 {
+            (self.pc, self.a, self.zero, self.carry, self.halted) = (0, 0, false, false, false);
+        } else if clock == 1 && self.last_clock == 0 && !self.halted {
+            self.execute();
+        }
+        self.last_clock = clock;
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        self.clock();
+        let word = self.instruction();
+        let (opcode, operand) = ((word >> 8) as u8, word & 0xFF);
+        let running = !self.halted;
+        write_bus(&mut self.pins, &self.output[..8], self.pc as u64);
+        write_bus(&mut self.pins, &self.output[8..16], operand);
+        write_bus(&mut self.pins, &self.output[16..24], self.a as u64);
+        let control = [
+            running && opcode == STA,
+            running && opcode == OUT,
+            running && opcode == IN,
+            self.halted,
+        ];
+        for (k, on) in control.into_iter().enumerate() {
+            write_bus(&mut self.pins, &self.output[24 + k..25 + k], on as u64);
+        }
+        running
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+/// The reference computer: a `CpuGate` wired to a 256x16 program ROM and a
+/// 256x8 data RAM. Inputs `clk, reset, p0..p7, ready`; outputs `o0..o7`
+/// (the accumulator), `strobe`, `ack`, `halt`. Load a program into the ROM
+/// with the assembler; a keyboard and terminal wire straight to the ports.
+pub fn reference_computer() -> Result<Chip, LgsimError> {
+    let mut chip = Chip::new(ChipId(0));
+    let shell_in: Vec<PinId> = (0..11)
+        .map(|_| chip.add_shell_pin(PinType::ChipInput))
+        .collect();
+    let shell_out: Vec<PinId> = (0..11)
+        .map(|_| chip.add_shell_pin(PinType::ChipOutput))
+        .collect();
+
+    let id = chip.ids.gate();
+    let cpu = CpuGate::new(id, &mut chip.ids);
+    let id = chip.ids.gate();
+    let rom = MemoryGate::new(MemoryKind::Rom, 8, 16, id, &mut chip.ids);
+    let id = chip.ids.gate();
+    let ram = MemoryGate::new(MemoryKind::Ram, 8, 8, id, &mut chip.ids);
+    let (ci, co) = (cpu.input.clone(), cpu.output.clone());
+    let (rom_in, rom_out) = (rom.input.clone(), rom.output.clone());
+    let (ram_in, ram_out) = (ram.input.clone(), ram.output.clone());
+    chip.add_gate(Gate::Cpu(cpu));
+    chip.add_gate(Gate::Memory(rom));
+    chip.add_gate(Gate::Memory(ram));
+
+    let (clk, reset, port, ready) = (shell_in[0], shell_in[1], &shell_in[2..10], shell_in[10]);
+    let mut wires: Vec<(PinId, PinId)> = Vec::new();
+    wires.extend(co[..8].iter().copied().zip(rom_in.iter().copied()));
+    wires.extend(rom_out.iter().copied().zip(ci[..16].iter().copied()));
+    wires.extend(co[8..16].iter().copied().zip(ram_in[..8].iter().copied()));
+    wires.extend(
+        co[16..24]
+            .iter()
+            .copied()
+            .zip(ram_in[8..16].iter().copied()),
+    );
+    wires.push((co[24], ram_in[16]));
+    wires.push((clk, ram_in[17]));
+    wires.extend(ram_out.iter().copied().zip(ci[16..24].iter().copied()));
+    wires.extend(port.iter().copied().zip(ci[24..32].iter().copied()));
+    wires.extend([(ready, ci[32]), (reset, ci[33]), (clk, ci[34])]);
+    wires.extend(
+        co[16..24]
+            .iter()
+            .copied()
+            .zip(shell_out[..8].iter().copied()),
+    );
+    wires.extend(
+        co[25..28]
+            .iter()
+            .copied()
+            .zip(shell_out[8..].iter().copied()),
+    );
+    for (from, to) in wires {
+        chip.connect_pins(from, to)?;
+    }
+    Ok(chip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::Runner;
+
+    fn cpu() -> CpuGate {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        CpuGate::new(id, &mut ids)
+    }
+
+    fn set_bus(cpu: &mut CpuGate, from: usize, len: usize, value: u64) {
+        let pins = cpu.input[from..from + len].to_vec();
+        write_bus(&mut cpu.pins, &pins, value);
+    }
+
+    /// Executes `source`'s single instruction with `memory` on `m0..m7`.
+    fn step(cpu: &mut CpuGate, source: &str, memory: u8) {
+        let word = assemble(source).unwrap()[0];
+        set_bus(cpu, 0, 16, word);
+        set_bus(cpu, 16, 8, memory as u64);
+        set_bus(cpu, 34, 1, 0);
+        cpu.evaluate();
+        set_bus(cpu, 34, 1, 1);
+        cpu.evaluate();
+    }
+
+    fn line_of(source: &str) -> usize {
+        match assemble(source) {
+            Err(LgsimError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn assembles_labels_constants_and_literals() {
+        let source = "\
+; echo until NUL
+port = 0x20
+start:  IN          ; poll
+        JZ start
+        SUBI ';'
+        ADDI 0b1
+loop: JMP loop
+        STA port
+        .word 0xBEEF
+        hlt
+";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0x0F00, 0x0C00, 0x0A3B, 0x0901, 0x0B04, 0x0320, 0xBEEF, 0x1300
+            ]
+        );
+        // A label may be used before it is defined.
+        assert_eq!(assemble("JMP end\nend: HLT").unwrap(), [0x0B01, 0x1300]);
+        assert_eq!(assemble("").unwrap(), []);
+    }
+
+    #[test]
+    fn assembler_errors_name_the_line() {
+        assert_eq!(line_of("NOP\nFOO 1\n"), 2);
+        assert_eq!(line_of("LDI\n"), 1);
+        assert_eq!(line_of("NOP\nHLT 3\n"), 2);
+        assert_eq!(line_of("LDI 256\n"), 1);
+        assert_eq!(line_of("\n\n.word 0x10000\n"), 3);
+        assert_eq!(line_of("JMP nowhere\n"), 1);
+        assert_eq!(line_of("a: NOP\na: NOP\n"), 2);
+        assert_eq!(line_of("x = 1\nx = 2\n"), 2);
+        assert_eq!(line_of("x = 'ab'\n"), 1);
+        assert_eq!(line_of(".word\n"), 1);
+
+        let long = "NOP\n".repeat(MEMORY_WORDS + 1);
+        assert_eq!(line_of(&long), MEMORY_WORDS + 1);
+        assert!(assemble(&"NOP\n".repeat(MEMORY_WORDS)).is_ok());
+    }
+
+    #[test]
+    fn disassembly_round_trips() {
+        for i in INSTRUCTIONS {
+            let word = (i.opcode as u64) << 8 | if i.has_operand { 0x5A } else { 0 };
+            let text = disassemble(word);
+            assert_eq!(assemble(&text).unwrap(), [word], "{}", text);
+        }
+        assert_eq!(disassemble(0x0142), "LDI 0x42");
+        assert_eq!(disassemble(0x1300), "HLT");
+        // Operands on instructions without one and unknown opcodes stay
+        // raw words.
+        assert_eq!(disassemble(0x1301), ".word 0x1301");
+        assert_eq!(disassemble(0xFF00), ".word 0xFF00");
+        assert_eq!(assemble(&disassemble(0xFF00)).unwrap(), [0xFF00]);
+    }
+
+    #[test]
+    fn arithmetic_sets_flags() {
+        let mut c = cpu();
+        step(&mut c, "LDI 200", 0);
+        assert_eq!((c.a, c.zero, c.pc), (200, false, 1));
+        step(&mut c, "ADD 0", 56);
+        assert_eq!((c.a, c.zero, c.carry), (0, true, true));
+        step(&mut c, "SUBI 1", 0);
+        assert_eq!((c.a, c.zero, c.carry), (255, false, true));
+        step(&mut c, "ADDI 1", 0);
+        assert_eq!((c.a, c.zero, c.carry), (0, true, true));
+        step(&mut c, "LDA 9", 0b1100);
+        step(&mut c, "SUB 9", 0b0100);
+        assert_eq!((c.a, c.carry), (0b1000, false));
+        step(&mut c, "OR 0", 0b0011);
+        step(&mut c, "AND 0", 0b1010);
+        step(&mut c, "XOR 0", 0b1111);
+        assert_eq!(c.a, 0b0101);
+        step(&mut c, "SHL", 0);
+        assert_eq!((c.a, c.carry), (0b1010, false));
+        step(&mut c, "SHR", 0);
+        step(&mut c, "SHR", 0);
+        assert_eq!((c.a, c.carry), (0b0010, true));
+        assert_eq!(c.pc, 12);
+
+        // Holding the clock high executes nothing more.
+        c.evaluate();
+        assert_eq!(c.pc, 12);
+    }
+
+    #[test]
+    fn jumps_follow_the_flags() {
+        let mut c = cpu();
+        step(&mut c, "JMP 0x40", 0);
+        assert_eq!(c.pc, 0x40);
+        step(&mut c, "LDI 0", 0);
+        step(&mut c, "JNZ 0x10", 0);
+        assert_eq!(c.pc, 0x42);
+        step(&mut c, "JZ 0x10", 0);
+        assert_eq!(c.pc, 0x10);
+        step(&mut c, "JC 0x20", 0);
+        assert_eq!(c.pc, 0x11);
+        step(&mut c, "SUBI 1", 0);
+        step(&mut c, "JC 0x20", 0);
+        assert_eq!(c.pc, 0x20);
+        step(&mut c, "NOP", 0);
+        step(&mut c, ".word 0xFF00", 0);
+        assert_eq!((c.pc, c.a), (0x22, 255));
+    }
+
+    #[test]
+    fn ports_and_halt() {
+        let mut c = cpu();
+        let output = |c: &CpuGate, k: usize| read_bus(&c.pins, &c.output[k..k + 1]);
+
+        // No byte waiting: Z is set and A is kept.
+        step(&mut c, "LDI 7", 0);
+        step(&mut c, "IN", 0);
+        assert_eq!((c.a, c.zero), (7, true));
+        assert_eq!(output(&c, 26), 1);
+
+        // A NUL byte still clears Z.
+        set_bus(&mut c, 32, 1, 1);
+        step(&mut c, "IN", 0);
+        assert_eq!((c.a, c.zero), (0, false));
+        set_bus(&mut c, 24, 8, b'x' as u64);
+        step(&mut c, "IN", 0);
+        assert_eq!((c.a, c.zero), (b'x', false));
+
+        step(&mut c, "OUT", 0);
+        assert_eq!((output(&c, 25), output(&c, 26)), (1, 0));
+        assert_eq!(read_bus(&c.pins, &c.output[16..24]), b'x' as u64);
+        step(&mut c, "STA 0x33", 0);
+        assert_eq!(output(&c, 24), 1);
+        assert_eq!(read_bus(&c.pins, &c.output[8..16]), 0x33);
+
+        step(&mut c, "HLT", 0);
+        step(&mut c, "LDI 1", 0);
+        assert!(c.halted);
+        assert_eq!((c.pc, c.a), (6, b'x'));
+        assert_eq!(output(&c, 27), 1);
+        assert!(!c.evaluate());
+
+        set_bus(&mut c, 33, 1, 1);
+        c.evaluate();
+        assert_eq!((c.pc, c.a, c.zero, c.halted), (0, 0, false, false));
+        assert_eq!(output(&c, 27), 0);
+    }
+
+    #[test]
+    fn copies_keep_the_registers() {
+        let mut c = cpu();
+        step(&mut c, "LDI 0x80", 0);
+        step(&mut c, "SHL", 0);
+        let mut ids = IdAllocator::default();
+        let copy = Gate::Cpu(c.clone())
+            .clone_with_new_ids(&mut HashMap::new(), &mut ids)
+            .unwrap();
+        let Gate::Cpu(copy) = copy else {
+            panic!("expected a CPU");
+        };
+        assert_eq!(
+            (copy.pc, copy.a, copy.zero, copy.carry, copy.halted),
+            (2, 0, true, true, false)
+        );
+    }
+
+    #[test]
+    fn reference_computer_runs_a_program() {
+        let mut chip = reference_computer().unwrap();
+        let program = assemble(
+            "\
+x = 0x10
+        LDI 3
+        STA x
+        LDI 4
+        ADD x
+        OUT
+        HLT
+",
+        )
+        .unwrap();
+        chip.for_each_gate_mut(&mut |gate| {
+            if let Gate::Memory(m) = gate
+                && m.kind == MemoryKind::Rom
+            {
+                m.load(&program).unwrap();
+            }
+        });
+        let mut runner = Runner::new(chip, Some(0)).unwrap();
+        for _ in 0..8 {
+            runner.cycle().unwrap();
+        }
+        // Outputs are the accumulator, strobe, ack and halt.
+        assert_eq!(runner.outputs(), 7 | 1 << 10);
+    }
+}
@@ -0,0 +1,289 @@
+//! Breakpoints on probe values and single-stepping of the board.
+//!
+//! A breakpoint fires when its probe changes to the given value, so "carry
+//! becomes 1" and "count equals 0xFF" are both `Breakpoint`s on a probe from
+//! the watch list. Steps advance one event (the next gate, in ID order, whose
+//! output changes), one delta cycle (every gate once) or one clock edge (the
+//! clock input toggled and the circuit settled), and remember which pins
+//! changed so the canvas can highlight their nets.
+
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::Gate;
+use crate::probe::WatchList;
+use crate::types::*;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+    Event,
+    Delta,
+    ClockEdge,
+}
+
+impl StepMode {
+    pub const ALL: [StepMode; 3] = [StepMode::Event, StepMode::Delta, StepMode::ClockEdge];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StepMode::Event => "Event",
+            StepMode::Delta => "Delta",
+            StepMode::ClockEdge => "Clock Edge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    /// Name of the watched probe.
+    pub probe: String,
+    pub value: u64,
+    pub enabled: bool,
+    /// Probe value at the last check.
+    last: Option<u64>,
+}
+
+impl Breakpoint {
+    pub fn new(probe: &str, value: u64) -> Self {
+        Self {
+            probe: probe.to_string(),
+            value,
+            enabled: true,
+            last: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub mode: StepMode,
+    /// Board source toggled by clock-edge steps and by running the clock.
+    pub clock: Option<GateId>,
+    /// Pins on the board whose value the last step changed.
+    pub changed: HashSet<PinId>,
+    /// Gate the last event step evaluated; the next one starts after it.
+    cursor: Option<GateId>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            mode: StepMode::Event,
+            clock: None,
+            changed: HashSet::new(),
+            cursor: None,
+        }
+    }
+}
+
+impl Debugger {
+    /// Advances `board` by one step of the current mode. Returns whether any
+    /// pin changed; an event step that changes nothing means the board has
+    /// settled.
+    pub fn step(&mut self, board: &mut Chip) -> Result<bool, LgsimError> {
+        let before = board.pin_values();
+        match self.mode {
+            StepMode::Event => self.event(board)?,
+            StepMode::Delta => {
+                board.clock()?;
+                board.delta()?;
+            }
+            StepMode::ClockEdge => self.clock_edge(board)?,
+        }
+        let after = board.pin_values();
+        self.changed = after
+            .iter()
+            .filter(|(id, val)| before.get(id) != Some(val))
+            .map(|(id, _)| *id)
+            .collect();
+        Ok(!self.changed.is_empty())
+    }
+
+    /// Evaluates gates from after the cursor, wrapping round once, up to and
+    /// including the first whose evaluation changes a pin.
+    fn event(&mut self, board: &mut Chip) -> Result<(), LgsimError> {
+        board.clock()?;
+        let mut ids: Vec<GateId> = board.gates.keys().copied().collect();
+        ids.sort();
+        let start = self
+            .cursor
+            .map_or(0, |c| ids.iter().position(|id| *id > c).unwrap_or(0));
+        for k in 0..ids.len() {
+            let gid = ids[(start + k) % ids.len()];
+            let before = board.pin_values();
+            board.evaluate_gate(gid)?;
+            if board.pin_values() != before {
+                self.cursor = Some(gid);
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Toggles the clock source and lets the board settle.
+    pub fn clock_edge(&mut self, board: &mut Chip) -> Result<(), LgsimError> {
+        let id = self.clock.ok_or(LgsimError::NoClock)?;
+        match board.gates.get_mut(&id) {
+            Some(Gate::Source(s)) => {
+                let pin = s.output[0];
+                let val = s.pins.get(&pin).and_then(|p| p.val).unwrap_or(0);
+                s.set_pin(&pin, Some(1 - val));
+            }
+            _ => return Err(LgsimError::NoClock),
+        }
+        board.simulate()?;
+        Ok(())
+    }
+
+    /// Checks every enabled breakpoint against the probes in `watch` and
+    /// returns a description of each one that fired.
+    pub fn check(&mut self, watch: &WatchList, board: &Chip) -> Vec<String> {
+        let mut fired = Vec::new();
+        for bp in &mut self.breakpoints {
+            let Some(probe) = watch.probes.iter().find(|p| p.name == bp.probe) else {
+                continue;
+            };
+            let value = probe.path.read(board);
+            if bp.enabled && value == Some(bp.value) && bp.last != value {
+                fired.push(format!(
+                    "{} = {}",
+                    bp.probe,
+                    probe.radix.format(value, probe.path.width())
+                ));
+            }
+            bp.last = value;
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::GateType;
+    use crate::pin::read_bus;
+    use crate::probe::ProbePath;
+
+    /// A board source driving two inverters in a row. Returns the board and
+    /// the source and inverter IDs, in ID order.
+    fn chain() -> (Chip, [GateId; 3]) {
+        let mut board = Chip::new(ChipId(0));
+        let ids = [GateType::Source, GateType::Not, GateType::Not].map(|kind| {
+            let gate = Gate::new(kind, vec![], &mut board.ids);
+            board.add_gate(gate)
+        });
+        for pair in ids.windows(2) {
+            let from = board.gates[&pair[0]].output()[0];
+            let to = board.gates[&pair[1]].input()[0];
+            board.connect_pins(from, to).unwrap();
+        }
+        (board, ids)
+    }
+
+    fn output(board: &Chip, id: GateId) -> u64 {
+        let gate = &board.gates[&id];
+        read_bus(gate.pins(), gate.output())
+    }
+
+    fn set_source(board: &mut Chip, id: GateId, val: PinValue) {
+        let pin = board.gates[&id].output()[0];
+        board.gates.get_mut(&id).unwrap().set_pin(&pin, Some(val));
+    }
+
+    #[test]
+    fn event_steps_one_gate_at_a_time() {
+        let (mut board, [source, first, second]) = chain();
+        board.simulate().unwrap();
+        set_source(&mut board, source, 1);
+
+        let mut debugger = Debugger::default();
+        // Sources settle before every step, so the first event is the first
+        // inverter: its input, its output and the wire on to the second.
+        assert!(debugger.step(&mut board).unwrap());
+        let (a, b) = (&board.gates[&first], &board.gates[&second]);
+        assert_eq!(
+            debugger.changed,
+            HashSet::from([a.input()[0], a.output()[0], b.input()[0]])
+        );
+        assert_eq!((output(&board, first), output(&board, second)), (0, 0));
+        assert!(debugger.step(&mut board).unwrap());
+        assert_eq!(
+            debugger.changed,
+            HashSet::from([board.gates[&second].output()[0]])
+        );
+        assert_eq!(output(&board, second), 1);
+        // Settled: nothing is left to change.
+        assert!(!debugger.step(&mut board).unwrap());
+        assert!(debugger.changed.is_empty());
+    }
+
+    #[test]
+    fn delta_steps_evaluate_every_gate() {
+        let (mut board, [source, _, second]) = chain();
+        board.simulate().unwrap();
+        set_source(&mut board, source, 1);
+
+        let mut debugger = Debugger {
+            mode: StepMode::Delta,
+            ..Debugger::default()
+        };
+        // Gates run in ID order, so one delta reaches the end of the chain.
+        assert!(debugger.step(&mut board).unwrap());
+        assert_eq!(output(&board, second), 1);
+        assert_eq!(debugger.changed.len(), 4);
+        assert!(!debugger.step(&mut board).unwrap());
+    }
+
+    #[test]
+    fn clock_edges_toggle_the_clock_source() {
+        let (mut board, [source, first, second]) = chain();
+        let mut debugger = Debugger {
+            mode: StepMode::ClockEdge,
+            ..Debugger::default()
+        };
+        assert_eq!(debugger.step(&mut board), Err(LgsimError::NoClock));
+        debugger.clock = Some(first);
+        assert_eq!(debugger.step(&mut board), Err(LgsimError::NoClock));
+
+        debugger.clock = Some(source);
+        assert!(debugger.step(&mut board).unwrap());
+        assert_eq!((output(&board, source), output(&board, second)), (1, 1));
+        assert!(debugger.changed.contains(&board.gates[&source].output()[0]));
+        debugger.step(&mut board).unwrap();
+        assert_eq!((output(&board, source), output(&board, second)), (0, 0));
+    }
+
+    #[test]
+    fn breakpoints_fire_when_the_value_is_reached() {
+        let (mut board, [source, _, second]) = chain();
+        board.simulate().unwrap();
+        let mut watch = WatchList::default();
+        let path: ProbePath = format!("{}/out0", second.0).parse().unwrap();
+        watch.add("q", path, &board).unwrap();
+
+        let mut debugger = Debugger {
+            clock: Some(source),
+            ..Debugger::default()
+        };
+        debugger.breakpoints.push(Breakpoint::new("q", 1));
+        debugger.breakpoints.push(Breakpoint::new("missing", 0));
+        assert!(debugger.check(&watch, &board).is_empty());
+
+        debugger.clock_edge(&mut board).unwrap();
+        assert_eq!(debugger.check(&watch, &board), ["q = 1"]);
+        // Staying at the value does not fire again.
+        assert!(debugger.check(&watch, &board).is_empty());
+
+        debugger.clock_edge(&mut board).unwrap();
+        assert!(debugger.check(&watch, &board).is_empty());
+        debugger.breakpoints[0].enabled = false;
+        debugger.clock_edge(&mut board).unwrap();
+        assert!(debugger.check(&watch, &board).is_empty());
+        // A disabled breakpoint still tracks the value, so enabling it does
+        // not fire for a change it already saw.
+        debugger.breakpoints[0].enabled = true;
+        assert!(debugger.check(&watch, &board).is_empty());
+    }
+}
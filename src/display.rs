@@ -0,0 +1,278 @@
+//! Output devices that show the values driving them: LEDs, segment displays,
+//! hex digits and a pixel matrix. They have inputs only, so they never drive
+//! anything; `gate_ui` draws them on the node body.
+
+use crate::error::LgsimError;
+use crate::pin::*;
+use crate::types::*;
+use std::collections::HashMap;
+
+/// Largest number of columns or rows of a pixel matrix.
+pub const MAX_MATRIX_SIZE: usize = 32;
+pub const DEFAULT_MATRIX_SIZE: usize = 8;
+pub const DEFAULT_LED_COLOR: [u8; 3] = [255, 40, 40];
+
+/// Segments `a..g` lit for each hex digit, bit 0 being `a`.
+pub const HEX_SEGMENTS: [u32; 16] = [
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayKind {
+    /// Input `on`.
+    Led,
+    /// Inputs `a, b, c, d, e, f, g, dp`, one per segment.
+    SevenSegment,
+    /// Inputs `a1, a2, b, c, d1, d2, e, f, g1, g2, h, i, j, k, l, m, dp`.
+    /// `a`, `d` and `g` are split into left and right halves; `h, i, j` are
+    /// the upper diagonal, vertical and diagonal strokes from left to right,
+    /// `k, l, m` the lower ones.
+    SixteenSegment,
+    /// Inputs `d0..d3`, shown as a seven-segment hex digit.
+    HexDigit,
+    /// Inputs `a0..a(k-1), d0..d(w-1), we, clk`. A clock edge with `we` high
+    /// stores `d` as row `a`, `d0` being the leftmost pixel.
+    PixelMatrix,
+}
+
+impl DisplayKind {
+    pub const ALL: [DisplayKind; 5] = [
+        DisplayKind::Led,
+        DisplayKind::SevenSegment,
+        DisplayKind::SixteenSegment,
+        DisplayKind::HexDigit,
+        DisplayKind::PixelMatrix,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DisplayKind::Led => "LED",
+            DisplayKind::SevenSegment => "7-Segment Display",
+            DisplayKind::SixteenSegment => "16-Segment Display",
+            DisplayKind::HexDigit => "Hex Digit",
+            DisplayKind::PixelMatrix => "Pixel Matrix",
+        }
+    }
+
+    /// Keyword used in the chip text format.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            DisplayKind::Led => "led",
+            DisplayKind::SevenSegment => "seg7",
+            DisplayKind::SixteenSegment => "seg16",
+            DisplayKind::HexDigit => "hexdigit",
+            DisplayKind::PixelMatrix => "matrix",
+        }
+    }
+
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.keyword() == keyword)
+    }
+}
+
+pub fn check_matrix_size(columns: usize, rows: usize) -> Result<(), LgsimError> {
+    for size in [columns, rows] {
+        if !(1..=MAX_MATRIX_SIZE).contains(&size) {
+            return Err(LgsimError::InvalidWidth {
+                width: size,
+                max: MAX_MATRIX_SIZE,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Address bits needed to select one of `rows` rows.
+fn row_address_bits(rows: usize) -> usize {
+    (usize::BITS - (rows - 1).leading_zeros()).max(1) as usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayGate {
+    pub id: GateId,
+    pub kind: DisplayKind,
+    /// Color of a lit LED.
+    pub color: [u8; 3],
+    /// Size of a pixel matrix; 1x1 for the other kinds.
+    pub columns: usize,
+    pub rows: usize,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
+    /// Pixel matrix rows, bit `c` being column `c`.
+    pub pixels: Vec<u64>,
+    last_clock: PinValue,
+}
+
+impl DisplayGate {
+    /// Creates a dark display. Matrix sizes outside the supported range are
+    /// clamped; use `check_matrix_size` to report them instead.
+    pub fn new(
+        kind: DisplayKind,
+        columns: usize,
+        rows: usize,
+        id: GateId,
+        ids: &mut IdAllocator,
+    ) -> Self {
+        let (columns, rows) = match kind {
+            DisplayKind::PixelMatrix => (
+                columns.clamp(1, MAX_MATRIX_SIZE),
+                rows.clamp(1, MAX_MATRIX_SIZE),
+            ),
+            _ => (1, 1),
+        };
+        let inputs = match kind {
+            DisplayKind::Led => 1,
+            DisplayKind::SevenSegment => 8,
+            DisplayKind::SixteenSegment => 17,
+            DisplayKind::HexDigit => 4,
+            DisplayKind::PixelMatrix => row_address_bits(rows) + columns + 2,
+        };
+        let (pins, input, _) = gate_pins(id, inputs, 0, ids);
+        Self {
+            id,
+            kind,
+            color: DEFAULT_LED_COLOR,
+            columns,
+            rows,
+            pins,
+            input,
+            pixels: vec![0; rows],
+            last_clock: 0,
+        }
+    }
+
+    /// The input values as a number, first input in bit 0.
+    pub fn inputs(&self) -> u64 {
+        read_bus(&self.pins, &self.input)
+    }
+
+    /// Lit segments, bit `k` for the `k`th segment input; a hex digit maps
+    /// its value through `HEX_SEGMENTS`. An LED is segment 0.
+    pub fn segments(&self) -> u32 {
+        match self.kind {
+            DisplayKind::HexDigit => HEX_SEGMENTS[self.inputs() as usize & 0xF],
+            DisplayKind::PixelMatrix => 0,
+            _ => self.inputs() as u32,
+        }
+    }
+
+    pub fn pixel(&self, column: usize, row: usize) -> bool {
+        self.pixels.get(row).is_some_and(|r| r >> column & 1 == 1)
+    }
+
+    /// Stores a pixel matrix row on a rising clock edge. See
+    /// `MemoryGate::clock`.
+    pub fn clock(&mut self) {
+        if self.kind != DisplayKind::PixelMatrix {
+            return;
+        }
+        let address_bits = row_address_bits(self.rows);
+        let n = self.input.len();
+        let clock = read_bus(&self.pins, &self.input[n - 1..]) as PinValue;
+        let write = read_bus(&self.pins, &self.input[n - 2..n - 1]) == 1;
+        if clock == 1 && self.last_clock == 0 && write {
+            let row = read_bus(&self.pins, &self.input[..address_bits]) as usize;
+            let data = read_bus(&self.pins, &self.input[address_bits..n - 2]);
+            if let Some(r) = self.pixels.get_mut(row) {
+                *r = data;
+            }
+        }
+        self.last_clock = clock;
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        self.clock();
+        match self.kind {
+            DisplayKind::PixelMatrix => self.pixels.iter().any(|&r| r != 0),
+            _ => self.segments() != 0,
+        }
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(kind: DisplayKind, columns: usize, rows: usize) -> DisplayGate {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        DisplayGate::new(kind, columns, rows, id, &mut ids)
+    }
+
+    fn drive(d: &mut DisplayGate, pins: std::ops::Range<usize>, value: u64) {
+        let bus = d.input[pins].to_vec();
+        write_bus(&mut d.pins, &bus, value);
+    }
+
+    #[test]
+    fn segments_follow_the_inputs() {
+        let mut led = display(DisplayKind::Led, 0, 0);
+        assert_eq!(led.input.len(), 1);
+        assert!(!led.evaluate());
+        drive(&mut led, 0..1, 1);
+        assert!(led.evaluate());
+        assert_eq!(led.segments(), 1);
+
+        let mut seven = display(DisplayKind::SevenSegment, 0, 0);
+        drive(&mut seven, 0..8, 0b1000_0110);
+        assert_eq!(seven.segments(), 0b1000_0110);
+        assert_eq!(display(DisplayKind::SixteenSegment, 0, 0).input.len(), 17);
+
+        // 0 lights all but g, 8 lights all seven, 1 only b and c.
+        let mut digit = display(DisplayKind::HexDigit, 0, 0);
+        for value in 0..16 {
+            drive(&mut digit, 0..4, value);
+            assert_eq!(digit.segments(), HEX_SEGMENTS[value as usize]);
+        }
+        assert_eq!(HEX_SEGMENTS[0], 0b011_1111);
+        assert_eq!(HEX_SEGMENTS[1], 0b000_0110);
+        assert_eq!(HEX_SEGMENTS[8], 0b111_1111);
+    }
+
+    #[test]
+    fn matrix_stores_rows_on_the_clock() {
+        // Five rows need three address bits: a0..a2, d0..d3, we, clk.
+        let mut m = display(DisplayKind::PixelMatrix, 4, 5);
+        assert_eq!(m.input.len(), 3 + 4 + 2);
+        let pulse = |m: &mut DisplayGate| {
+            drive(m, 8..9, 0);
+            m.evaluate();
+            drive(m, 8..9, 1);
+            m.evaluate()
+        };
+        drive(&mut m, 0..3, 2);
+        drive(&mut m, 3..7, 0b1001);
+        assert!(!pulse(&mut m));
+        drive(&mut m, 7..8, 1);
+        assert!(pulse(&mut m));
+        assert_eq!(m.pixels, [0, 0, 0b1001, 0, 0]);
+        assert!(m.pixel(0, 2) && m.pixel(3, 2) && !m.pixel(1, 2));
+        assert!(!m.pixel(0, 9));
+
+        // Row 7 does not exist and is ignored.
+        drive(&mut m, 0..3, 7);
+        pulse(&mut m);
+        assert_eq!(m.pixels, [0, 0, 0b1001, 0, 0]);
+    }
+
+    #[test]
+    fn matrix_sizes_are_checked() {
+        assert!(check_matrix_size(1, MAX_MATRIX_SIZE).is_ok());
+        assert_eq!(
+            check_matrix_size(8, 0),
+            Err(LgsimError::InvalidWidth {
+                width: 0,
+                max: MAX_MATRIX_SIZE,
+            })
+        );
+        let m = display(DisplayKind::PixelMatrix, 100, 1);
+        assert_eq!((m.columns, m.rows), (MAX_MATRIX_SIZE, 1));
+        assert_eq!(m.input.len(), 1 + MAX_MATRIX_SIZE + 2);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::Gate;
+use crate::pin::PinType;
+use crate::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Result of `extract_subcircuit`.
+pub struct Extraction {
+    /// The new chip, with its own ID space, ready to be stored as a template.
+    pub template: Chip,
+    /// The instance of `template` that replaced the extracted gates.
+    pub instance: GateId,
+}
+
+/// Moves `selection` out of `chip` into a new template called `name` and
+/// puts a single instance of it in their place.
+///
+/// Wires entering the selection become shell inputs (one per outside driver,
+/// however many pins it feeds) and wires leaving it become shell outputs (one
+/// per inside driver). Shell pins are ordered by the outside/inside driver's
+/// ID, so the same selection always yields the same pinout.
+pub fn extract_subcircuit(
+    chip: &mut Chip,
+    selection: &HashSet<GateId>,
+    name: &str,
+) -> Result<Extraction, LgsimError> {
+    if selection.is_empty() {
+        return Err(LgsimError::EmptySelection);
+    }
+    let mut inside: HashSet<PinId> = HashSet::new();
+    for gid in selection {
+        let gate = chip.gates.get(gid).ok_or(LgsimError::UnknownGate(*gid))?;
+        if matches!(gate, Gate::Source(_) | Gate::Output(_)) {
+            return Err(LgsimError::UnsupportedGate(gate.gate_type()));
+        }
+        inside.extend(gate.input().iter().chain(gate.output()).copied());
+    }
+
+    let wires = chip.wires();
+    let mut entering: Vec<PinId> = Vec::new();
+    let mut leaving: Vec<PinId> = Vec::new();
+    for &(src, dest) in &wires {
+        match (inside.contains(&src), inside.contains(&dest)) {
+            (false, true) if !entering.contains(&src) => entering.push(src),
+            (true, false) if !leaving.contains(&src) => leaving.push(src),
+            _ => {}
+        }
+    }
+
+    let mut template = Chip::new(ChipId(0));
+    template.name = Some(name.to_string());
+    let shell_in: HashMap<PinId, PinId> = entering
+        .iter()
+        .map(|&src| (src, template.add_shell_pin(PinType::ChipInput)))
+        .collect();
+    let shell_out: HashMap<PinId, PinId> = leaving
+        .iter()
+        .map(|&src| (src, template.add_shell_pin(PinType::ChipOutput)))
+        .collect();
+
+    let mut id_map: HashMap<PinId, PinId> = HashMap::new();
+    let mut ordered: Vec<&GateId> = selection.iter().collect();
+    ordered.sort();
+    for gid in ordered {
+        let copy = chip.gates[gid].clone_with_new_ids(&mut id_map, &mut template.ids)?;
+        template.add_gate(copy);
+    }
+    for &(src, dest) in &wires {
+        match (inside.contains(&src), inside.contains(&dest)) {
+            (true, true) => template.connect_pins(id_map[&src], id_map[&dest])?,
+            (false, true) => template.connect_pins(shell_in[&src], id_map[&dest])?,
+            _ => {}
+        }
+    }
+    for &src in &leaving {
+        template.connect_pins(id_map[&src], shell_out[&src])?;
+    }
+
+    // Rewire a copy, so a failure leaves `chip` as it was.
+    let mut rewired = chip.clone();
+    let instance_chip = template.deep_copy(&mut rewired.ids)?;
+    let instance_in = instance_chip.input.clone();
+    let instance_out = instance_chip.output.clone();
+    for gid in selection {
+        rewired.remove_gate(gid);
+    }
+    let instance = rewired.add_gate(Gate::Chip(instance_chip));
+    for (k, &src) in entering.iter().enumerate() {
+        rewired.connect_pins(src, instance_in[k])?;
+    }
+    for &(src, dest) in &wires {
+        if inside.contains(&src) && !inside.contains(&dest) {
+            let k = leaving
+                .iter()
+                .position(|&p| p == src)
+                .ok_or(LgsimError::UnknownPin(src))?;
+            rewired.connect_pins(instance_out[k], dest)?;
+        }
+    }
+    *chip = rewired;
+    Ok(Extraction { template, instance })
+}
+
+/// Replaces the chip instance `instance` with its internal gates and wires,
+/// re-wiring the outer nets straight to the internal pins. Returns the IDs of
+/// the gates that were moved up, in ID order. Nested chips stay nested.
+pub fn inline_chip(chip: &mut Chip, instance: &GateId) -> Result<Vec<GateId>, LgsimError> {
+    let gate = chip
+        .gates
+        .get(instance)
+        .ok_or(LgsimError::UnknownGate(*instance))?;
+    let Gate::Chip(_) = gate else {
+        return Err(LgsimError::UnsupportedGate(gate.gate_type()));
+    };
+    let drivers: HashMap<PinId, PinId> = gate
+        .input()
+        .iter()
+        .filter_map(|pin| Some((*pin, chip.driver_of(pin)?)))
+        .collect();
+    let sinks: HashMap<PinId, Vec<PinId>> = gate
+        .output()
+        .iter()
+        .map(|pin| (*pin, chip.connections.get(pin).cloned().unwrap_or_default()))
+        .collect();
+    let Some(Gate::Chip(inner)) = chip.remove_gate(instance) else {
+        return Err(LgsimError::UnknownGate(*instance));
+    };
+
+    // The instance's internals were allocated from this chip's allocator when
+    // it was placed, so they can move up without renumbering.
+    let mut moved: Vec<GateId> = inner.gates.keys().copied().collect();
+    moved.sort();
+    let wires = inner.wires();
+    for (_, gate) in inner.gates {
+        chip.add_gate(gate);
+    }
+    for (src, dest) in wires {
+        let src = if inner.input.contains(&src) {
+            match drivers.get(&src) {
+                Some(&outer) => outer,
+                None => continue,
+            }
+        } else {
+            src
+        };
+        let dests = sinks.get(&dest).cloned().unwrap_or_else(|| vec![dest]);
+        for dest in dests {
+            chip.connect_pins(src, dest)?;
+        }
+    }
+    Ok(moved)
+}
+
+/// Copies `chip` with every nested chip, at any depth, inlined, so that only
+/// primitive gates remain.
+pub fn flatten(chip: &Chip) -> Result<Chip, LgsimError> {
+    let mut flat = chip.clone();
+    loop {
+        let mut nested: Vec<GateId> = flat
+            .gates
+            .iter()
+            .filter(|(_, g)| matches!(g, Gate::Chip(_)))
+            .map(|(id, _)| *id)
+            .collect();
+        if nested.is_empty() {
+            return Ok(flat);
+        }
+        nested.sort();
+        for id in nested {
+            inline_chip(&mut flat, &id)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+    use crate::gate::GateType;
+
+    /// Outputs for every combination of inputs, input 0 in bit 0.
+    fn truth_table(chip: &Chip) -> Vec<Vec<Option<PinValue>>> {
+        let mut chip = chip.clone();
+        (0..1u32 << chip.input.len())
+            .map(|bits| {
+                for (k, pin) in chip.input.clone().iter().enumerate() {
+                    chip.set_pin(pin, Some((bits >> k) as PinValue & 1));
+                }
+                chip.simulate().unwrap();
+                chip.output.iter().map(|p| chip.pins[p].val).collect()
+            })
+            .collect()
+    }
+
+    /// A full adder from primitive gates.
+    fn full_adder() -> Chip {
+        crate::circuit! {
+            inputs: [a, b, c];
+            let partial = xor(a, b);
+            let s = xor(partial, c);
+            let generate = and(a, b);
+            let propagate = and(partial, c);
+            let carry = or(generate, propagate);
+            outputs: [s, carry];
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn extract_keeps_behaviour() {
+        let mut chip = full_adder();
+        let before = truth_table(&chip);
+        for (bits, row) in before.iter().enumerate() {
+            let total = (bits & 1) + (bits >> 1 & 1) + (bits >> 2 & 1);
+            assert_eq!(row, &[Some(total as u8 & 1), Some(total as u8 >> 1)]);
+        }
+        let mut ids: Vec<GateId> = chip.gates.keys().copied().collect();
+        ids.sort();
+        // The first half of the gates: the first XOR and a bit more.
+        let selection: HashSet<GateId> = ids[..ids.len() / 2].iter().copied().collect();
+        let extraction = extract_subcircuit(&mut chip, &selection, "Part").unwrap();
+
+        assert_eq!(extraction.template.name.as_deref(), Some("Part"));
+        assert_eq!(extraction.template.gates.len(), selection.len());
+        assert!(!extraction.template.input.is_empty());
+        assert!(!extraction.template.output.is_empty());
+        assert_eq!(chip.gates.len(), ids.len() - selection.len() + 1);
+        assert!(matches!(chip.gates[&extraction.instance], Gate::Chip(_)));
+        assert_eq!(truth_table(&chip), before);
+    }
+
+    #[test]
+    fn extract_orders_shell_pins_by_driver() {
+        let mut b = ChipBuilder::new();
+        let (x, y) = (b.input(), b.input());
+        let n = b.nand(x, y);
+        b.output(n);
+        let mut chip = b.build().unwrap();
+        let all: HashSet<GateId> = chip.gates.keys().copied().collect();
+        let template = extract_subcircuit(&mut chip.clone(), &all, "NAND")
+            .unwrap()
+            .template;
+        let again = extract_subcircuit(&mut chip, &all, "NAND")
+            .unwrap()
+            .template;
+        assert_eq!(template, again);
+        // Two outside drivers feed the AND; one inside driver leaves.
+        assert_eq!((template.input.len(), template.output.len()), (2, 1));
+    }
+
+    #[test]
+    fn failed_extract_leaves_chip_alone() {
+        let mut chip = full_adder();
+        let not = Gate::new(GateType::Not, vec![], &mut chip.ids);
+        let input = not.input()[0];
+        let id = chip.add_gate(not);
+        // A wire from a pin that no longer exists only fails once the
+        // instance is being wired in.
+        chip.connections.insert(PinId(9999), vec![input]);
+        let before = chip.clone();
+        assert_eq!(
+            extract_subcircuit(&mut chip, &HashSet::from([id]), "X").err(),
+            Some(LgsimError::UnknownPin(PinId(9999)))
+        );
+        assert_eq!(chip, before);
+    }
+
+    #[test]
+    fn extract_rejects_bad_selections() {
+        let mut chip = full_adder();
+        assert_eq!(
+            extract_subcircuit(&mut chip, &HashSet::new(), "X").err(),
+            Some(LgsimError::EmptySelection)
+        );
+        let missing = HashSet::from([GateId(9999)]);
+        assert_eq!(
+            extract_subcircuit(&mut chip, &missing, "X").err(),
+            Some(LgsimError::UnknownGate(GateId(9999)))
+        );
+    }
+
+    /// Number of non-chip gates at every level of `chip`.
+    fn primitives(chip: &Chip) -> usize {
+        chip.gates
+            .values()
+            .map(|g| match g {
+                Gate::Chip(inner) => primitives(inner),
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// A two-bit ripple adder from full adder instances.
+    fn ripple_adder() -> Chip {
+        let stage = full_adder();
+        let mut b = ChipBuilder::new();
+        let (a0, a1, b0, b1) = (b.input(), b.input(), b.input(), b.input());
+        let carry = b.input();
+        let low = b.instance(&stage, &[a0, b0, carry]);
+        let high = b.instance(&stage, &[a1, b1, low[1]]);
+        b.output(low[0]);
+        b.output(high[0]);
+        b.output(high[1]);
+        b.build().unwrap()
+    }
+
+    #[test]
+    fn inline_moves_gates_up() {
+        let mut chip = ripple_adder();
+        let before = truth_table(&chip);
+        let mut instances: Vec<GateId> = chip.gates.keys().copied().collect();
+        instances.sort();
+        let Gate::Chip(inner) = &chip.gates[&instances[0]] else {
+            unreachable!();
+        };
+        let mut inner_ids: Vec<GateId> = inner.gates.keys().copied().collect();
+        inner_ids.sort();
+
+        let moved = inline_chip(&mut chip, &instances[0]).unwrap();
+        assert_eq!(moved, inner_ids);
+        assert!(!chip.gates.contains_key(&instances[0]));
+        assert!(moved.iter().all(|id| chip.gates.contains_key(id)));
+        assert_eq!(chip.gates.len(), 1 + inner_ids.len());
+        assert_eq!(truth_table(&chip), before);
+    }
+
+    #[test]
+    fn inline_rejects_other_gates() {
+        let mut chip = full_adder();
+        let id = *chip.gates.keys().next().unwrap();
+        let kind = chip.gates[&id].gate_type();
+        assert_eq!(
+            inline_chip(&mut chip, &id),
+            Err(LgsimError::UnsupportedGate(kind))
+        );
+        assert_eq!(
+            inline_chip(&mut chip, &GateId(9999)),
+            Err(LgsimError::UnknownGate(GateId(9999)))
+        );
+    }
+
+    #[test]
+    fn flatten_removes_every_level() {
+        // A chip holding the ripple adder, so instances are two deep.
+        let adder = ripple_adder();
+        let mut b = ChipBuilder::new();
+        let inputs: Vec<_> = (0..5).map(|_| b.input()).collect();
+        for out in b.instance(&adder, &inputs) {
+            b.output(out);
+        }
+        let chip = b.build().unwrap();
+        let flat = flatten(&chip).unwrap();
+
+        assert!(flat.gates.values().all(|g| !matches!(g, Gate::Chip(_))));
+        assert_eq!(flat.gates.len(), primitives(&chip));
+        assert_eq!((&flat.input, &flat.output), (&chip.input, &chip.output));
+        assert_eq!(truth_table(&flat), truth_table(&chip));
+        assert_eq!(truth_table(&flat), truth_table(&adder));
+    }
+}
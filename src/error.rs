@@ -0,0 +1,171 @@
+use crate::gate::GateType;
+use crate::types::*;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LgsimError {
+    UnknownPin(PinId),
+    UnknownGate(GateId),
+    SelfConnection(PinId),
+    AlreadyConnected {
+        from: PinId,
+        to: PinId,
+    },
+    /// `from` cannot drive a signal, or `to` cannot receive one.
+    WrongDirection {
+        from: PinId,
+        to: PinId,
+    },
+    /// `pin` is already driven by `existing`.
+    DuplicateDriver {
+        pin: PinId,
+        existing: PinId,
+    },
+    /// Source/Output gates belong to a board and cannot be copied into a chip.
+    UnsupportedGate(GateType),
+    EmptySelection,
+    UnknownTemplate(String),
+    /// A template with this name already exists.
+    NameTaken(String),
+    /// The template cannot be deleted while `users` (other templates, or
+    /// "the board") contain instances of it.
+    TemplateInUse {
+        name: String,
+        users: Vec<String>,
+    },
+    /// Malformed input file; `line` is 1-based.
+    Parse {
+        line: usize,
+        message: String,
+    },
+    Io(String),
+    /// Importing `imported` would downgrade library `name` from `installed`.
+    OlderLibrary {
+        name: String,
+        installed: u32,
+        imported: u32,
+    },
+    InvalidWidth {
+        width: usize,
+        max: usize,
+    },
+    /// `words` words were given for a memory that holds `capacity`.
+    MemoryOverflow {
+        words: usize,
+        capacity: usize,
+    },
+    /// A text file format was read from bytes that are not UTF-8; `offset`
+    /// is the first bad byte.
+    NotText {
+        offset: usize,
+    },
+    /// A program was assembled for a chip that has no ROM to hold it.
+    NoRom,
+    /// The program window was used with no chip open in it.
+    NoProgramView,
+    /// The hex editor was used with no memory open in it.
+    NoMemoryView,
+    /// A chip input was picked by position but the chip only has `count`.
+    NoSuchInput {
+        index: usize,
+        count: usize,
+    },
+    /// A chip instance was given `given` input handles for `expected` inputs.
+    InputCountMismatch {
+        expected: usize,
+        given: usize,
+    },
+    /// A builder gate handle was asked for a pin past the gate's last one.
+    NoSuchPin,
+    /// A probe path that does not lead to pins of the board.
+    UnknownProbe(String),
+    /// A probe with this name is already being watched.
+    ProbeNameTaken(String),
+    /// A clock step was asked for with no board input chosen as the clock.
+    NoClock,
+    /// Text typed where a number was expected.
+    InvalidValue(String),
+    /// The circuit has feedback through this pin, so it is not combinational.
+    CombinationalLoop(PinId),
+}
+
+impl fmt::Display for LgsimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LgsimError::UnknownPin(id) => write!(f, "unknown pin {}", id.0),
+            LgsimError::UnknownGate(id) => write!(f, "unknown gate {}", id.0),
+            LgsimError::SelfConnection(id) => write!(f, "pin {} cannot be wired to itself", id.0),
+            LgsimError::AlreadyConnected { from, to } => {
+                write!(f, "pin {} is already wired to pin {}", from.0, to.0)
+            }
+            LgsimError::WrongDirection { from, to } => write!(
+                f,
+                "cannot connect pin {} to pin {}: wires must run from an output to an input",
+                from.0, to.0
+            ),
+            LgsimError::DuplicateDriver { pin, existing } => {
+                write!(f, "pin {} is already driven by pin {}", pin.0, existing.0)
+            }
+            LgsimError::UnsupportedGate(kind) => {
+                write!(f, "{:?} gates cannot be placed inside a chip", kind)
+            }
+            LgsimError::EmptySelection => write!(f, "nothing is selected"),
+            LgsimError::UnknownTemplate(name) => write!(f, "there is no chip named \"{}\"", name),
+            LgsimError::NameTaken(name) => write!(f, "a chip named \"{}\" already exists", name),
+            LgsimError::TemplateInUse { name, users } => {
+                write!(f, "\"{}\" is still used by {}", name, users.join(", "))
+            }
+            LgsimError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LgsimError::Io(message) => write!(f, "{}", message),
+            LgsimError::OlderLibrary {
+                name,
+                installed,
+                imported,
+            } => write!(
+                f,
+                "library \"{}\" version {} is older than the installed version {}",
+                name, imported, installed
+            ),
+            LgsimError::InvalidWidth { width, max } => {
+                write!(f, "width {} is out of range (1 to {})", width, max)
+            }
+            LgsimError::MemoryOverflow { words, capacity } => write!(
+                f,
+                "{} words do not fit in a memory of {} words",
+                words, capacity
+            ),
+            LgsimError::NotText { offset } => {
+                write!(f, "the file is not text (bad byte at offset {})", offset)
+            }
+            LgsimError::NoRom => write!(f, "there is no ROM to load into"),
+            LgsimError::NoProgramView => write!(f, "no chip is open in the program window"),
+            LgsimError::NoMemoryView => write!(f, "no memory is open in the hex editor"),
+            LgsimError::NoSuchInput { index, count } => {
+                write!(f, "there is no input {} (the chip has {})", index, count)
+            }
+            LgsimError::InputCountMismatch { expected, given } => {
+                write!(f, "{} inputs given for a chip with {}", given, expected)
+            }
+            LgsimError::NoSuchPin => write!(f, "a gate pin was picked past the gate's last pin"),
+            LgsimError::UnknownProbe(path) => write!(f, "no pins at probe path \"{}\"", path),
+            LgsimError::ProbeNameTaken(name) => {
+                write!(f, "a probe named \"{}\" already exists", name)
+            }
+            LgsimError::NoClock => write!(f, "no board input is chosen as the clock"),
+            LgsimError::CombinationalLoop(pin) => write!(
+                f,
+                "pin {} is on a feedback loop; the circuit is not combinational",
+                pin.0
+            ),
+            LgsimError::InvalidValue(text) => write!(f, "\"{}\" is not a number", text),
+        }
+    }
+}
+
+impl std::error::Error for LgsimError {}
+
+impl From<std::io::Error> for LgsimError {
+    fn from(e: std::io::Error) -> Self {
+        LgsimError::Io(e.to_string())
+    }
+}
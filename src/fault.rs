@@ -0,0 +1,341 @@
+//! Stuck-at fault simulation. Every pin of a chip, nested instances
+//! included, can be stuck at 0 or 1; a fault is detected when some test
+//! vector makes an observed pin differ from the fault-free circuit. Faults
+//! are simulated one at a time on a copy of the chip, applying the vectors
+//! in order so sequential circuits keep their state between them, and each
+//! fault is dropped as soon as it is detected.
+
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::Gate;
+use crate::types::*;
+use std::fmt;
+
+/// The pins a test drives and the pins it observes. For a chip these are
+/// its shell inputs and outputs; for a board, the pins of its Source and
+/// Output gates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ports {
+    pub inputs: Vec<PinId>,
+    pub outputs: Vec<PinId>,
+}
+
+impl Ports {
+    pub fn of_chip(chip: &Chip) -> Self {
+        Self {
+            inputs: chip.input.clone(),
+            outputs: chip.output.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Fault {
+    StuckAt0,
+    StuckAt1,
+}
+
+impl Fault {
+    pub fn value(self) -> PinValue {
+        match self {
+            Fault::StuckAt0 => 0,
+            Fault::StuckAt1 => 1,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stuck-at-{}", self.value())
+    }
+}
+
+/// A fault on one pin. `path` leads from the chip down to the level the pin
+/// is on: the pins of a nested instance's shell belong to the level holding
+/// the instance, like any other gate's.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FaultSite {
+    pub path: Vec<GateId>,
+    pub pin: PinId,
+    pub fault: Fault,
+}
+
+impl FaultSite {
+    /// Where the pin is, in the `gate/gate/in1` form probes use, e.g.
+    /// `12/40/out0 stuck-at-1`. Pins of the chip's own shell have no gate.
+    pub fn describe(&self, chip: &Chip) -> String {
+        let mut text: String = self.path.iter().map(|id| format!("{}/", id.0)).collect();
+        match pin_owner(chip, &self.path, &self.pin) {
+            Some((gate, name)) => {
+                if let Some(gate) = gate {
+                    text += &format!("{}/", gate.0);
+                }
+                text += &name;
+            }
+            None => text += &format!("pin {}", self.pin.0),
+        }
+        format!("{} {}", text, self.fault)
+    }
+
+    /// The gate on the top level of `chip` that holds the fault: the
+    /// outermost instance on the path, or the gate owning the pin.
+    pub fn top_gate(&self, chip: &Chip) -> Option<GateId> {
+        match self.path.first() {
+            Some(id) => Some(*id),
+            None => pin_owner(chip, &[], &self.pin).and_then(|(gate, _)| gate),
+        }
+    }
+}
+
+/// The chip at `path` below `chip`.
+pub fn level<'a>(chip: &'a Chip, path: &[GateId]) -> Option<&'a Chip> {
+    path.iter().try_fold(chip, |c, id| match c.gates.get(id)? {
+        Gate::Chip(inner) => Some(inner),
+        _ => None,
+    })
+}
+
+/// The path to the level of `chip` that `pin` is on, by the convention of
+/// `FaultSite`.
+pub fn locate(chip: &Chip, pin: &PinId) -> Option<Vec<GateId>> {
+    if chip.pins.contains_key(pin) || chip.gates.values().any(|g| g.pins().contains_key(pin)) {
+        return Some(Vec::new());
+    }
+    let mut ids: Vec<&GateId> = chip.gates.keys().collect();
+    ids.sort();
+    ids.into_iter().find_map(|id| match &chip.gates[id] {
+        Gate::Chip(inner) => locate(inner, pin).map(|mut path| {
+            path.insert(0, *id);
+            path
+        }),
+        _ => None,
+    })
+}
+
+fn level_mut<'a>(chip: &'a mut Chip, path: &[GateId]) -> Option<&'a mut Chip> {
+    path.iter()
+        .try_fold(chip, |c, id| match c.gates.get_mut(id)? {
+            Gate::Chip(inner) => Some(inner),
+            _ => None,
+        })
+}
+
+/// The gate owning `pin` on the level at `path` (none for the level's own
+/// shell) and the pin's name on it, `in<k>` or `out<k>`.
+fn pin_owner(chip: &Chip, path: &[GateId], pin: &PinId) -> Option<(Option<GateId>, String)> {
+    let level = level(chip, path)?;
+    let name = |input: &[PinId], output: &[PinId]| {
+        if let Some(k) = input.iter().position(|p| p == pin) {
+            Some(format!("in{}", k))
+        } else {
+            output
+                .iter()
+                .position(|p| p == pin)
+                .map(|k| format!("out{}", k))
+        }
+    };
+    if let Some(name) = name(&level.input, &level.output) {
+        return Some((None, name));
+    }
+    level
+        .gates
+        .values()
+        .find_map(|g| name(g.input(), g.output()).map(|n| (Some(g.id()), n)))
+}
+
+/// Both stuck-at faults on every pin of `chip` and the chips nested in it,
+/// in a stable order.
+pub fn all_faults(chip: &Chip) -> Vec<FaultSite> {
+    let mut sites = Vec::new();
+    collect_faults(chip, &mut Vec::new(), &mut sites);
+    sites.sort();
+    sites
+}
+
+fn collect_faults(chip: &Chip, path: &mut Vec<GateId>, sites: &mut Vec<FaultSite>) {
+    // A nested chip's shell pins are its instance's pins, already counted
+    // on the level above.
+    let root = path.is_empty();
+    let shell = chip.input.iter().chain(&chip.output).filter(|_| root);
+    let gates = chip
+        .gates
+        .values()
+        .flat_map(|g| g.input().iter().chain(g.output()));
+    for pin in shell.chain(gates) {
+        for fault in [Fault::StuckAt0, Fault::StuckAt1] {
+            sites.push(FaultSite {
+                path: path.clone(),
+                pin: *pin,
+                fault,
+            });
+        }
+    }
+    for (id, gate) in &chip.gates {
+        if let Gate::Chip(inner) = gate {
+            path.push(*id);
+            collect_faults(inner, path, sites);
+            path.pop();
+        }
+    }
+}
+
+/// Sets a port pin: a shell pin of `chip` or the output of one of its gates.
+pub fn drive(chip: &mut Chip, pin: &PinId, val: PinValue) {
+    if chip.pins.contains_key(pin) {
+        chip.set_pin(pin, Some(val));
+    } else if let Some(gate) = chip.gates.values_mut().find(|g| g.output().contains(pin)) {
+        gate.set_pin(pin, Some(val));
+    }
+}
+
+/// Applies `vectors` in order, handing `stop` the index and the observed
+/// outputs after each, until it returns true.
+fn run(
+    chip: &mut Chip,
+    ports: &Ports,
+    vectors: &[Vec<PinValue>],
+    stop: &mut dyn FnMut(usize, &[Option<PinValue>]) -> bool,
+) -> Result<(), LgsimError> {
+    for (i, vector) in vectors.iter().enumerate() {
+        for (pin, &val) in ports.inputs.iter().zip(vector) {
+            drive(chip, pin, val);
+        }
+        chip.simulate()?;
+        let observed: Vec<Option<PinValue>> = ports
+            .outputs
+            .iter()
+            .map(|p| chip.find_pin(p).and_then(|p| p.val))
+            .collect();
+        if stop(i, &observed) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultReport {
+    pub faults: Vec<FaultSite>,
+    /// For each fault, the first vector that detects it.
+    pub detected_by: Vec<Option<usize>>,
+}
+
+impl FaultReport {
+    pub fn detected(&self) -> usize {
+        self.detected_by.iter().filter(|d| d.is_some()).count()
+    }
+
+    /// Detected faults as a percentage of all faults.
+    pub fn coverage(&self) -> f64 {
+        if self.faults.is_empty() {
+            return 100.0;
+        }
+        100.0 * self.detected() as f64 / self.faults.len() as f64
+    }
+
+    pub fn undetected(&self) -> impl Iterator<Item = &FaultSite> {
+        self.faults
+            .iter()
+            .zip(&self.detected_by)
+            .filter(|(_, d)| d.is_none())
+            .map(|(f, _)| f)
+    }
+}
+
+/// Simulates every fault in `faults` against `vectors`, each a value for
+/// every input port, starting each from the state `chip` is in.
+pub fn simulate_faults(
+    chip: &Chip,
+    ports: &Ports,
+    faults: Vec<FaultSite>,
+    vectors: &[Vec<PinValue>],
+) -> Result<FaultReport, LgsimError> {
+    let mut good = Vec::new();
+    run(&mut chip.clone(), ports, vectors, &mut |_, observed| {
+        good.push(observed.to_vec());
+        false
+    })?;
+    let mut detected_by = Vec::with_capacity(faults.len());
+    for site in &faults {
+        let mut faulty = chip.clone();
+        level_mut(&mut faulty, &site.path)
+            .ok_or(LgsimError::UnknownPin(site.pin))?
+            .faults
+            .insert(site.pin, site.fault.value());
+        let mut detected = None;
+        run(&mut faulty, ports, vectors, &mut |i, observed| {
+            if observed != good[i] {
+                detected = Some(i);
+            }
+            detected.is_some()
+        })?;
+        detected_by.push(detected);
+    }
+    Ok(FaultReport {
+        faults,
+        detected_by,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+
+    /// An input through an inverter chip instance to an output.
+    fn nested() -> Chip {
+        let inverter = crate::circuit! {
+            inputs: [a];
+            let y = not(a);
+            outputs: [y];
+        }
+        .unwrap();
+        let mut b = ChipBuilder::new();
+        let x = b.input();
+        let y = b.instance(&inverter, &[x]);
+        b.output(y[0]);
+        b.build().unwrap()
+    }
+
+    #[test]
+    fn nested_shell_pins_are_counted_once() {
+        let chip = nested();
+        let faults = all_faults(&chip);
+        // The board's two shell pins, the instance's two and the inverter's
+        // two, each stuck at 0 and at 1.
+        assert_eq!(faults.len(), 12);
+        let mut pins: Vec<PinId> = faults.iter().map(|f| f.pin).collect();
+        pins.dedup();
+        assert_eq!(pins.len(), 6);
+        let inner = faults.iter().filter(|f| !f.path.is_empty()).count();
+        assert_eq!(inner, 4);
+        for site in &faults {
+            assert_eq!(locate(&chip, &site.pin).as_ref(), Some(&site.path));
+        }
+    }
+
+    #[test]
+    fn coverage_follows_the_vectors() {
+        let chip = nested();
+        let ports = Ports::of_chip(&chip);
+
+        // With the input low every pin is at one value, so only the fault
+        // to the other value shows.
+        let report = simulate_faults(&chip, &ports, all_faults(&chip), &[vec![0]]).unwrap();
+        assert_eq!(report.detected(), 6);
+        assert_eq!(report.coverage(), 50.0);
+        let mut good = chip.clone();
+        run(&mut good, &ports, &[vec![0]], &mut |_, _| false).unwrap();
+        for f in report.undetected() {
+            let level = level(&good, &f.path).unwrap();
+            let val = level.find_pin(&f.pin).and_then(|p| p.val);
+            assert_eq!(val, Some(f.fault.value()), "{}", f.describe(&chip));
+        }
+
+        let report =
+            simulate_faults(&chip, &ports, all_faults(&chip), &[vec![0], vec![1]]).unwrap();
+        assert_eq!(report.coverage(), 100.0);
+        assert!(report.detected_by.iter().all(|d| d.is_some()));
+        assert_eq!(report.undetected().count(), 0);
+    }
+}
@@ -0,0 +1,945 @@
+//! Plain-text chip format used by library files.
+//!
+//! A chip is a block of lines:
+//!
+//! ```text
+//! chip "XOR" in 1 2 out 3
+//!   and in 4 5 out 6
+//!   not in 7 out 8
+//!   chip "Half Adder" in 9 10 out 11 12
+//!     ...
+//!   end
+//!   wire 1 4
+//! end
+//! ```
+//!
+//! Registers are written as `<kind> <width> in ... out ...`, where kind is
+//! `register`, `counter`, `sipo` or `piso`. Displays are written as
+//! `led <rrggbb> in ...`, `seg7 in ...`, `seg16 in ...`, `hexdigit in ...`
+//! or `matrix <columns> <rows> in ...`. Input devices are written as
+//! `button out ...`, `toggle out ...`, `dip <switches> out ...`,
+//! `keypad out ...` or `tie <0|1> out ...`; only a tie keeps its value.
+//! Keyboards and terminals are `keyboard in ... out ...` and
+//! `terminal in ...`, and the reference CPU core is `cpu in ... out ...`.
+//! Memories are written as `ram <address bits> <data bits> in ... out ...`
+//! (or `rom`), followed by `data` lines of hexadecimal words starting at
+//! address 0.
+//!
+//! Board files (see `project`) also have `input in out ...` and
+//! `output in ... out` lines for the board's inputs and outputs; templates
+//! cannot contain them.
+//!
+//! Numbers are pin labels local to the file; they only have to be unique
+//! within a block and its header. Names are quoted (`\"` and `\\` escape),
+//! `-` stands for an unnamed chip and `#` starts a comment.
+
+use crate::circuit::Chip;
+use crate::cpu::CpuGate;
+use crate::display::{self, DisplayGate, DisplayKind};
+use crate::error::LgsimError;
+use crate::gate::{Gate, GateType};
+use crate::memory::{self, MemoryGate, MemoryKind};
+use crate::peripheral::{KeyboardGate, TerminalGate};
+use crate::register::{self, RegisterGate, RegisterKind};
+use crate::switch::{self, SwitchGate, SwitchKind};
+use crate::types::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Pin labels of gates without inputs or outputs.
+static NO_PINS: Vec<usize> = Vec::new();
+
+/// A chip as read from a file, before any IDs are allocated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipNode {
+    pub name: Option<String>,
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+    /// `(gate, line)`.
+    pub gates: Vec<(GateNode, usize)>,
+    /// `(from, to, line)`.
+    pub wires: Vec<(usize, usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateNode {
+    And {
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+    Not {
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+    Chip(ChipNode),
+    Memory {
+        kind: MemoryKind,
+        address_bits: usize,
+        data_bits: usize,
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+        contents: Vec<u64>,
+    },
+    Register {
+        kind: RegisterKind,
+        width: usize,
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+    Display {
+        kind: DisplayKind,
+        color: [u8; 3],
+        columns: usize,
+        rows: usize,
+        inputs: Vec<usize>,
+    },
+    Switch {
+        kind: SwitchKind,
+        switches: usize,
+        tie: u64,
+        outputs: Vec<usize>,
+    },
+    /// A board input; only board files have these.
+    Input {
+        outputs: Vec<usize>,
+    },
+    /// A board output; only board files have these.
+    Output {
+        inputs: Vec<usize>,
+    },
+    Keyboard {
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+    Terminal {
+        inputs: Vec<usize>,
+    },
+    Cpu {
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+}
+
+impl ChipNode {
+    /// Whether the block, or any block nested in it, has board inputs or
+    /// outputs.
+    pub fn has_board_io(&self) -> bool {
+        self.gates.iter().any(|(g, _)| match g {
+            GateNode::Input { .. } | GateNode::Output { .. } => true,
+            GateNode::Chip(inner) => inner.has_board_io(),
+            _ => false,
+        })
+    }
+}
+
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn pin_list(pins: &[PinId]) -> String {
+    pins.iter().map(|p| format!(" {}", p.0)).collect()
+}
+
+/// Appends `chip` and everything nested in it to `out`.
+pub fn write_chip(out: &mut String, chip: &Chip, depth: usize) -> Result<(), LgsimError> {
+    let indent = "  ".repeat(depth);
+    let name = chip.name.as_deref().map_or("-".to_string(), quote);
+    let _ = writeln!(
+        out,
+        "{}chip {} in{} out{}",
+        indent,
+        name,
+        pin_list(&chip.input),
+        pin_list(&chip.output)
+    );
+    let mut ids: Vec<&GateId> = chip.gates.keys().collect();
+    ids.sort();
+    for id in ids {
+        let gate = &chip.gates[id];
+        let kind = match gate {
+            Gate::And(_) => "and",
+            Gate::Not(_) => "not",
+            Gate::Source(_) => "input",
+            Gate::Output(_) => "output",
+            Gate::Keyboard(_) => "keyboard",
+            Gate::Terminal(_) => "terminal",
+            Gate::Cpu(_) => "cpu",
+            Gate::Chip(c) => {
+                write_chip(out, c, depth + 1)?;
+                continue;
+            }
+            Gate::Memory(m) => {
+                write_memory(out, m, &indent);
+                continue;
+            }
+            Gate::Register(r) => {
+                let _ = writeln!(
+                    out,
+                    "{}  {} {} in{} out{}",
+                    indent,
+                    r.kind.keyword(),
+                    r.width,
+                    pin_list(&r.input),
+                    pin_list(&r.output)
+                );
+                continue;
+            }
+            Gate::Display(d) => {
+                let params = match d.kind {
+                    DisplayKind::Led => {
+                        let [r, g, b] = d.color;
+                        format!(" {:02x}{:02x}{:02x}", r, g, b)
+                    }
+                    DisplayKind::PixelMatrix => format!(" {} {}", d.columns, d.rows),
+                    _ => String::new(),
+                };
+                let _ = writeln!(
+                    out,
+                    "{}  {}{} in{}",
+                    indent,
+                    d.kind.keyword(),
+                    params,
+                    pin_list(&d.input)
+                );
+                continue;
+            }
+            Gate::Switch(w) => {
+                let param = match w.kind {
+                    SwitchKind::DipSwitch => format!(" {}", w.output.len()),
+                    SwitchKind::Constant => format!(" {}", w.state),
+                    _ => String::new(),
+                };
+                let _ = writeln!(
+                    out,
+                    "{}  {}{} out{}",
+                    indent,
+                    w.kind.keyword(),
+                    param,
+                    pin_list(&w.output)
+                );
+                continue;
+            }
+        };
+        let _ = writeln!(
+            out,
+            "{}  {} in{} out{}",
+            indent,
+            kind,
+            pin_list(gate.input()),
+            pin_list(gate.output())
+        );
+    }
+    for (src, dest) in chip.wires() {
+        let _ = writeln!(out, "{}  wire {} {}", indent, src.0, dest.0);
+    }
+    let _ = writeln!(out, "{}end", indent);
+    Ok(())
+}
+
+fn write_memory(out: &mut String, m: &MemoryGate, indent: &str) {
+    let kind = match m.kind {
+        MemoryKind::Ram => "ram",
+        MemoryKind::Rom => "rom",
+    };
+    let _ = writeln!(
+        out,
+        "{}  {} {} {} in{} out{}",
+        indent,
+        kind,
+        m.address_bits,
+        m.data_bits,
+        pin_list(&m.input),
+        pin_list(&m.output)
+    );
+    let used = m
+        .contents
+        .iter()
+        .rposition(|&w| w != 0)
+        .map_or(0, |i| i + 1);
+    for row in m.contents[..used].chunks(16) {
+        let words: String = row.iter().map(|w| format!(" {:x}", w)).collect();
+        let _ = writeln!(out, "{}  data{}", indent, words);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    Text(String),
+}
+
+/// Splits one line into words and quoted strings.
+pub fn tokenize(line: &str, line_no: usize) -> Result<Vec<Token>, LgsimError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(e) => text.push(e),
+                        None => return Err(parse_error(line_no, "unfinished escape")),
+                    },
+                    Some(ch) => text.push(ch),
+                    None => return Err(parse_error(line_no, "missing closing quote")),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else {
+            let mut word = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch.is_whitespace() || ch == '"' || ch == '#' {
+                    break;
+                }
+                word.push(ch);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+pub fn parse_error(line: usize, message: impl Into<String>) -> LgsimError {
+    LgsimError::Parse {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Reads consecutive non-empty lines as token lists, keeping line numbers.
+pub struct Lines<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().enumerate(),
+        }
+    }
+
+    /// The next line that has any tokens, with its 1-based number.
+    pub fn next_tokens(&mut self) -> Result<Option<(usize, Vec<Token>)>, LgsimError> {
+        for (idx, line) in self.lines.by_ref() {
+            let tokens = tokenize(line, idx + 1)?;
+            if !tokens.is_empty() {
+                return Ok(Some((idx + 1, tokens)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Reads `in <pins> out <pins>` starting at `tokens[0]`.
+fn parse_pins(tokens: &[Token], line: usize) -> Result<(Vec<usize>, Vec<usize>), LgsimError> {
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut current: Option<&mut Vec<usize>> = None;
+    for token in tokens {
+        match token {
+            Token::Word(w) if w == "in" => current = Some(&mut inputs),
+            Token::Word(w) if w == "out" => current = Some(&mut outputs),
+            Token::Word(w) => {
+                let pin = w.parse().map_err(|_| {
+                    parse_error(line, format!("expected a pin number, found {}", w))
+                })?;
+                match current.as_mut() {
+                    Some(list) => list.push(pin),
+                    None => return Err(parse_error(line, "pin numbers must follow in/out")),
+                }
+            }
+            Token::Text(_) => return Err(parse_error(line, "unexpected name")),
+        }
+    }
+    Ok((inputs, outputs))
+}
+
+/// Parses a chip block whose `chip` header is `header` (line `line`),
+/// consuming lines up to and including its `end`.
+pub fn parse_chip(
+    lines: &mut Lines,
+    header: &[Token],
+    line: usize,
+) -> Result<ChipNode, LgsimError> {
+    let name = match header.get(1) {
+        Some(Token::Text(name)) => Some(name.clone()),
+        Some(Token::Word(w)) if w == "-" => None,
+        _ => return Err(parse_error(line, "expected a chip name or -")),
+    };
+    let (inputs, outputs) = parse_pins(&header[2..], line)?;
+    let mut node = ChipNode {
+        name,
+        inputs,
+        outputs,
+        gates: Vec::new(),
+        wires: Vec::new(),
+    };
+    loop {
+        let Some((line, tokens)) = lines.next_tokens()? else {
+            return Err(parse_error(line, "chip block is missing its end"));
+        };
+        let Token::Word(keyword) = &tokens[0] else {
+            return Err(parse_error(line, "expected a keyword"));
+        };
+        match keyword.as_str() {
+            "end" => return Ok(node),
+            "and" | "not" => {
+                let (inputs, outputs) = parse_pins(&tokens[1..], line)?;
+                node.gates.push((
+                    if keyword == "and" {
+                        GateNode::And { inputs, outputs }
+                    } else {
+                        GateNode::Not { inputs, outputs }
+                    },
+                    line,
+                ));
+            }
+            "input" => {
+                let (inputs, outputs) = parse_pins(&tokens[1..], line)?;
+                if !inputs.is_empty() {
+                    return Err(parse_error(line, "board inputs have no inputs"));
+                }
+                node.gates.push((GateNode::Input { outputs }, line));
+            }
+            "output" => {
+                let (inputs, outputs) = parse_pins(&tokens[1..], line)?;
+                if !outputs.is_empty() {
+                    return Err(parse_error(line, "board outputs have no outputs"));
+                }
+                node.gates.push((GateNode::Output { inputs }, line));
+            }
+            "keyboard" => {
+                let (inputs, outputs) = parse_pins(&tokens[1..], line)?;
+                node.gates
+                    .push((GateNode::Keyboard { inputs, outputs }, line));
+            }
+            "cpu" => {
+                let (inputs, outputs) = parse_pins(&tokens[1..], line)?;
+                node.gates.push((GateNode::Cpu { inputs, outputs }, line));
+            }
+            "terminal" => {
+                let (inputs, outputs) = parse_pins(&tokens[1..], line)?;
+                if !outputs.is_empty() {
+                    return Err(parse_error(line, "terminals have no outputs"));
+                }
+                node.gates.push((GateNode::Terminal { inputs }, line));
+            }
+            "ram" | "rom" => {
+                let kind = if keyword == "ram" {
+                    MemoryKind::Ram
+                } else {
+                    MemoryKind::Rom
+                };
+                let width = |i: usize| match tokens.get(i) {
+                    Some(Token::Word(w)) => w
+                        .parse::<usize>()
+                        .map_err(|_| parse_error(line, "bad memory width")),
+                    _ => Err(parse_error(line, "expected address and data widths")),
+                };
+                let (address_bits, data_bits) = (width(1)?, width(2)?);
+                memory::check_widths(address_bits, data_bits)
+                    .map_err(|e| parse_error(line, e.to_string()))?;
+                let (inputs, outputs) = parse_pins(&tokens[3..], line)?;
+                node.gates.push((
+                    GateNode::Memory {
+                        kind,
+                        address_bits,
+                        data_bits,
+                        inputs,
+                        outputs,
+                        contents: Vec::new(),
+                    },
+                    line,
+                ));
+            }
+            other if RegisterKind::from_keyword(other).is_some() => {
+                let kind = RegisterKind::from_keyword(other).unwrap_or(RegisterKind::Register);
+                let width = match tokens.get(1) {
+                    Some(Token::Word(w)) => w
+                        .parse::<usize>()
+                        .map_err(|_| parse_error(line, "bad register width"))?,
+                    _ => return Err(parse_error(line, "expected a register width")),
+                };
+                register::check_width(width).map_err(|e| parse_error(line, e.to_string()))?;
+                let (inputs, outputs) = parse_pins(&tokens[2..], line)?;
+                node.gates.push((
+                    GateNode::Register {
+                        kind,
+                        width,
+                        inputs,
+                        outputs,
+                    },
+                    line,
+                ));
+            }
+            other if DisplayKind::from_keyword(other).is_some() => {
+                let kind = DisplayKind::from_keyword(other).unwrap_or(DisplayKind::Led);
+                let number = |i: usize, radix: u32| match tokens.get(i) {
+                    Some(Token::Word(w)) => usize::from_str_radix(w, radix)
+                        .map_err(|_| parse_error(line, format!("bad {} parameter", other))),
+                    _ => Err(parse_error(
+                        line,
+                        format!("{} is missing a parameter", other),
+                    )),
+                };
+                let (mut color, mut columns, mut rows) = (display::DEFAULT_LED_COLOR, 1, 1);
+                let params = match kind {
+                    DisplayKind::Led => {
+                        let rgb = number(1, 16)?;
+                        color = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+                        1
+                    }
+                    DisplayKind::PixelMatrix => {
+                        (columns, rows) = (number(1, 10)?, number(2, 10)?);
+                        display::check_matrix_size(columns, rows)
+                            .map_err(|e| parse_error(line, e.to_string()))?;
+                        2
+                    }
+                    _ => 0,
+                };
+                let (inputs, outputs) = parse_pins(&tokens[1 + params..], line)?;
+                if !outputs.is_empty() {
+                    return Err(parse_error(line, "displays have no outputs"));
+                }
+                node.gates.push((
+                    GateNode::Display {
+                        kind,
+                        color,
+                        columns,
+                        rows,
+                        inputs,
+                    },
+                    line,
+                ));
+            }
+            other if SwitchKind::from_keyword(other).is_some() => {
+                let kind = SwitchKind::from_keyword(other).unwrap_or(SwitchKind::Toggle);
+                let number = || match tokens.get(1) {
+                    Some(Token::Word(w)) => w
+                        .parse::<usize>()
+                        .map_err(|_| parse_error(line, format!("bad {} parameter", other))),
+                    _ => Err(parse_error(
+                        line,
+                        format!("{} is missing a parameter", other),
+                    )),
+                };
+                let (mut switches, mut tie, mut params) = (1, 0, 0);
+                match kind {
+                    SwitchKind::DipSwitch => {
+                        switches = number()?;
+                        switch::check_switches(switches)
+                            .map_err(|e| parse_error(line, e.to_string()))?;
+                        params = 1;
+                    }
+                    SwitchKind::Constant => {
+                        tie = number()? as u64;
+                        if tie > 1 {
+                            return Err(parse_error(line, "a tie is either 0 or 1"));
+                        }
+                        params = 1;
+                    }
+                    _ => {}
+                }
+                let (inputs, outputs) = parse_pins(&tokens[1 + params..], line)?;
+                if !inputs.is_empty() {
+                    return Err(parse_error(line, "input devices have no inputs"));
+                }
+                node.gates.push((
+                    GateNode::Switch {
+                        kind,
+                        switches,
+                        tie,
+                        outputs,
+                    },
+                    line,
+                ));
+            }
+            "data" => {
+                let Some((GateNode::Memory { contents, .. }, _)) = node.gates.last_mut() else {
+                    return Err(parse_error(line, "data must follow a ram or rom line"));
+                };
+                for token in &tokens[1..] {
+                    let Token::Word(w) = token else {
+                        return Err(parse_error(line, "unexpected name"));
+                    };
+                    let word = u64::from_str_radix(w, 16)
+                        .map_err(|_| parse_error(line, format!("{} is not a hex number", w)))?;
+                    contents.push(word);
+                }
+            }
+            "chip" => node
+                .gates
+                .push((GateNode::Chip(parse_chip(lines, &tokens, line)?), line)),
+            "wire" => {
+                let (Some(Token::Word(a)), Some(Token::Word(b)), None) =
+                    (tokens.get(1), tokens.get(2), tokens.get(3))
+                else {
+                    return Err(parse_error(line, "expected: wire <from> <to>"));
+                };
+                let a = a.parse().map_err(|_| parse_error(line, "bad pin number"))?;
+                let b = b.parse().map_err(|_| parse_error(line, "bad pin number"))?;
+                node.wires.push((a, b, line));
+            }
+            other => return Err(parse_error(line, format!("unknown keyword {}", other))),
+        }
+    }
+}
+
+fn bind(
+    labels: &[usize],
+    pins: &[PinId],
+    map: &mut HashMap<usize, PinId>,
+    line: usize,
+) -> Result<(), LgsimError> {
+    if labels.len() != pins.len() {
+        return Err(parse_error(
+            line,
+            format!("expected {} pins, found {}", pins.len(), labels.len()),
+        ));
+    }
+    for (&label, &pin) in labels.iter().zip(pins) {
+        label_pin(map, label, pin, line)?;
+    }
+    Ok(())
+}
+
+/// Gives `label` to `pin`, refusing a label that already stands for a pin.
+fn label_pin(
+    map: &mut HashMap<usize, PinId>,
+    label: usize,
+    pin: PinId,
+    line: usize,
+) -> Result<(), LgsimError> {
+    if map.insert(label, pin).is_some() {
+        return Err(parse_error(line, format!("pin {} is used twice", label)));
+    }
+    Ok(())
+}
+
+/// Builds a chip from `node` with its own ID space, the way a template is
+/// stored. `rename` maps names of nested chips to the names they should get.
+/// `line` is only used in error messages.
+pub fn build_chip(
+    node: &ChipNode,
+    rename: &dyn Fn(&str) -> String,
+    line: usize,
+) -> Result<Chip, LgsimError> {
+    build_labeled(node, rename, line).map(|built| built.chip)
+}
+
+/// A chip built from a block, with what its labels turned into.
+pub struct Built {
+    pub chip: Chip,
+    /// The block's gates, in the order they are listed.
+    pub gates: Vec<GateId>,
+    /// The pin each label of the block stands for.
+    pub pins: HashMap<usize, PinId>,
+}
+
+/// `build_chip`, also returning the IDs the block's gates and pin labels
+/// were given.
+pub fn build_labeled(
+    node: &ChipNode,
+    rename: &dyn Fn(&str) -> String,
+    line: usize,
+) -> Result<Built, LgsimError> {
+    let mut chip = Chip::new(ChipId(0));
+    let mut gates = Vec::with_capacity(node.gates.len());
+    chip.name = node.name.as_deref().map(rename);
+    let mut map: HashMap<usize, PinId> = HashMap::new();
+    for &label in &node.inputs {
+        let pin = chip.add_shell_pin(crate::pin::PinType::ChipInput);
+        label_pin(&mut map, label, pin, line)?;
+    }
+    for &label in &node.outputs {
+        let pin = chip.add_shell_pin(crate::pin::PinType::ChipOutput);
+        label_pin(&mut map, label, pin, line)?;
+    }
+    for &(ref gate, line) in &node.gates {
+        let (labels_in, labels_out, gate) = match gate {
+            GateNode::And { inputs, outputs } => (
+                inputs,
+                outputs,
+                Gate::new(GateType::And, vec![], &mut chip.ids),
+            ),
+            GateNode::Not { inputs, outputs } => (
+                inputs,
+                outputs,
+                Gate::new(GateType::Not, vec![], &mut chip.ids),
+            ),
+            GateNode::Chip(inner) => {
+                let template = build_chip(inner, rename, line)?;
+                let copy = template.deep_copy(&mut chip.ids)?;
+                (&inner.inputs, &inner.outputs, Gate::Chip(copy))
+            }
+            GateNode::Memory {
+                kind,
+                address_bits,
+                data_bits,
+                inputs,
+                outputs,
+                contents,
+            } => {
+                let id = chip.ids.gate();
+                let mut m = MemoryGate::new(*kind, *address_bits, *data_bits, id, &mut chip.ids);
+                m.load(contents)
+                    .map_err(|e| parse_error(line, e.to_string()))?;
+                (inputs, outputs, Gate::Memory(m))
+            }
+            GateNode::Register {
+                kind,
+                width,
+                inputs,
+                outputs,
+            } => {
+                let id = chip.ids.gate();
+                let r = RegisterGate::new(*kind, *width, id, &mut chip.ids);
+                (inputs, outputs, Gate::Register(r))
+            }
+            GateNode::Display {
+                kind,
+                color,
+                columns,
+                rows,
+                inputs,
+            } => {
+                let id = chip.ids.gate();
+                let mut d = DisplayGate::new(*kind, *columns, *rows, id, &mut chip.ids);
+                d.color = *color;
+                (inputs, &NO_PINS, Gate::Display(d))
+            }
+            GateNode::Switch {
+                kind,
+                switches,
+                tie,
+                outputs,
+            } => {
+                let id = chip.ids.gate();
+                let mut w = SwitchGate::new(*kind, *switches, id, &mut chip.ids);
+                w.state = *tie;
+                w.evaluate();
+                (&NO_PINS, outputs, Gate::Switch(w))
+            }
+            GateNode::Input { outputs } => (
+                &NO_PINS,
+                outputs,
+                Gate::new(GateType::Source, vec![], &mut chip.ids),
+            ),
+            GateNode::Output { inputs } => (
+                inputs,
+                &NO_PINS,
+                Gate::new(GateType::Output, vec![], &mut chip.ids),
+            ),
+            GateNode::Keyboard { inputs, outputs } => {
+                let id = chip.ids.gate();
+                let k = KeyboardGate::new(id, &mut chip.ids);
+                (inputs, outputs, Gate::Keyboard(k))
+            }
+            GateNode::Terminal { inputs } => {
+                let id = chip.ids.gate();
+                let t = TerminalGate::new(id, &mut chip.ids);
+                (inputs, &NO_PINS, Gate::Terminal(t))
+            }
+            GateNode::Cpu { inputs, outputs } => {
+                let id = chip.ids.gate();
+                let c = CpuGate::new(id, &mut chip.ids);
+                (inputs, outputs, Gate::Cpu(c))
+            }
+        };
+        bind(labels_in, gate.input(), &mut map, line)?;
+        bind(labels_out, gate.output(), &mut map, line)?;
+        gates.push(chip.add_gate(gate));
+    }
+    for &(a, b, line) in &node.wires {
+        let (Some(&from), Some(&to)) = (map.get(&a), map.get(&b)) else {
+            return Err(parse_error(
+                line,
+                format!("wire {} {} uses an unknown pin", a, b),
+            ));
+        };
+        chip.connect_pins(from, to)
+            .map_err(|e| parse_error(line, e.to_string()))?;
+    }
+    Ok(Built {
+        chip,
+        gates,
+        pins: map,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the chip block in `text`.
+    fn read(text: &str) -> Result<Chip, LgsimError> {
+        let mut lines = Lines::new(text);
+        let (line, header) = lines.next_tokens()?.unwrap();
+        let node = parse_chip(&mut lines, &header, line)?;
+        build_chip(&node, &|name| name.to_string(), line)
+    }
+
+    fn write(chip: &Chip) -> String {
+        let mut out = String::new();
+        write_chip(&mut out, chip, 0).unwrap();
+        out
+    }
+
+    /// One gate of every kind a template can hold, a nested chip and a few
+    /// wires.
+    fn every_kind() -> Chip {
+        let mut chip = Chip::new(ChipId(0));
+        chip.name = Some("Everything".to_string());
+        let input = chip.add_shell_pin(crate::pin::PinType::ChipInput);
+        let output = chip.add_shell_pin(crate::pin::PinType::ChipOutput);
+        let kinds = [
+            GateType::And,
+            GateType::Not,
+            GateType::Ram,
+            GateType::Rom,
+            GateType::Register,
+            GateType::Counter,
+            GateType::ShiftSipo,
+            GateType::ShiftPiso,
+            GateType::Led,
+            GateType::SevenSegment,
+            GateType::SixteenSegment,
+            GateType::HexDigit,
+            GateType::PixelMatrix,
+            GateType::PushButton,
+            GateType::Toggle,
+            GateType::DipSwitch,
+            GateType::HexKeypad,
+            GateType::Constant,
+            GateType::Keyboard,
+            GateType::Terminal,
+            GateType::Cpu,
+        ];
+        for kind in kinds {
+            let mut gate = Gate::new(kind, vec![], &mut chip.ids);
+            match &mut gate {
+                Gate::Memory(m) if m.kind == MemoryKind::Rom => {
+                    m.load(&[0xa, 0, 0x3c]).unwrap();
+                }
+                Gate::Switch(w) if w.kind == SwitchKind::Constant => w.state = 1,
+                Gate::Display(d) if d.kind == DisplayKind::Led => d.color = [0x12, 0xab, 0xff],
+                _ => {}
+            }
+            chip.add_gate(gate);
+        }
+        let nested = crate::circuit! {
+            inputs: [a, b];
+            let y = nand(a, b);
+            outputs: [y];
+        }
+        .unwrap();
+        let mut nested = nested.deep_copy(&mut chip.ids).unwrap();
+        nested.name = Some("NAND \"2\"".to_string());
+        let (nand_in, nand_out) = (nested.input[0], nested.output[0]);
+        chip.add_gate(Gate::Chip(nested));
+        let first = |kind: GateType| {
+            let gate = chip.gates.values().find(|g| g.gate_type() == kind).unwrap();
+            (gate.input()[0], gate.output()[0])
+        };
+        let (and_in, and_out) = first(GateType::And);
+        let (not_in, not_out) = first(GateType::Not);
+        for (src, dest) in [
+            (input, nand_in),
+            (nand_out, and_in),
+            (and_out, not_in),
+            (not_out, output),
+        ] {
+            chip.connect_pins(src, dest).unwrap();
+        }
+        chip
+    }
+
+    #[test]
+    fn round_trip_keeps_every_kind() {
+        let text = write(&every_kind());
+        let chip = read(&text).unwrap();
+        assert_eq!(write(&chip), text);
+        assert_eq!(chip.gates.len(), every_kind().gates.len());
+        assert_eq!(chip.wires().len(), 4);
+        let rom = chip.gates.values().find_map(|g| match g {
+            Gate::Memory(m) if m.kind == MemoryKind::Rom => Some(m),
+            _ => None,
+        });
+        assert_eq!(rom.unwrap().contents[..3], [0xa, 0, 0x3c]);
+        let nested = chip.gates.values().find_map(|g| match g {
+            Gate::Chip(c) => c.name.as_deref(),
+            _ => None,
+        });
+        assert_eq!(nested, Some("NAND \"2\""));
+    }
+
+    #[test]
+    fn board_inputs_and_outputs_round_trip() {
+        let text = "chip - in out\n  input in out 1\n  not in 2 out 3\n  output in 4 out\n  \
+                    wire 1 2\n  wire 3 4\nend\n";
+        let chip = read(text).unwrap();
+        let kinds: Vec<GateType> = {
+            let mut ids: Vec<&GateId> = chip.gates.keys().collect();
+            ids.sort();
+            ids.iter().map(|id| chip.gates[id].gate_type()).collect()
+        };
+        assert_eq!(kinds, [GateType::Source, GateType::Not, GateType::Output]);
+        assert_eq!(read(&write(&chip)).unwrap(), chip);
+    }
+
+    #[test]
+    fn tokenizer_handles_quotes_and_comments() {
+        let tokens = tokenize(r#"chip "a \"b\" \\ c" in 1 # note"#, 1).unwrap();
+        assert_eq!(
+            tokens,
+            [
+                Token::Word("chip".to_string()),
+                Token::Text("a \"b\" \\ c".to_string()),
+                Token::Word("in".to_string()),
+                Token::Word("1".to_string()),
+            ]
+        );
+        assert!(tokenize("chip \"open", 3).is_err());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let line_of = |text: &str| match read(text) {
+            Err(LgsimError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other.map(|c| write(&c))),
+        };
+        assert_eq!(line_of("chip - in 1 out\n  xor in 2 out 3\nend\n"), 2);
+        // An unfinished block is reported at its header.
+        assert_eq!(line_of("chip - in 1 out\n  not in 2 out 3\n"), 1);
+        assert_eq!(
+            line_of("chip - in 1 out\n  not in 2 out 3\n  wire 1 9\nend\n"),
+            3
+        );
+        // Pin counts are checked when the block is built, against the gate.
+        assert_eq!(line_of("chip - in 1 out\n  and in 2 out 3\nend\n"), 2);
+        assert_eq!(
+            line_of("chip - in out\n  chip - in 1 out\n    and in 1 2 3 out 4\n  end\nend\n"),
+            3
+        );
+        // A label can stand for only one pin.
+        assert_eq!(line_of("chip - in 1 1 out\nend\n"), 1);
+        assert_eq!(
+            line_of("chip - in 1 out\n  not in 2 out 3\n  not in 3 out 3\nend\n"),
+            3
+        );
+        assert_eq!(
+            line_of(
+                "chip - in out\n  rom 4 8 in 1 2 3 4 out 5 6 7 8 9 10 11 12\n  data 1 zz\nend\n"
+            ),
+            3
+        );
+    }
+}
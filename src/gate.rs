@@ -1,4 +1,11 @@
+use crate::cpu::CpuGate;
+use crate::display::{DEFAULT_MATRIX_SIZE, DisplayGate, DisplayKind};
+use crate::error::LgsimError;
+use crate::memory::{DEFAULT_ADDRESS_BITS, DEFAULT_DATA_BITS, MemoryGate, MemoryKind};
+use crate::peripheral::{KeyboardGate, TerminalGate};
 use crate::pin::*;
+use crate::register::{DEFAULT_REGISTER_BITS, RegisterGate, RegisterKind};
+use crate::switch::{DEFAULT_DIP_SWITCHES, SwitchGate, SwitchKind};
 use crate::types::*;
 use std::collections::HashMap;
 
@@ -9,6 +16,25 @@ pub enum GateType {
     Source,
     Output,
     Chip,
+    Ram,
+    Rom,
+    Register,
+    Counter,
+    ShiftSipo,
+    ShiftPiso,
+    Led,
+    SevenSegment,
+    SixteenSegment,
+    HexDigit,
+    PixelMatrix,
+    PushButton,
+    Toggle,
+    DipSwitch,
+    HexKeypad,
+    Constant,
+    Keyboard,
+    Terminal,
+    Cpu,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,28 +44,112 @@ pub enum Gate {
     Source(SourceGate),
     Output(OutputGate),
     Chip(crate::circuit::Chip),
+    Memory(MemoryGate),
+    Register(RegisterGate),
+    Display(DisplayGate),
+    Switch(SwitchGate),
+    Keyboard(KeyboardGate),
+    Terminal(TerminalGate),
+    Cpu(CpuGate),
 }
 
 impl Gate {
-    pub fn new(gate_type: GateType, _input: Vec<PinValue>) -> Self {
-        // FIX: Use global counter
-        let id = next_uuid();
+    pub fn new(gate_type: GateType, _input: Vec<PinValue>, ids: &mut IdAllocator) -> Self {
+        let id = ids.gate();
         match gate_type {
-            GateType::And => Gate::And(AndGate::new(id)),
-            GateType::Not => Gate::Not(NotGate::new(id)),
-            GateType::Source => Gate::Source(SourceGate::new(id)),
-            GateType::Output => Gate::Output(OutputGate::new(id)),
-            GateType::Chip => Gate::Chip(crate::circuit::Chip::new(id)),
+            GateType::And => Gate::And(AndGate::new(id, ids)),
+            GateType::Not => Gate::Not(NotGate::new(id, ids)),
+            GateType::Source => Gate::Source(SourceGate::new(id, ids)),
+            GateType::Output => Gate::Output(OutputGate::new(id, ids)),
+            GateType::Chip => Gate::Chip(crate::circuit::Chip::new(id.into())),
+            GateType::Ram | GateType::Rom => {
+                let kind = if gate_type == GateType::Ram {
+                    MemoryKind::Ram
+                } else {
+                    MemoryKind::Rom
+                };
+                Gate::Memory(MemoryGate::new(
+                    kind,
+                    DEFAULT_ADDRESS_BITS,
+                    DEFAULT_DATA_BITS,
+                    id,
+                    ids,
+                ))
+            }
+            GateType::Register => Gate::Register(RegisterGate::new(
+                RegisterKind::Register,
+                DEFAULT_REGISTER_BITS,
+                id,
+                ids,
+            )),
+            GateType::Counter => Gate::Register(RegisterGate::new(
+                RegisterKind::Counter,
+                DEFAULT_REGISTER_BITS,
+                id,
+                ids,
+            )),
+            GateType::ShiftSipo => Gate::Register(RegisterGate::new(
+                RegisterKind::ShiftSipo,
+                DEFAULT_REGISTER_BITS,
+                id,
+                ids,
+            )),
+            GateType::ShiftPiso => Gate::Register(RegisterGate::new(
+                RegisterKind::ShiftPiso,
+                DEFAULT_REGISTER_BITS,
+                id,
+                ids,
+            )),
+            GateType::Led
+            | GateType::SevenSegment
+            | GateType::SixteenSegment
+            | GateType::HexDigit
+            | GateType::PixelMatrix => {
+                let kind = match gate_type {
+                    GateType::Led => DisplayKind::Led,
+                    GateType::SevenSegment => DisplayKind::SevenSegment,
+                    GateType::SixteenSegment => DisplayKind::SixteenSegment,
+                    GateType::HexDigit => DisplayKind::HexDigit,
+                    _ => DisplayKind::PixelMatrix,
+                };
+                Gate::Display(DisplayGate::new(
+                    kind,
+                    DEFAULT_MATRIX_SIZE,
+                    DEFAULT_MATRIX_SIZE,
+                    id,
+                    ids,
+                ))
+            }
+            GateType::PushButton
+            | GateType::Toggle
+            | GateType::DipSwitch
+            | GateType::HexKeypad
+            | GateType::Constant => {
+                let kind = match gate_type {
+                    GateType::PushButton => SwitchKind::PushButton,
+                    GateType::Toggle => SwitchKind::Toggle,
+                    GateType::DipSwitch => SwitchKind::DipSwitch,
+                    GateType::HexKeypad => SwitchKind::HexKeypad,
+                    _ => SwitchKind::Constant,
+                };
+                Gate::Switch(SwitchGate::new(kind, DEFAULT_DIP_SWITCHES, id, ids))
+            }
+            GateType::Keyboard => Gate::Keyboard(KeyboardGate::new(id, ids)),
+            GateType::Terminal => Gate::Terminal(TerminalGate::new(id, ids)),
+            GateType::Cpu => Gate::Cpu(CpuGate::new(id, ids)),
         }
     }
 
-    pub fn clone_with_new_ids(&self, id_map: &mut HashMap<usize, usize>) -> Self {
-        // FIX: Use global counter
-        let new_id = next_uuid();
+    pub fn clone_with_new_ids(
+        &self,
+        id_map: &mut HashMap<PinId, PinId>,
+        ids: &mut IdAllocator,
+    ) -> Result<Self, LgsimError> {
+        let new_id = ids.gate();
 
-        match self {
+        Ok(match self {
             Gate::And(_) => {
-                let new_gate = AndGate::new(new_id);
+                let new_gate = AndGate::new(new_id, ids);
                 let old_inputs = self.input();
                 let new_inputs = new_gate.input.clone();
                 for (old, new) in old_inputs.iter().zip(new_inputs.iter()) {
@@ -53,7 +163,7 @@ impl Gate {
                 Gate::And(new_gate)
             }
             Gate::Not(_) => {
-                let new_gate = NotGate::new(new_id);
+                let new_gate = NotGate::new(new_id, ids);
                 let old_inputs = self.input();
                 let new_inputs = new_gate.input.clone();
                 for (old, new) in old_inputs.iter().zip(new_inputs.iter()) {
@@ -67,8 +177,8 @@ impl Gate {
                 Gate::Not(new_gate)
             }
             Gate::Chip(c) => {
-                let mut new_chip = c.deep_copy();
-                new_chip.id = new_id;
+                let mut new_chip = c.deep_copy(ids)?;
+                new_chip.id = new_id.into();
                 for (old, new) in c.input.iter().zip(new_chip.input.iter()) {
                     id_map.insert(*old, *new);
                 }
@@ -77,81 +187,249 @@ impl Gate {
                 }
                 Gate::Chip(new_chip)
             }
-            _ => panic!("Cannot clone Source/Output gates into a chip"),
+            Gate::Memory(m) => {
+                let mut new_gate =
+                    MemoryGate::new(m.kind, m.address_bits, m.data_bits, new_id, ids);
+                new_gate.contents = m.contents.clone();
+                let old_pins = m.input.iter().chain(&m.output);
+                let new_pins = new_gate.input.iter().chain(&new_gate.output);
+                for (old, new) in old_pins.zip(new_pins) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Memory(new_gate)
+            }
+            Gate::Register(r) => {
+                let mut new_gate = RegisterGate::new(r.kind, r.width, new_id, ids);
+                new_gate.value = r.value;
+                let old_pins = r.input.iter().chain(&r.output);
+                let new_pins = new_gate.input.iter().chain(&new_gate.output);
+                for (old, new) in old_pins.zip(new_pins) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Register(new_gate)
+            }
+            Gate::Display(d) => {
+                let mut new_gate = DisplayGate::new(d.kind, d.columns, d.rows, new_id, ids);
+                new_gate.color = d.color;
+                new_gate.pixels = d.pixels.clone();
+                for (old, new) in d.input.iter().zip(&new_gate.input) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Display(new_gate)
+            }
+            Gate::Switch(w) => {
+                let mut new_gate = SwitchGate::new(w.kind, w.output.len(), new_id, ids);
+                new_gate.state = w.state;
+                new_gate.evaluate();
+                for (old, new) in w.output.iter().zip(&new_gate.output) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Switch(new_gate)
+            }
+            Gate::Keyboard(k) => {
+                let mut new_gate = KeyboardGate::new(new_id, ids);
+                new_gate.buffer = k.buffer.clone();
+                let old_pins = k.input.iter().chain(&k.output);
+                let new_pins = new_gate.input.iter().chain(&new_gate.output);
+                for (old, new) in old_pins.zip(new_pins) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Keyboard(new_gate)
+            }
+            Gate::Terminal(t) => {
+                let mut new_gate = TerminalGate::new(new_id, ids);
+                new_gate.text = t.text.clone();
+                for (old, new) in t.input.iter().zip(&new_gate.input) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Terminal(new_gate)
+            }
+            Gate::Cpu(c) => {
+                let mut new_gate = CpuGate::new(new_id, ids);
+                new_gate.pc = c.pc;
+                new_gate.a = c.a;
+                new_gate.zero = c.zero;
+                new_gate.carry = c.carry;
+                new_gate.halted = c.halted;
+                let old_pins = c.input.iter().chain(&c.output);
+                let new_pins = new_gate.input.iter().chain(&new_gate.output);
+                for (old, new) in old_pins.zip(new_pins) {
+                    id_map.insert(*old, *new);
+                }
+                Gate::Cpu(new_gate)
+            }
+            Gate::Source(_) | Gate::Output(_) => {
+                return Err(LgsimError::UnsupportedGate(self.gate_type()));
+            }
+        })
+    }
+
+    pub fn gate_type(&self) -> GateType {
+        match self {
+            Gate::And(_) => GateType::And,
+            Gate::Not(_) => GateType::Not,
+            Gate::Source(_) => GateType::Source,
+            Gate::Output(_) => GateType::Output,
+            Gate::Chip(_) => GateType::Chip,
+            Gate::Memory(m) => match m.kind {
+                MemoryKind::Ram => GateType::Ram,
+                MemoryKind::Rom => GateType::Rom,
+            },
+            Gate::Register(r) => match r.kind {
+                RegisterKind::Register => GateType::Register,
+                RegisterKind::Counter => GateType::Counter,
+                RegisterKind::ShiftSipo => GateType::ShiftSipo,
+                RegisterKind::ShiftPiso => GateType::ShiftPiso,
+            },
+            Gate::Display(d) => match d.kind {
+                DisplayKind::Led => GateType::Led,
+                DisplayKind::SevenSegment => GateType::SevenSegment,
+                DisplayKind::SixteenSegment => GateType::SixteenSegment,
+                DisplayKind::HexDigit => GateType::HexDigit,
+                DisplayKind::PixelMatrix => GateType::PixelMatrix,
+            },
+            Gate::Switch(w) => match w.kind {
+                SwitchKind::PushButton => GateType::PushButton,
+                SwitchKind::Toggle => GateType::Toggle,
+                SwitchKind::DipSwitch => GateType::DipSwitch,
+                SwitchKind::HexKeypad => GateType::HexKeypad,
+                SwitchKind::Constant => GateType::Constant,
+            },
+            Gate::Keyboard(_) => GateType::Keyboard,
+            Gate::Terminal(_) => GateType::Terminal,
+            Gate::Cpu(_) => GateType::Cpu,
         }
     }
 
-    pub fn evaluate(&mut self) -> bool {
+    pub fn evaluate(&mut self) -> Result<bool, LgsimError> {
         match self {
-            Gate::And(g) => g.evaluate(),
-            Gate::Not(g) => g.evaluate(),
-            Gate::Source(_) => false,
-            Gate::Output(g) => g.evaluate(),
+            Gate::And(g) => Ok(g.evaluate()),
+            Gate::Not(g) => Ok(g.evaluate()),
+            Gate::Source(_) => Ok(false),
+            Gate::Output(g) => Ok(g.evaluate()),
             Gate::Chip(c) => c.simulate(),
+            Gate::Memory(m) => Ok(m.evaluate()),
+            Gate::Register(r) => Ok(r.evaluate()),
+            Gate::Display(d) => Ok(d.evaluate()),
+            Gate::Switch(w) => Ok(w.evaluate()),
+            Gate::Keyboard(k) => Ok(k.evaluate()),
+            Gate::Terminal(t) => Ok(t.evaluate()),
+            Gate::Cpu(c) => Ok(c.evaluate()),
+        }
+    }
+
+    /// Lets a clocked gate act on a rising edge of its clock input without
+    /// driving its outputs yet; see `Chip::clock`. Other gates ignore this.
+    pub fn clock(&mut self) -> Result<(), LgsimError> {
+        match self {
+            Gate::Chip(c) => return c.clock(),
+            Gate::Memory(m) => m.clock(),
+            Gate::Register(r) => r.clock(),
+            Gate::Display(d) => d.clock(),
+            Gate::Keyboard(k) => k.clock(),
+            Gate::Terminal(t) => t.clock(),
+            Gate::Cpu(c) => c.clock(),
+            _ => {}
         }
+        Ok(())
     }
 
-    pub fn set_pin(&mut self, id: &usize, val: Option<PinValue>) {
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
         match self {
             Gate::And(g) => g.set_pin(id, val),
             Gate::Not(g) => g.set_pin(id, val),
             Gate::Source(g) => g.set_pin(id, val),
             Gate::Output(g) => g.set_pin(id, val),
             Gate::Chip(c) => c.set_pin(id, val),
+            Gate::Memory(m) => m.set_pin(id, val),
+            Gate::Register(r) => r.set_pin(id, val),
+            Gate::Display(d) => d.set_pin(id, val),
+            Gate::Switch(w) => w.set_pin(id, val),
+            Gate::Keyboard(k) => k.set_pin(id, val),
+            Gate::Terminal(t) => t.set_pin(id, val),
+            Gate::Cpu(c) => c.set_pin(id, val),
         }
     }
 
-    pub fn pins(&self) -> &HashMap<usize, Pin> {
+    pub fn pins(&self) -> &HashMap<PinId, Pin> {
         match self {
             Gate::And(g) => &g.pins,
             Gate::Not(g) => &g.pins,
             Gate::Source(g) => &g.pins,
             Gate::Output(g) => &g.pins,
             Gate::Chip(c) => &c.pins,
+            Gate::Memory(m) => &m.pins,
+            Gate::Register(r) => &r.pins,
+            Gate::Display(d) => &d.pins,
+            Gate::Switch(w) => &w.pins,
+            Gate::Keyboard(k) => &k.pins,
+            Gate::Terminal(t) => &t.pins,
+            Gate::Cpu(c) => &c.pins,
         }
     }
 
-    pub fn id(&self) -> usize {
+    pub fn id(&self) -> GateId {
         match self {
             Gate::And(g) => g.id,
             Gate::Not(g) => g.id,
             Gate::Source(g) => g.id,
             Gate::Output(g) => g.id,
-            Gate::Chip(c) => c.id,
+            Gate::Chip(c) => c.id.into(),
+            Gate::Memory(m) => m.id,
+            Gate::Register(r) => r.id,
+            Gate::Display(d) => d.id,
+            Gate::Switch(w) => w.id,
+            Gate::Keyboard(k) => k.id,
+            Gate::Terminal(t) => t.id,
+            Gate::Cpu(c) => c.id,
         }
     }
 
-    pub fn input(&self) -> &[usize] {
+    pub fn input(&self) -> &[PinId] {
         match self {
             Gate::And(g) => &g.input,
             Gate::Not(g) => &g.input,
             Gate::Source(_) => &[],
             Gate::Output(g) => &g.input,
             Gate::Chip(c) => &c.input,
+            Gate::Memory(m) => &m.input,
+            Gate::Register(r) => &r.input,
+            Gate::Display(d) => &d.input,
+            Gate::Switch(_) => &[],
+            Gate::Keyboard(k) => &k.input,
+            Gate::Terminal(t) => &t.input,
+            Gate::Cpu(c) => &c.input,
         }
     }
 
-    pub fn output(&self) -> &[usize] {
+    pub fn output(&self) -> &[PinId] {
         match self {
             Gate::And(g) => &g.output,
             Gate::Not(g) => &g.output,
             Gate::Source(g) => &g.output,
             Gate::Output(_) => &[],
             Gate::Chip(c) => &c.output,
+            Gate::Memory(m) => &m.output,
+            Gate::Register(r) => &r.output,
+            Gate::Display(_) => &[],
+            Gate::Switch(w) => &w.output,
+            Gate::Keyboard(k) => &k.output,
+            Gate::Terminal(_) => &[],
+            Gate::Cpu(c) => &c.output,
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceGate {
-    pub id: usize,
-    pub pins: HashMap<usize, Pin>,
-    pub output: Vec<usize>,
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    pub output: Vec<PinId>,
 }
 
 impl SourceGate {
-    pub fn new(id: usize) -> Self {
-        let pin = Pin::new(PinType::GateOutput, id, 0);
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let pin = Pin::new(PinType::GateOutput, id, 0, ids);
         let mut pins = HashMap::new();
         pins.insert(pin.id, pin);
         Self {
@@ -160,7 +438,7 @@ impl SourceGate {
             output: vec![pin.id],
         }
     }
-    pub fn set_pin(&mut self, id: &usize, val: Option<PinValue>) {
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
         if let Some(p) = self.pins.get_mut(id) {
             p.val = val;
         }
@@ -169,14 +447,14 @@ impl SourceGate {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct OutputGate {
-    pub id: usize,
-    pub pins: HashMap<usize, Pin>,
-    pub input: Vec<usize>,
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
 }
 
 impl OutputGate {
-    pub fn new(id: usize) -> Self {
-        let pin = Pin::new(PinType::GateInput, id, 0);
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let pin = Pin::new(PinType::GateInput, id, 0, ids);
         let mut pins = HashMap::new();
         pins.insert(pin.id, pin);
         Self {
@@ -192,7 +470,7 @@ impl OutputGate {
             .unwrap_or(0)
             == 1
     }
-    pub fn set_pin(&mut self, id: &usize, val: Option<PinValue>) {
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
         if let Some(p) = self.pins.get_mut(id) {
             p.val = val;
         }
@@ -201,17 +479,17 @@ impl OutputGate {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AndGate {
-    pub id: usize,
-    pub pins: HashMap<usize, Pin>,
-    pub input: Vec<usize>,
-    pub output: Vec<usize>,
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
+    pub output: Vec<PinId>,
 }
 
 impl AndGate {
-    pub fn new(id: usize) -> Self {
-        let p1 = Pin::new(PinType::GateInput, id, 0);
-        let p2 = Pin::new(PinType::GateInput, id, 0);
-        let out = Pin::new(PinType::GateOutput, id, 42);
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let p1 = Pin::new(PinType::GateInput, id, 0, ids);
+        let p2 = Pin::new(PinType::GateInput, id, 0, ids);
+        let out = Pin::new(PinType::GateOutput, id, 42, ids);
         let mut pins = HashMap::new();
         pins.insert(p1.id, p1);
         pins.insert(p2.id, p2);
@@ -240,7 +518,7 @@ impl AndGate {
         }
         res
     }
-    pub fn set_pin(&mut self, id: &usize, val: Option<PinValue>) {
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
         if let Some(p) = self.pins.get_mut(id) {
             p.val = val;
         }
@@ -249,16 +527,16 @@ impl AndGate {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct NotGate {
-    pub id: usize,
-    pub pins: HashMap<usize, Pin>,
-    pub input: Vec<usize>,
-    pub output: Vec<usize>,
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
+    pub output: Vec<PinId>,
 }
 
 impl NotGate {
-    pub fn new(id: usize) -> Self {
-        let p1 = Pin::new(PinType::GateInput, id, 0);
-        let out = Pin::new(PinType::GateOutput, id, 42);
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let p1 = Pin::new(PinType::GateInput, id, 0, ids);
+        let out = Pin::new(PinType::GateOutput, id, 42, ids);
         let mut pins = HashMap::new();
         pins.insert(p1.id, p1);
         pins.insert(out.id, out);
@@ -281,7 +559,7 @@ impl NotGate {
         }
         res
     }
-    pub fn set_pin(&mut self, id: &usize, val: Option<PinValue>) {
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
         if let Some(p) = self.pins.get_mut(id) {
             p.val = val;
         }
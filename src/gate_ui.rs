@@ -1,40 +1,106 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::atpg::{self, Atpg};
+use crate::camera::{Camera, GRID_SIZE, snap_to_grid};
 use crate::circuit::Chip;
+use crate::cpu;
+use crate::debugger::{Breakpoint, Debugger, StepMode};
+use crate::display::{self, DisplayGate, DisplayKind};
+use crate::edit;
+use crate::error::LgsimError;
+use crate::fault::{self, FaultReport, Ports};
 use crate::gate::{Gate, GateType};
-use crate::pin::next_uuid;
+use crate::library::{ChipLibrary, LibrarySource};
+use crate::memory::{self, MemoryFormat, MemoryGate, MemoryKind};
+use crate::probe::{Probe, ProbePath, Radix, WatchList, parse_value};
+use crate::project::{self, Project};
+use crate::register::{self, RegisterGate, RegisterKind};
+use crate::routing;
+use crate::runner;
+use crate::stats::{self, CostTable, Stats};
+use crate::stdlib::Component;
+use crate::switch::{self, SwitchGate, SwitchKind};
+use crate::timing::{self, Delays, Timing};
+use crate::types::{ChipId, GateId, PinId, PinValue};
 use eframe::egui::{Color32, Pos2, Rect, Sense, Stroke, Ui, Vec2};
 
+/// How a pin would react to the wire currently being dragged onto it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropTarget {
+    Valid,
+    /// Legal, but replaces the pin's existing driver.
+    NeedsConfirm,
+    Invalid,
+}
+
+impl DropTarget {
+    pub fn classify(chip: &Chip, from: PinId, to: PinId) -> Self {
+        match chip.check_connection(from, to) {
+            Ok(()) => DropTarget::Valid,
+            Err(LgsimError::DuplicateDriver { .. }) => DropTarget::NeedsConfirm,
+            Err(_) => DropTarget::Invalid,
+        }
+    }
+
+    fn color(self) -> Color32 {
+        match self {
+            DropTarget::Valid => Color32::YELLOW,
+            DropTarget::NeedsConfirm => Color32::from_rgb(255, 140, 0),
+            DropTarget::Invalid => Color32::RED,
+        }
+    }
+}
+
+/// A pin as drawn on the canvas, in screen coordinates.
+pub struct PinDot {
+    pub id: PinId,
+    pub pos: Pos2,
+    pub val: u8,
+    pub is_input: bool,
+    pub radius: f32,
+}
+
 pub fn draw_connection_dot(
     ui: &mut Ui,
-    pos: Pos2,
-    pin_id: usize,
-    val: u8,
-    is_input_pin: bool,
-    dragging_wire_from: &mut Option<(usize, Pos2)>,
-) -> Option<usize> {
-    let radius = 6.0;
+    pin: PinDot,
+    drop: Option<DropTarget>,
+    dragging_wire_from: &mut Option<(PinId, Pos2)>,
+) -> Option<PinId> {
+    let PinDot {
+        id: pin_id,
+        pos,
+        val,
+        is_input: is_input_pin,
+        radius,
+    } = pin;
     let color = if val == 1 {
         Color32::GREEN
     } else {
         Color32::from_rgb(50, 0, 0)
     };
-    let hit_rect = Rect::from_center_size(pos, Vec2::splat(20.0));
+    let hit_rect = Rect::from_center_size(pos, Vec2::splat((radius * 3.0).max(12.0)));
     let interact = ui.interact(hit_rect, ui.id().with("pin").with(pin_id), Sense::drag());
 
     if interact.drag_started() && !is_input_pin {
         *dragging_wire_from = Some((pin_id, pos));
     }
 
-    if let Some(_) = dragging_wire_from {
-        if is_input_pin
-            && hit_rect.contains(ui.input(|i| i.pointer.hover_pos().unwrap_or(Pos2::ZERO)))
-        {
-            ui.painter()
-                .circle_stroke(pos, radius + 4.0, Stroke::new(2.0, Color32::YELLOW));
-            if ui.input(|i| i.pointer.any_released()) {
-                return Some(pin_id);
-            }
+    if let Some(drop) = drop
+        && dragging_wire_from.is_some()
+        && hit_rect.contains(ui.input(|i| i.pointer.hover_pos().unwrap_or(Pos2::ZERO)))
+    {
+        let stroke = Stroke::new(2.0, drop.color());
+        ui.painter().circle_stroke(pos, radius + 4.0, stroke);
+        if drop == DropTarget::Invalid {
+            let d = Vec2::splat(radius + 2.0);
+            ui.painter().line_segment([pos - d, pos + d], stroke);
+            ui.painter().line_segment(
+                [pos + Vec2::new(d.x, -d.y), pos + Vec2::new(-d.x, d.y)],
+                stroke,
+            );
+        }
+        if ui.input(|i| i.pointer.any_released()) {
+            return Some(pin_id);
         }
     }
 
@@ -46,33 +112,591 @@ pub fn draw_connection_dot(
     None
 }
 
+pub struct Toast {
+    pub text: String,
+    pub shown_at: Option<f64>,
+}
+
+const TOAST_SECONDS: f64 = 4.0;
+/// Most board inputs the fault window will enumerate every vector for.
+const MAX_EXHAUSTIVE_INPUTS: usize = 12;
+
+/// A library operation waiting for the user to type a name or folder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LibraryEdit {
+    Rename { name: String, new_name: String },
+    Duplicate { name: String, new_name: String },
+    Move { name: String, folder: String },
+}
+
+// Board geometry, in world units (see `Camera`).
+pub const NODE_SIZE: Vec2 = Vec2::new(80.0, 50.0);
+pub const PIN_SPACING: f32 = 15.0;
+/// Distance from a global input/output body to its pin.
+pub const IO_PIN_OFFSET: f32 = 25.0;
+const IO_SPACING: f32 = 40.0;
+const IO_INPUT_X: f32 = 20.0;
+const IO_OUTPUT_X: f32 = 740.0;
+const IO_TOP: f32 = 100.0;
+
 #[derive(Clone)]
 pub struct VisualNode {
-    pub gate_id: usize,
+    pub gate_id: GateId,
     pub pos: Pos2,
-    pub inputs: Vec<usize>,
-    pub outputs: Vec<usize>,
+    pub inputs: Vec<PinId>,
+    pub outputs: Vec<PinId>,
+    pub label: String,
+}
+
+/// Text shown on a gate's body.
+pub fn gate_label(gate: &Gate) -> String {
+    match gate {
+        Gate::And(_) => "AND".to_string(),
+        Gate::Not(_) => "NOT".to_string(),
+        Gate::Chip(c) => c.name.clone().unwrap_or_else(|| "CHIP".to_string()),
+        Gate::Memory(m) => {
+            let kind = match m.kind {
+                MemoryKind::Ram => "RAM",
+                MemoryKind::Rom => "ROM",
+            };
+            format!("{} {}x{}", kind, m.words(), m.data_bits)
+        }
+        Gate::Register(r) => {
+            let kind = match r.kind {
+                RegisterKind::Register => "REG",
+                RegisterKind::Counter => "CNT",
+                RegisterKind::ShiftSipo => "SIPO",
+                RegisterKind::ShiftPiso => "PISO",
+            };
+            format!("{} {}", kind, r.width)
+        }
+        Gate::Display(d) => d.kind.label().to_string(),
+        Gate::Keyboard(_) => "KBD".to_string(),
+        Gate::Terminal(_) => "TTY".to_string(),
+        Gate::Cpu(_) => "CPU".to_string(),
+        Gate::Switch(w) => match w.kind {
+            SwitchKind::Constant => w.state.to_string(),
+            kind => kind.label().to_string(),
+        },
+        _ => "UNK".to_string(),
+    }
+}
+
+/// A segment stroke from one point of a 1x2 digit box to another.
+type SegmentStroke = ((f32, f32), (f32, f32));
+
+/// Segment strokes in input order.
+const SEVEN_SEGMENTS: [SegmentStroke; 7] = [
+    ((0.0, 0.0), (1.0, 0.0)),
+    ((1.0, 0.0), (1.0, 1.0)),
+    ((1.0, 1.0), (1.0, 2.0)),
+    ((0.0, 2.0), (1.0, 2.0)),
+    ((0.0, 1.0), (0.0, 2.0)),
+    ((0.0, 0.0), (0.0, 1.0)),
+    ((0.0, 1.0), (1.0, 1.0)),
+];
+
+const SIXTEEN_SEGMENTS: [SegmentStroke; 16] = [
+    ((0.0, 0.0), (0.5, 0.0)),
+    ((0.5, 0.0), (1.0, 0.0)),
+    ((1.0, 0.0), (1.0, 1.0)),
+    ((1.0, 1.0), (1.0, 2.0)),
+    ((0.0, 2.0), (0.5, 2.0)),
+    ((0.5, 2.0), (1.0, 2.0)),
+    ((0.0, 1.0), (0.0, 2.0)),
+    ((0.0, 0.0), (0.0, 1.0)),
+    ((0.0, 1.0), (0.5, 1.0)),
+    ((0.5, 1.0), (1.0, 1.0)),
+    ((0.0, 0.0), (0.5, 1.0)),
+    ((0.5, 0.0), (0.5, 1.0)),
+    ((1.0, 0.0), (0.5, 1.0)),
+    ((0.0, 2.0), (0.5, 1.0)),
+    ((0.5, 1.0), (0.5, 2.0)),
+    ((1.0, 2.0), (0.5, 1.0)),
+];
+
+const SEGMENT_OFF: Color32 = Color32::from_gray(45);
+
+fn dim(color: Color32) -> Color32 {
+    Color32::from_rgb(color.r() / 4, color.g() / 4, color.b() / 4)
+}
+
+/// Draws a display device inside `rect`.
+fn draw_display(painter: &eframe::egui::Painter, rect: Rect, d: &DisplayGate) {
+    let [r, g, b] = d.color;
+    let lit = Color32::from_rgb(r, g, b);
+    let segments = d.segments();
+    let strokes: &[SegmentStroke] = match d.kind {
+        DisplayKind::Led => {
+            let color = if segments & 1 == 1 { lit } else { dim(lit) };
+            let radius = rect.width().min(rect.height()) / 2.0;
+            painter.circle_filled(rect.center(), radius, color);
+            return;
+        }
+        DisplayKind::PixelMatrix => {
+            let cell = (rect.width() / d.columns as f32).min(rect.height() / d.rows as f32);
+            let origin =
+                rect.center() - Vec2::new(d.columns as f32 * cell, d.rows as f32 * cell) / 2.0;
+            for row in 0..d.rows {
+                for column in 0..d.columns {
+                    let center = origin + Vec2::new(column as f32 + 0.5, row as f32 + 0.5) * cell;
+                    let color = if d.pixel(column, row) {
+                        lit
+                    } else {
+                        SEGMENT_OFF
+                    };
+                    painter.circle_filled(center, cell * 0.4, color);
+                }
+            }
+            return;
+        }
+        DisplayKind::SixteenSegment => &SIXTEEN_SEGMENTS,
+        DisplayKind::SevenSegment | DisplayKind::HexDigit => &SEVEN_SEGMENTS,
+    };
+    // Fit the 1x2 digit box, leaving room on the right for the point.
+    let unit = (rect.width() / 1.5).min(rect.height() / 2.0);
+    let origin = rect.center() - Vec2::new(0.6 * unit, unit);
+    let at = |(x, y): (f32, f32)| origin + Vec2::new(x, y) * unit;
+    for (k, &(from, to)) in strokes.iter().enumerate() {
+        let (from, to) = (at(from), at(to));
+        // Leave a small gap where segments meet.
+        let gap = (to - from) * 0.1;
+        let color = if segments >> k & 1 == 1 {
+            lit
+        } else {
+            SEGMENT_OFF
+        };
+        painter.line_segment([from + gap, to - gap], Stroke::new(unit * 0.12, color));
+    }
+    if d.kind != DisplayKind::HexDigit {
+        let point = if segments >> strokes.len() & 1 == 1 {
+            lit
+        } else {
+            SEGMENT_OFF
+        };
+        painter.circle_filled(at((1.25, 2.0)), unit * 0.08, point);
+    }
+}
+
+fn is_rom(gate: &Gate) -> bool {
+    matches!(gate, Gate::Memory(m) if m.kind == MemoryKind::Rom)
+}
+
+/// `gate`, or the first gate nested in it, that matches `pred`.
+fn find_gate<'a>(gate: &'a Gate, pred: &dyn Fn(&Gate) -> bool) -> Option<&'a Gate> {
+    if pred(gate) {
+        return Some(gate);
+    }
+    match gate {
+        Gate::Chip(c) => {
+            let mut ids: Vec<&GateId> = c.gates.keys().collect();
+            ids.sort();
+            ids.into_iter().find_map(|id| find_gate(&c.gates[id], pred))
+        }
+        _ => None,
+    }
+}
+
+fn find_gate_mut<'a>(gate: &'a mut Gate, pred: &dyn Fn(&Gate) -> bool) -> Option<&'a mut Gate> {
+    if pred(gate) {
+        return Some(gate);
+    }
+    match gate {
+        Gate::Chip(c) => {
+            let mut ids: Vec<GateId> = c.gates.keys().copied().collect();
+            ids.sort();
+            let id = ids
+                .into_iter()
+                .find(|id| find_gate(&c.gates[id], pred).is_some())?;
+            find_gate_mut(c.gates.get_mut(&id)?, pred)
+        }
+        _ => None,
+    }
+}
+
+/// Collects the path and a label for every RAM and ROM in `chip`, at any
+/// depth, in ID order. `prefix` is the path to `chip` itself.
+fn memory_paths(chip: &Chip, prefix: &mut Vec<GateId>, out: &mut Vec<(Vec<GateId>, String)>) {
+    let mut ids: Vec<&GateId> = chip.gates.keys().collect();
+    ids.sort();
+    for id in ids {
+        prefix.push(*id);
+        match &chip.gates[id] {
+            Gate::Memory(m) => {
+                let kind = match m.kind {
+                    MemoryKind::Ram => "RAM",
+                    MemoryKind::Rom => "ROM",
+                };
+                let path: Vec<String> = prefix.iter().map(|g| g.0.to_string()).collect();
+                let label = format!(
+                    "{} {}x{} at {}",
+                    kind,
+                    m.words(),
+                    m.data_bits,
+                    path.join("/")
+                );
+                out.push((prefix.clone(), label));
+            }
+            Gate::Chip(inner) => memory_paths(inner, prefix, out),
+            _ => {}
+        }
+        prefix.pop();
+    }
+}
+
+/// Lists the gates of `chip` with buttons that put a probe path to their
+/// pins into `picked`; chip instances open into their own gates.
+fn probe_tree(ui: &mut Ui, chip: &Chip, prefix: &mut Vec<GateId>, picked: &mut Option<String>) {
+    let mut ids: Vec<&GateId> = chip.gates.keys().collect();
+    ids.sort();
+    for id in ids {
+        let gate = &chip.gates[id];
+        prefix.push(*id);
+        let path = prefix
+            .iter()
+            .map(|g| g.0.to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        let buttons = |ui: &mut Ui, picked: &mut Option<String>| {
+            for (side, count) in [("in", gate.input().len()), ("out", gate.output().len())] {
+                let text = match count {
+                    0 => continue,
+                    1 => format!("{}/{}0", path, side),
+                    _ => format!("{}/{}0-{}", path, side, count - 1),
+                };
+                if ui.small_button(side).on_hover_text(&text).clicked() {
+                    *picked = Some(text);
+                }
+            }
+        };
+        let label = format!("{} #{}", gate_label(gate), id.0);
+        match gate {
+            Gate::Chip(inner) => {
+                eframe::egui::CollapsingHeader::new(label)
+                    .id_source(&path)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Pins:");
+                            buttons(ui, picked);
+                        });
+                        probe_tree(ui, inner, prefix, picked);
+                    });
+            }
+            _ => {
+                ui.horizontal(|ui| {
+                    ui.label(label);
+                    buttons(ui, picked);
+                });
+            }
+        }
+        prefix.pop();
+    }
+}
+
+/// Draws the recorded samples of `probe`, newest at the right edge. Single
+/// pins draw as a level, buses as a band labelled at each change.
+fn draw_waveform(ui: &mut Ui, probe: &Probe) {
+    const STEP: f32 = 6.0;
+    const HEIGHT: f32 = 18.0;
+    let (rect, _) = ui.allocate_exact_size(
+        Vec2::new(ui.available_width().max(STEP), HEIGHT),
+        Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+    let shown = ((rect.width() / STEP) as usize).min(probe.samples.len());
+    let samples = probe.samples.iter().skip(probe.samples.len() - shown);
+    let width = probe.path.width();
+    let (top, bottom) = (rect.top() + 3.0, rect.bottom() - 3.0);
+    let green = Stroke::new(1.5, Color32::GREEN);
+    let mut previous: Option<Option<u64>> = None;
+    for (i, sample) in samples.enumerate() {
+        let x = rect.right() - (shown - i) as f32 * STEP;
+        let span = [x, x + STEP];
+        let changed = previous.is_some_and(|p| p != *sample);
+        match sample {
+            None => {
+                let y = rect.center().y;
+                painter.line_segment(
+                    [Pos2::new(span[0], y), Pos2::new(span[1], y)],
+                    Stroke::new(1.5, Color32::RED),
+                );
+            }
+            Some(v) if width == 1 => {
+                let y = if *v == 1 { top } else { bottom };
+                painter.line_segment([Pos2::new(span[0], y), Pos2::new(span[1], y)], green);
+                if changed {
+                    painter.line_segment([Pos2::new(x, top), Pos2::new(x, bottom)], green);
+                }
+            }
+            Some(_) => {
+                for y in [top, bottom] {
+                    painter.line_segment([Pos2::new(span[0], y), Pos2::new(span[1], y)], green);
+                }
+                if changed || previous.is_none() {
+                    painter.line_segment([Pos2::new(x, top), Pos2::new(x, bottom)], green);
+                    painter.text(
+                        Pos2::new(x + 2.0, rect.center().y),
+                        eframe::egui::Align2::LEFT_CENTER,
+                        probe.radix.format(*sample, width),
+                        eframe::egui::FontId::monospace(10.0),
+                        Color32::WHITE,
+                    );
+                }
+            }
+        }
+        previous = Some(*sample);
+    }
+}
+
+/// Keypad keys, top row first.
+const KEYPAD: [[u8; 4]; 4] = [
+    [1, 2, 3, 0xA],
+    [4, 5, 6, 0xB],
+    [7, 8, 9, 0xC],
+    [0xE, 0, 0xF, 0xD],
+];
+
+/// Cell of keypad row `row`, column `column` when the keypad fills `rect`.
+fn keypad_cell(rect: Rect, row: usize, column: usize) -> Rect {
+    let size = rect.size() / 4.0;
+    let min = rect.min + Vec2::new(column as f32 * size.x, row as f32 * size.y);
+    Rect::from_min_size(min, size)
+}
+
+/// The keypad key under `pos`, if any.
+fn keypad_key(rect: Rect, pos: Pos2) -> Option<u8> {
+    (0..16).find_map(|k| {
+        let (row, column) = (k / 4, k % 4);
+        keypad_cell(rect, row, column)
+            .contains(pos)
+            .then_some(KEYPAD[row][column])
+    })
+}
+
+/// Draws an input device inside `rect`. `rows` are the screen heights of its
+/// output pins, which DIP switches line up with.
+fn draw_switch(painter: &eframe::egui::Painter, rect: Rect, rows: &[f32], w: &SwitchGate) {
+    let on = Color32::DARK_GREEN;
+    let off = Color32::DARK_RED;
+    let outline = Stroke::new(1.0, Color32::WHITE);
+    match w.kind {
+        SwitchKind::PushButton => {
+            let radius = rect.width().min(rect.height()) / 2.0;
+            let color = if w.state == 1 {
+                on
+            } else {
+                Color32::from_gray(90)
+            };
+            painter.circle_filled(rect.center(), radius, color);
+            painter.circle_stroke(rect.center(), radius, outline);
+        }
+        SwitchKind::Toggle => {
+            let color = if w.state == 1 { on } else { off };
+            painter.rect_filled(rect, 4.0, color);
+            painter.rect_stroke(rect, 4.0, outline);
+        }
+        SwitchKind::DipSwitch => {
+            let height = (rect.height() / rows.len() as f32).min(PIN_SPACING) * 0.8;
+            for (k, &y) in rows.iter().enumerate() {
+                let slot = Rect::from_center_size(
+                    Pos2::new(rect.center().x, y),
+                    Vec2::new(rect.width(), height),
+                );
+                let high = w.state >> k & 1 == 1;
+                painter.rect_filled(slot, 2.0, if high { on } else { off });
+                // The knob sits to the right when the switch is on.
+                let knob = Rect::from_min_size(
+                    if high {
+                        Pos2::new(slot.center().x, slot.min.y)
+                    } else {
+                        slot.min
+                    },
+                    Vec2::new(slot.width() / 2.0, slot.height()),
+                );
+                painter.rect_filled(knob, 2.0, Color32::from_gray(200));
+            }
+        }
+        SwitchKind::HexKeypad => {
+            let held = w.state >> 4 & 1 == 1;
+            for (row, keys) in KEYPAD.iter().enumerate() {
+                for (column, &key) in keys.iter().enumerate() {
+                    let cell = keypad_cell(rect, row, column).shrink(1.0);
+                    let pressed = held && w.state & 0xF == key as u64;
+                    let fill = if pressed { on } else { Color32::from_gray(80) };
+                    painter.rect_filled(cell, 2.0, fill);
+                    painter.text(
+                        cell.center(),
+                        eframe::egui::Align2::CENTER_CENTER,
+                        format!("{:X}", key),
+                        eframe::egui::FontId::monospace(cell.height() * 0.7),
+                        Color32::WHITE,
+                    );
+                }
+            }
+        }
+        SwitchKind::Constant => {}
+    }
+}
+
+fn pin_offset(idx: usize, count: usize, x: f32) -> Vec2 {
+    Vec2::new(x, (idx as f32 - (count as f32 - 1.0) / 2.0) * PIN_SPACING)
+}
+
+impl VisualNode {
+    /// The body grows taller than `NODE_SIZE` when the pins need the room.
+    pub fn rect(&self) -> Rect {
+        let pins = self.inputs.len().max(self.outputs.len()) + 1;
+        let height = NODE_SIZE.y.max(pins as f32 * PIN_SPACING);
+        Rect::from_center_size(self.pos, Vec2::new(NODE_SIZE.x, height))
+    }
+
+    pub fn input_pos(&self, idx: usize) -> Pos2 {
+        self.pos + pin_offset(idx, self.inputs.len(), -NODE_SIZE.x / 2.0)
+    }
+
+    pub fn output_pos(&self, idx: usize) -> Pos2 {
+        self.pos + pin_offset(idx, self.outputs.len(), NODE_SIZE.x / 2.0)
+    }
+}
+
+/// Gates copied from a board, kept with their original IDs until pasted.
+#[derive(Clone)]
+pub struct ClipboardEntry {
+    pub gate: Gate,
     pub label: String,
+    /// Position relative to the center of the copied group.
+    pub offset: Vec2,
+}
+
+#[derive(Clone, Default)]
+pub struct Clipboard {
+    pub entries: Vec<ClipboardEntry>,
+    /// Wires whose both ends are on copied gates.
+    pub connections: Vec<(PinId, PinId)>,
+    /// Bend points of those wires, relative to the center of the copied group.
+    pub wire_bends: HashMap<(PinId, PinId), Vec<Vec2>>,
 }
 
 pub struct LogicApp {
     pub chip: Chip,
     pub nodes: Vec<VisualNode>,
-    pub dragging_wire_from: Option<(usize, Pos2)>,
+    pub dragging_wire_from: Option<(PinId, Pos2)>,
     pub input_count: usize,
     pub output_count: usize,
-    pub global_input_ids: Vec<usize>,
-    pub global_output_ids: Vec<usize>,
+    pub global_input_ids: Vec<GateId>,
+    pub global_output_ids: Vec<GateId>,
     pub auto_sim: bool,
-    pub chip_templates: HashMap<String, Chip>,
+    pub library: ChipLibrary,
     pub show_abstract_window: bool,
     pub abstract_name: String,
+    /// Whether the naming window turns the selection, rather than the whole
+    /// board, into a chip.
+    pub abstract_selection: bool,
+    /// Set while the user has agreed to replace the template named
+    /// `abstract_name`.
+    pub overwrite_template: bool,
+    pub confirm_overwrite: bool,
+    pub library_edit: Option<LibraryEdit>,
+    pub show_library_file: bool,
+    pub library_path: String,
+    /// Name and version written into exported library files.
+    pub library_source: LibrarySource,
+    pub show_project_file: bool,
+    pub project_path: String,
+    /// Built-in component and width picked in the "Components" palette.
+    pub component: Component,
+    pub component_width: usize,
+    pub memory_address_bits: usize,
+    pub memory_data_bits: usize,
+    /// Memory gate shown in the hex editor: gates from the board down, every
+    /// one but the last a chip instance.
+    pub memory_view: Option<Vec<GateId>>,
+    /// Word being edited in the hex editor and the text typed so far.
+    pub memory_edit: Option<(usize, String)>,
+    pub memory_path: String,
+    pub memory_format: MemoryFormat,
+    /// Terminal shown in the terminal window.
+    pub terminal_view: Option<GateId>,
+    /// Gate whose ROM (its own, or one nested in it) the program window
+    /// assembles into and disassembles.
+    pub program_view: Option<GateId>,
+    pub program_source: String,
+    pub program_error: Option<String>,
+    /// Probes of the board, shown in the watch window.
+    pub watch: WatchList,
+    pub show_watch: bool,
+    /// Name and path typed for the next probe.
+    pub probe_name: String,
+    pub probe_path: String,
+    /// Breakpoints, step mode and clock input.
+    pub debugger: Debugger,
+    /// Whether the clock input toggles every frame.
+    pub run_clock: bool,
+    /// Probe and value typed for the next breakpoint.
+    pub breakpoint_probe: String,
+    pub breakpoint_value: String,
+    pub show_faults: bool,
+    /// Test vectors typed for fault simulation, in the test vector format.
+    pub fault_vectors: String,
+    pub fault_report: Option<FaultReport>,
+    /// Result of the last test generation, for its redundant faults.
+    pub atpg_report: Option<Atpg>,
+    pub vector_path: String,
+    pub show_timing: bool,
+    pub delays: Delays,
+    pub timing: Option<Timing>,
+    /// Board gates and wires the critical path runs through.
+    pub critical_gates: HashSet<GateId>,
+    pub critical_wires: HashSet<(PinId, PinId)>,
+    pub highlight_critical: bool,
+    pub show_stats: bool,
+    /// Template to count, or the board when `None`.
+    pub stats_target: Option<String>,
+    /// Cost table being edited, as `NAME=COST` lines.
+    pub cost_text: String,
+    pub stats: Option<Stats>,
+    /// Whether gates holding undetected faults are outlined on the canvas.
+    pub highlight_faults: bool,
+    /// Kind and width picked in the "Sequential" palette.
+    pub register_kind: RegisterKind,
+    pub register_width: usize,
+    /// Device and settings picked in the "Displays" palette.
+    pub display_kind: DisplayKind,
+    pub led_color: [u8; 3],
+    pub matrix_columns: usize,
+    pub matrix_rows: usize,
+    /// Device and settings picked in the "Inputs" palette.
+    pub switch_kind: SwitchKind,
+    pub dip_switches: usize,
+    pub tie_high: bool,
+    pub toasts: Vec<Toast>,
+    /// A dropped wire whose target already has a driver, awaiting confirmation.
+    pub pending_replace: Option<(PinId, PinId)>,
+    pub camera: Camera,
+    pub snap_to_grid: bool,
+    /// World positions of the global input/output gates.
+    pub io_positions: HashMap<GateId, Pos2>,
+    pub selected: HashSet<GateId>,
+    /// Screen position where the current rubber-band drag started.
+    pub rubber_band: Option<Pos2>,
+    /// Lives on the app rather than the board so it survives board switches.
+    pub clipboard: Clipboard,
+    /// User bend points of each wire, in world coordinates. Wires without an
+    /// entry take the default route.
+    pub wire_bends: HashMap<(PinId, PinId), Vec<Pos2>>,
+}
+
+impl Default for LogicApp {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LogicApp {
     pub fn new() -> Self {
         Self {
-            chip: Chip::new(0),
+            chip: Chip::new(ChipId(0)),
             nodes: Vec::new(),
             dragging_wire_from: None,
             input_count: 2,
@@ -80,436 +704,2862 @@ impl LogicApp {
             global_input_ids: Vec::new(),
             global_output_ids: Vec::new(),
             auto_sim: false,
-            chip_templates: HashMap::new(),
+            library: ChipLibrary::new(),
             show_abstract_window: false,
             abstract_name: String::new(),
+            abstract_selection: false,
+            overwrite_template: false,
+            confirm_overwrite: false,
+            library_edit: None,
+            show_library_file: false,
+            library_path: "library.lgl".to_string(),
+            show_project_file: false,
+            project_path: "project.lgp".to_string(),
+            library_source: LibrarySource {
+                name: "mylib".to_string(),
+                version: 1,
+            },
+            component: Component::Mux,
+            component_width: 2,
+            memory_address_bits: memory::DEFAULT_ADDRESS_BITS,
+            memory_data_bits: memory::DEFAULT_DATA_BITS,
+            memory_view: None,
+            memory_edit: None,
+            memory_path: "memory.hex".to_string(),
+            memory_format: MemoryFormat::IntelHex,
+            terminal_view: None,
+            program_view: None,
+            program_source: String::new(),
+            program_error: None,
+            watch: WatchList::default(),
+            show_watch: false,
+            probe_name: String::new(),
+            probe_path: String::new(),
+            debugger: Debugger::default(),
+            run_clock: false,
+            breakpoint_probe: String::new(),
+            breakpoint_value: "1".to_string(),
+            show_faults: false,
+            fault_vectors: String::new(),
+            fault_report: None,
+            atpg_report: None,
+            vector_path: "vectors.txt".to_string(),
+            show_timing: false,
+            delays: Delays::default(),
+            timing: None,
+            critical_gates: HashSet::new(),
+            critical_wires: HashSet::new(),
+            highlight_critical: true,
+            show_stats: false,
+            stats_target: None,
+            cost_text: CostTable::default().to_text(),
+            stats: None,
+            highlight_faults: true,
+            register_kind: RegisterKind::Register,
+            register_width: register::DEFAULT_REGISTER_BITS,
+            display_kind: DisplayKind::Led,
+            led_color: display::DEFAULT_LED_COLOR,
+            matrix_columns: display::DEFAULT_MATRIX_SIZE,
+            matrix_rows: display::DEFAULT_MATRIX_SIZE,
+            switch_kind: SwitchKind::Toggle,
+            dip_switches: switch::DEFAULT_DIP_SWITCHES,
+            tie_high: true,
+            toasts: Vec::new(),
+            pending_replace: None,
+            camera: Camera::default(),
+            snap_to_grid: false,
+            io_positions: HashMap::new(),
+            selected: HashSet::new(),
+            rubber_band: None,
+            clipboard: Clipboard::default(),
+            wire_bends: HashMap::new(),
         }
     }
 
     pub fn add_gate(&mut self, gtype: GateType, pos: Pos2) {
-        let gate = Gate::new(gtype, vec![]);
+        let gate = Gate::new(gtype, vec![], &mut self.chip.ids);
         self.register_visual_node(gate, pos, "UNK".to_string());
     }
 
-    pub fn add_custom_chip(&mut self, name: &str, pos: Pos2) {
-        if let Some(template) = self.chip_templates.get(name) {
-            let new_chip = template.deep_copy();
-            let gate = Gate::Chip(new_chip);
-            self.register_visual_node(gate, pos, name.to_string());
+    pub fn add_custom_chip(&mut self, name: &str, pos: Pos2) -> Result<(), LgsimError> {
+        let template = self
+            .library
+            .get(name)
+            .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))?;
+        let new_chip = template.deep_copy(&mut self.chip.ids)?;
+        self.register_visual_node(Gate::Chip(new_chip), pos, name.to_string());
+        Ok(())
+    }
+
+    /// Simulates once and records the probes. Returns whether it succeeded.
+    pub fn step(&mut self) -> bool {
+        let result = self.chip.simulate();
+        if self.report(result).is_none() {
+            return false;
         }
+        self.debugger.changed.clear();
+        self.after_step();
+        true
     }
 
-    pub fn register_visual_node(&mut self, gate: Gate, pos: Pos2, custom_label: String) {
-        let id = gate.id();
-        let inputs = gate.input().to_vec();
-        let outputs = gate.output().to_vec();
-        let label = match gate {
-            Gate::And(_) => "AND".to_string(),
-            Gate::Not(_) => "NOT".to_string(),
-            Gate::Chip(_) => custom_label,
-            _ => "UNK".to_string(),
+    /// Toggles the clock input once, for a running clock.
+    pub fn clock_step(&mut self) -> bool {
+        let result = self.debugger.clock_edge(&mut self.chip);
+        if self.report(result).is_none() {
+            return false;
+        }
+        self.debugger.changed.clear();
+        self.after_step();
+        true
+    }
+
+    /// Advances one step of the debugger's mode, keeping the changed pins
+    /// for the canvas to highlight.
+    pub fn debug_step(&mut self) {
+        let result = self.debugger.step(&mut self.chip);
+        if let Some(changed) = self.report(result) {
+            if !changed {
+                self.toasts.push(Toast {
+                    text: "nothing changed: the board has settled".to_string(),
+                    shown_at: None,
+                });
+            }
+            self.after_step();
+        }
+    }
+
+    /// Records the probes and pauses running simulation if a breakpoint
+    /// fired.
+    fn after_step(&mut self) {
+        self.watch.sample(&self.chip);
+        let fired = self.debugger.check(&self.watch, &self.chip);
+        if !fired.is_empty() {
+            self.auto_sim = false;
+            self.run_clock = false;
+            self.toasts.push(Toast {
+                text: format!("breakpoint: {}", fired.join(", ")),
+                shown_at: None,
+            });
+        }
+    }
+
+    /// Shows the error of a failed operation as a toast instead of aborting.
+    pub fn report<T>(&mut self, result: Result<T, LgsimError>) -> Option<T> {
+        match result {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.toasts.push(Toast {
+                    text: e.to_string(),
+                    shown_at: None,
+                });
+                None
+            }
+        }
+    }
+
+    /// Makes a chip built in code (e.g. with `ChipBuilder`) available in the
+    /// chip library, replacing any template of the same name.
+    pub fn add_template(&mut self, name: &str, template: Chip) {
+        self.library.replace(name, template, "");
+    }
+
+    /// Fails with `NameTaken` if saving under `abstract_name` would overwrite
+    /// a template the user has not agreed to replace.
+    fn check_template_name(&self) -> Result<(), LgsimError> {
+        if !self.overwrite_template && self.library.contains(&self.abstract_name) {
+            return Err(LgsimError::NameTaken(self.abstract_name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Saves `template` as `abstract_name`, keeping the folder of a template
+    /// it replaces.
+    fn store_template(&mut self, template: Chip) {
+        let name = std::mem::take(&mut self.abstract_name);
+        let folder = self
+            .library
+            .template(&name)
+            .map(|t| t.folder.clone())
+            .unwrap_or_default();
+        self.library.replace(&name, template, &folder);
+    }
+
+    /// Runs the naming window's action, asking before overwriting a template.
+    fn finish_abstraction(&mut self) {
+        let result = if self.abstract_selection {
+            self.extract_selection()
+        } else {
+            self.create_abstract_chip()
         };
+        match result {
+            Err(LgsimError::NameTaken(_)) => self.confirm_overwrite = true,
+            other => {
+                self.report(other);
+            }
+        }
+    }
 
-        self.chip.add_gate(gate);
-        self.nodes.push(VisualNode {
-            gate_id: id,
-            pos,
-            inputs,
-            outputs,
-            label,
+    /// Applies `library_edit` and refreshes the labels of renamed instances.
+    fn apply_library_edit(&mut self, edit: LibraryEdit) -> Result<(), LgsimError> {
+        match edit {
+            LibraryEdit::Rename { name, new_name } => {
+                self.library.rename(&name, &new_name, &mut self.chip)?;
+                for node in &mut self.nodes {
+                    if let Some(Gate::Chip(c)) = self.chip.gates.get(&node.gate_id)
+                        && let Some(label) = &c.name
+                    {
+                        node.label = label.clone();
+                    }
+                }
+                Ok(())
+            }
+            LibraryEdit::Duplicate { name, new_name } => self.library.duplicate(&name, &new_name),
+            LibraryEdit::Move { name, folder } => self.library.set_folder(&name, folder.trim()),
+        }
+    }
+
+    /// Places the palette's built-in component, adding its template to the
+    /// library first if needed.
+    pub fn add_component(&mut self, pos: Pos2) -> Result<(), LgsimError> {
+        let name = self
+            .component
+            .install(self.component_width, &mut self.library)?;
+        self.add_custom_chip(&name, pos)
+    }
+
+    pub fn add_memory(&mut self, kind: MemoryKind, pos: Pos2) -> Result<(), LgsimError> {
+        memory::check_widths(self.memory_address_bits, self.memory_data_bits)?;
+        let id = self.chip.ids.gate();
+        let gate = MemoryGate::new(
+            kind,
+            self.memory_address_bits,
+            self.memory_data_bits,
+            id,
+            &mut self.chip.ids,
+        );
+        self.register_visual_node(Gate::Memory(gate), pos, String::new());
+        Ok(())
+    }
+
+    pub fn add_register(&mut self, pos: Pos2) -> Result<(), LgsimError> {
+        register::check_width(self.register_width)?;
+        let id = self.chip.ids.gate();
+        let gate = RegisterGate::new(
+            self.register_kind,
+            self.register_width,
+            id,
+            &mut self.chip.ids,
+        );
+        self.register_visual_node(Gate::Register(gate), pos, String::new());
+        Ok(())
+    }
+
+    pub fn add_display(&mut self, pos: Pos2) -> Result<(), LgsimError> {
+        if self.display_kind == DisplayKind::PixelMatrix {
+            display::check_matrix_size(self.matrix_columns, self.matrix_rows)?;
+        }
+        let id = self.chip.ids.gate();
+        let mut gate = DisplayGate::new(
+            self.display_kind,
+            self.matrix_columns,
+            self.matrix_rows,
+            id,
+            &mut self.chip.ids,
+        );
+        gate.color = self.led_color;
+        self.register_visual_node(Gate::Display(gate), pos, String::new());
+        Ok(())
+    }
+
+    pub fn add_switch(&mut self, pos: Pos2) -> Result<(), LgsimError> {
+        if self.switch_kind == SwitchKind::DipSwitch {
+            switch::check_switches(self.dip_switches)?;
+        }
+        let id = self.chip.ids.gate();
+        let mut gate = SwitchGate::new(self.switch_kind, self.dip_switches, id, &mut self.chip.ids);
+        if self.switch_kind == SwitchKind::Constant {
+            gate.state = self.tie_high as u64;
+            gate.evaluate();
+        }
+        self.register_visual_node(Gate::Switch(gate), pos, String::new());
+        Ok(())
+    }
+
+    /// Assembles the program source into the ROM of the gate in the program
+    /// window. Returns the number of words loaded.
+    fn load_program(&mut self) -> Result<usize, LgsimError> {
+        let words = cpu::assemble(&self.program_source)?;
+        let id = self.program_view.ok_or(LgsimError::NoProgramView)?;
+        let gate = self
+            .chip
+            .gates
+            .get_mut(&id)
+            .ok_or(LgsimError::UnknownGate(id))?;
+        match find_gate_mut(gate, &is_rom) {
+            Some(Gate::Memory(rom)) => rom.load(&words)?,
+            _ => return Err(LgsimError::NoRom),
+        }
+        Ok(words.len())
+    }
+
+    fn program_window(&mut self, ctx: &eframe::egui::Context) {
+        let Some(gate) = self.program_view.and_then(|id| self.chip.gates.get(&id)) else {
+            self.program_view = None;
+            return;
+        };
+        let rom = find_gate(gate, &is_rom).and_then(|g| match g {
+            Gate::Memory(m) => Some(m.contents.clone()),
+            _ => None,
         });
+        let cpu = find_gate(gate, &|g| matches!(g, Gate::Cpu(_))).and_then(|g| match g {
+            Gate::Cpu(c) => Some(c.clone()),
+            _ => None,
+        });
+        let mut open = true;
+        let mut assemble = false;
+        eframe::egui::Window::new("Program")
+            .open(&mut open)
+            .default_size([560.0, 380.0])
+            .show(ctx, |ui| {
+                ui.columns(2, |columns| {
+                    let ui = &mut columns[0];
+                    ui.label("Assembly:");
+                    eframe::egui::ScrollArea::vertical()
+                        .id_source("program_source")
+                        .max_height(280.0)
+                        .show(ui, |ui| {
+                            ui.add(
+                                eframe::egui::TextEdit::multiline(&mut self.program_source)
+                                    .code_editor()
+                                    .desired_rows(16),
+                            );
+                        });
+                    if ui.button("Assemble && Load").clicked() {
+                        assemble = true;
+                    }
+                    if let Some(error) = &self.program_error {
+                        ui.colored_label(Color32::RED, error);
+                    }
+
+                    let ui = &mut columns[1];
+                    if let Some(c) = &cpu {
+                        ui.monospace(format!(
+                            "PC {:02X}  A {:02X}  Z {}  C {}{}",
+                            c.pc,
+                            c.a,
+                            c.zero as u8,
+                            c.carry as u8,
+                            if c.halted { "  halted" } else { "" }
+                        ));
+                    }
+                    let Some(words) = &rom else {
+                        ui.label("No ROM to disassemble.");
+                        return;
+                    };
+                    let pc = cpu.as_ref().map(|c| c.pc as usize);
+                    let row_height = ui.text_style_height(&eframe::egui::TextStyle::Monospace);
+                    eframe::egui::ScrollArea::vertical()
+                        .id_source("disassembly")
+                        .show_rows(ui, row_height, words.len(), |ui, rows| {
+                            for address in rows {
+                                let text = format!(
+                                    "{:02X}  {}",
+                                    address,
+                                    cpu::disassemble(words[address])
+                                );
+                                let current = pc == Some(address);
+                                let _ = ui.selectable_label(
+                                    current,
+                                    eframe::egui::RichText::new(text).monospace(),
+                                );
+                            }
+                        });
+                });
+            });
+        if assemble {
+            match self.load_program() {
+                Ok(count) => {
+                    self.program_error = None;
+                    self.toasts.push(Toast {
+                        text: format!("loaded {} words", count),
+                        shown_at: None,
+                    });
+                }
+                Err(e) => self.program_error = Some(e.to_string()),
+            }
+        }
+        if !open {
+            self.program_view = None;
+        }
     }
 
-    pub fn sync_io(&mut self) {
-        while self.global_input_ids.len() < self.input_count {
-            let gate = Gate::new(GateType::Source, vec![]);
-            self.global_input_ids.push(self.chip.add_gate(gate));
+    fn viewed_memory(&mut self) -> Result<&mut MemoryGate, LgsimError> {
+        let path = self.memory_view.as_ref().ok_or(LgsimError::NoMemoryView)?;
+        let id = path.last().copied().ok_or(LgsimError::NoMemoryView)?;
+        match self.chip.gate_at_mut(path) {
+            Some(Gate::Memory(m)) => Ok(m),
+            Some(g) => Err(LgsimError::UnsupportedGate(g.gate_type())),
+            None => Err(LgsimError::UnknownGate(id)),
         }
-        while self.global_input_ids.len() > self.input_count {
-            self.global_input_ids.pop();
+    }
+
+    /// Replaces the contents of the memory in the hex editor with the file at
+    /// `memory_path`.
+    pub fn load_memory(&mut self) -> Result<(), LgsimError> {
+        let data = std::fs::read(&self.memory_path)?;
+        let format = self.memory_format;
+        let m = self.viewed_memory()?;
+        let words = memory::parse_contents(format, &data, m.data_bits, m.words())?;
+        m.load(&words)
+    }
+
+    pub fn save_memory(&mut self) -> Result<(), LgsimError> {
+        let format = self.memory_format;
+        let path = self.memory_path.clone();
+        let m = self.viewed_memory()?;
+        std::fs::write(
+            path,
+            memory::write_contents(format, &m.contents, m.data_bits),
+        )?;
+        Ok(())
+    }
+
+    /// The board's global inputs and outputs, as fault simulation drives
+    /// and observes them.
+    fn board_ports(&self) -> Ports {
+        let pins = |ids: &[GateId], output: bool| {
+            ids.iter()
+                .filter_map(|id| self.chip.gates.get(id))
+                .filter_map(|g| match output {
+                    true => g.output().first(),
+                    false => g.input().first(),
+                })
+                .copied()
+                .collect()
+        };
+        Ports {
+            inputs: pins(&self.global_input_ids, true),
+            outputs: pins(&self.global_output_ids, false),
+        }
+    }
+
+    /// Fault-simulates the board against the typed test vectors.
+    fn run_fault_simulation(&mut self) -> Result<(), LgsimError> {
+        let ports = self.board_ports();
+        let vectors =
+            runner::parse_vectors(&self.fault_vectors, ports.inputs.len(), ports.outputs.len())?;
+        let inputs: Vec<Vec<PinValue>> = vectors.into_iter().map(|v| v.inputs).collect();
+        let faults = fault::all_faults(&self.chip);
+        self.fault_report = Some(fault::simulate_faults(&self.chip, &ports, faults, &inputs)?);
+        Ok(())
+    }
+
+    /// Generates test vectors for the board with ATPG and puts them in the
+    /// vector editor.
+    fn generate_tests(&mut self) -> Result<(), LgsimError> {
+        let atpg = atpg::generate(&self.chip, &self.board_ports())?;
+        self.fault_vectors = runner::format_vectors(&atpg.vectors);
+        self.atpg_report = Some(atpg);
+        Ok(())
+    }
+
+    /// Board gates holding an undetected fault, to outline on the canvas.
+    fn faulty_gates(&self) -> HashSet<GateId> {
+        match &self.fault_report {
+            Some(report) if self.highlight_faults => report
+                .undetected()
+                .filter_map(|f| f.top_gate(&self.chip))
+                .collect(),
+            _ => HashSet::new(),
+        }
+    }
+
+    /// Test vectors for the board, the fault coverage they reach and the
+    /// faults they miss.
+    fn fault_window(&mut self, ctx: &eframe::egui::Context) {
+        let mut open = true;
+        let mut run = false;
+        let mut generate = false;
+        let mut save = false;
+        eframe::egui::Window::new("Fault Simulation")
+            .open(&mut open)
+            .default_size([420.0, 420.0])
+            .show(ctx, |ui| {
+                let inputs = self.global_input_ids.len();
+                ui.label(format!(
+                    "One vector per line: {} input bits (In 0 first), optionally followed by \
+                     the expected outputs.",
+                    inputs
+                ));
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            inputs <= MAX_EXHAUSTIVE_INPUTS,
+                            eframe::egui::Button::new("Exhaustive"),
+                        )
+                        .on_disabled_hover_text(format!(
+                            "Only for up to {} inputs",
+                            MAX_EXHAUSTIVE_INPUTS
+                        ))
+                        .clicked()
+                    {
+                        self.fault_vectors = (0..1usize << inputs)
+                            .map(|v| {
+                                (0..inputs).map(move |b| if v >> b & 1 == 1 { '1' } else { '0' })
+                            })
+                            .map(|bits| bits.collect::<String>() + "\n")
+                            .collect();
+                    }
+                    generate = ui
+                        .button("Generate (ATPG)")
+                        .on_hover_text("Combinational boards only: AND, NOT and chips of them")
+                        .clicked();
+                    run = ui.button("Run").clicked();
+                    ui.checkbox(&mut self.highlight_faults, "Highlight Undetected");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.text_edit_singleline(&mut self.vector_path);
+                    save = ui.button("Save Vectors").clicked();
+                });
+                eframe::egui::ScrollArea::vertical()
+                    .id_source("fault_vectors")
+                    .max_height(120.0)
+                    .show(ui, |ui| {
+                        ui.add(
+                            eframe::egui::TextEdit::multiline(&mut self.fault_vectors)
+                                .code_editor()
+                                .desired_width(f32::INFINITY),
+                        );
+                    });
+                if let Some(atpg) = &self.atpg_report {
+                    ui.separator();
+                    ui.label(format!(
+                        "ATPG: {} vectors detect {} faults ({:.1}% of the detectable); \
+                         {} redundant, {} aborted",
+                        atpg.vectors.len(),
+                        atpg.detected.len(),
+                        atpg.efficiency(),
+                        atpg.redundant.len(),
+                        atpg.aborted.len()
+                    ));
+                    if !atpg.redundant.is_empty() {
+                        eframe::egui::CollapsingHeader::new("Redundant").show(ui, |ui| {
+                            eframe::egui::ScrollArea::vertical()
+                                .id_source("redundant")
+                                .max_height(100.0)
+                                .show(ui, |ui| {
+                                    for f in &atpg.redundant {
+                                        ui.monospace(f.describe(&self.chip));
+                                    }
+                                });
+                        });
+                    }
+                }
+                let Some(report) = &self.fault_report else {
+                    return;
+                };
+                ui.separator();
+                ui.label(format!(
+                    "Coverage: {:.1}% ({} of {} faults detected)",
+                    report.coverage(),
+                    report.detected(),
+                    report.faults.len()
+                ));
+                ui.label("Undetected:");
+                eframe::egui::ScrollArea::vertical()
+                    .id_source("undetected")
+                    .show(ui, |ui| {
+                        for f in report.undetected() {
+                            ui.monospace(f.describe(&self.chip));
+                        }
+                    });
+            });
+        if generate {
+            let result = self.generate_tests();
+            self.report(result);
+        }
+        if save {
+            let result = std::fs::write(&self.vector_path, &self.fault_vectors);
+            self.report(result.map_err(LgsimError::from));
+        }
+        if run {
+            let result = self.run_fault_simulation();
+            self.report(result);
+        }
+        if !open {
+            self.show_faults = false;
+        }
+    }
+
+    /// Runs timing analysis on the board and finds the board gates and wires
+    /// along the critical path.
+    fn analyze_timing(&mut self) -> Result<(), LgsimError> {
+        let timing = timing::analyze(&self.chip, &self.board_ports(), self.delays)?;
+        let mut gates: Vec<GateId> = timing
+            .critical_path
+            .iter()
+            .filter_map(|p| self.chip.top_gate_of(p))
+            .collect();
+        gates.dedup();
+        let steps: HashSet<(GateId, GateId)> = gates.windows(2).map(|w| (w[0], w[1])).collect();
+        self.critical_wires = self
+            .chip
+            .wires()
+            .into_iter()
+            .filter(
+                |(src, dest)| match (self.chip.top_gate_of(src), self.chip.top_gate_of(dest)) {
+                    (Some(a), Some(b)) => steps.contains(&(a, b)),
+                    _ => false,
+                },
+            )
+            .collect();
+        self.critical_gates = gates.into_iter().collect();
+        self.timing = Some(timing);
+        Ok(())
+    }
+
+    /// Gate delays, the depth of every output and the longest path between
+    /// each input and output.
+    fn timing_window(&mut self, ctx: &eframe::egui::Context) {
+        let mut open = true;
+        let mut analyze = false;
+        eframe::egui::Window::new("Timing")
+            .open(&mut open)
+            .default_size([380.0, 360.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Delay AND:");
+                    ui.add(eframe::egui::DragValue::new(&mut self.delays.and).clamp_range(1..=100));
+                    ui.label("NOT:");
+                    ui.add(eframe::egui::DragValue::new(&mut self.delays.not).clamp_range(1..=100));
+                    analyze = ui.button("Analyze").clicked();
+                });
+                ui.checkbox(&mut self.highlight_critical, "Highlight Critical Path");
+                let Some(timing) = &self.timing else {
+                    return;
+                };
+                ui.separator();
+                match timing.critical_delay() {
+                    Some(delay) => ui.label(format!(
+                        "Critical path: {} units through {} gates",
+                        delay,
+                        timing
+                            .outputs
+                            .iter()
+                            .flatten()
+                            .filter(|a| a.delay == delay)
+                            .map(|a| a.gates)
+                            .max()
+                            .unwrap_or(0)
+                    )),
+                    None => ui.label("No path reaches an output."),
+                };
+                let cell = |v: Option<u32>| v.map_or("-".to_string(), |v| v.to_string());
+                eframe::egui::Grid::new("output_depth")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Output");
+                        ui.strong("Delay");
+                        ui.strong("Depth");
+                        ui.end_row();
+                        for (k, a) in timing.outputs.iter().enumerate() {
+                            ui.label(format!("Out {}", k));
+                            ui.label(cell(a.map(|a| a.delay)));
+                            ui.label(cell(a.map(|a| a.gates)));
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                ui.label("Longest path, input to output:");
+                eframe::egui::ScrollArea::both()
+                    .id_source("timing_paths")
+                    .show(ui, |ui| {
+                        eframe::egui::Grid::new("timing_paths")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label("");
+                                for k in 0..timing.outputs.len() {
+                                    ui.strong(format!("Out {}", k));
+                                }
+                                ui.end_row();
+                                for (i, row) in timing.paths.iter().enumerate() {
+                                    ui.strong(format!("In {}", i));
+                                    for delay in row {
+                                        ui.label(cell(*delay));
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        if analyze {
+            let result = self.analyze_timing();
+            self.report(result);
+        }
+        if !open {
+            self.show_timing = false;
+        }
+    }
+
+    /// Counts the board or the chosen template with the typed cost table.
+    fn count_stats(&mut self) -> Result<(), LgsimError> {
+        let costs = CostTable::parse(&self.cost_text)?;
+        let chip = match &self.stats_target {
+            Some(name) => self
+                .library
+                .get(name)
+                .ok_or_else(|| LgsimError::UnknownTemplate(name.clone()))?,
+            None => &self.chip,
+        };
+        self.stats = Some(stats::stats(chip, &costs)?);
+        Ok(())
+    }
+
+    /// Gate counts and cost of the board or a template, through every
+    /// nested chip.
+    fn stats_window(&mut self, ctx: &eframe::egui::Context) {
+        let mut open = true;
+        let mut count = false;
+        eframe::egui::Window::new("Stats")
+            .open(&mut open)
+            .default_size([320.0, 420.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Count:");
+                    let selected = self.stats_target.as_deref().unwrap_or("Board").to_string();
+                    eframe::egui::ComboBox::from_id_source("stats_target")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.stats_target, None, "Board");
+                            for name in self.library.names() {
+                                let target = Some(name.to_string());
+                                ui.selectable_value(&mut self.stats_target, target, name);
+                            }
+                        });
+                    count = ui.button("Count").clicked();
+                });
+                ui.label("Transistors per gate (NAME=COST):");
+                ui.add(
+                    eframe::egui::TextEdit::multiline(&mut self.cost_text)
+                        .code_editor()
+                        .desired_rows(4),
+                );
+                let Some(stats) = &self.stats else {
+                    return;
+                };
+                ui.separator();
+                eframe::egui::Grid::new("stats_summary")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let mut row = |name: &str, value: String| {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        };
+                        row("Gates", stats.total_gates().to_string());
+                        row("Transistors", stats.transistors.to_string());
+                        row("AND-NOT pairs as NAND", stats.nands.to_string());
+                        row("Chip instances", stats.instances.to_string());
+                        row("Hierarchy depth", stats.depth.to_string());
+                        row("Nets", stats.nets.to_string());
+                        row("Max fan-in", stats.max_fan_in.to_string());
+                        row("Max fan-out", stats.max_fan_out.to_string());
+                    });
+                if !stats.uncosted.is_empty() {
+                    ui.label(format!("Not costed: {}", stats.uncosted.join(", ")));
+                }
+                ui.separator();
+                eframe::egui::ScrollArea::vertical()
+                    .id_source("stats_gates")
+                    .show(ui, |ui| {
+                        eframe::egui::Grid::new("stats_gates")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.strong("Gate");
+                                ui.strong("Count");
+                                ui.end_row();
+                                for (name, n) in &stats.gates {
+                                    ui.label(name);
+                                    ui.label(n.to_string());
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        if count {
+            let result = self.count_stats();
+            self.report(result);
+        }
+        if !open {
+            self.show_stats = false;
+        }
+    }
+
+    /// Probe values and recorded waveforms. New probes are added by path,
+    /// picked from the hierarchy browser or a node's context menu.
+    fn watch_window(&mut self, ctx: &eframe::egui::Context) {
+        let mut open = true;
+        let mut add = false;
+        let mut add_breakpoint = false;
+        let mut remove = None;
+        eframe::egui::Window::new("Watch")
+            .open(&mut open)
+            .default_size([520.0, 360.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.add(
+                        eframe::egui::TextEdit::singleline(&mut self.probe_name)
+                            .desired_width(80.0),
+                    );
+                    ui.label("Path:");
+                    ui.add(
+                        eframe::egui::TextEdit::singleline(&mut self.probe_path)
+                            .desired_width(140.0),
+                    )
+                    .on_hover_text(
+                        "Gate IDs from the board down, then a pin range, e.g. 12/40/out0-7",
+                    );
+                    add = ui.button("Add Probe").clicked();
+                });
+                eframe::egui::CollapsingHeader::new("Browse").show(ui, |ui| {
+                    eframe::egui::ScrollArea::vertical()
+                        .id_source("probe_tree")
+                        .max_height(160.0)
+                        .show(ui, |ui| {
+                            let mut picked = None;
+                            probe_tree(ui, &self.chip, &mut Vec::new(), &mut picked);
+                            if let Some(path) = picked {
+                                self.probe_path = path;
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.watch.recording, "Record");
+                    if ui.button("Clear Waveforms").clicked() {
+                        self.watch.clear_samples();
+                    }
+                });
+                eframe::egui::CollapsingHeader::new("Breakpoints")
+                    .default_open(!self.debugger.breakpoints.is_empty())
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Stop when");
+                            eframe::egui::ComboBox::from_id_source("breakpoint_probe")
+                                .selected_text(&self.breakpoint_probe)
+                                .show_ui(ui, |ui| {
+                                    for probe in &self.watch.probes {
+                                        ui.selectable_value(
+                                            &mut self.breakpoint_probe,
+                                            probe.name.clone(),
+                                            &probe.name,
+                                        );
+                                    }
+                                });
+                            ui.label("becomes");
+                            ui.add(
+                                eframe::egui::TextEdit::singleline(&mut self.breakpoint_value)
+                                    .desired_width(60.0),
+                            );
+                            add_breakpoint = ui.button("Add").clicked();
+                        });
+                        let mut removed = None;
+                        for (k, bp) in self.debugger.breakpoints.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(
+                                    &mut bp.enabled,
+                                    format!("{} = {:#X}", bp.probe, bp.value),
+                                );
+                                if ui.small_button("x").clicked() {
+                                    removed = Some(k);
+                                }
+                            });
+                        }
+                        if let Some(k) = removed {
+                            self.debugger.breakpoints.remove(k);
+                        }
+                    });
+                ui.separator();
+                eframe::egui::ScrollArea::vertical()
+                    .id_source("probes")
+                    .show(ui, |ui| {
+                        for probe in &mut self.watch.probes {
+                            ui.horizontal(|ui| {
+                                ui.strong(&probe.name).on_hover_text(probe.path.to_string());
+                                let value = probe.path.read(&self.chip);
+                                ui.monospace(probe.radix.format(value, probe.path.width()));
+                                if probe.path.width() > 1 {
+                                    for radix in Radix::ALL {
+                                        if ui
+                                            .selectable_label(probe.radix == radix, radix.label())
+                                            .clicked()
+                                        {
+                                            probe.radix = radix;
+                                        }
+                                    }
+                                }
+                                if ui.small_button("x").clicked() {
+                                    remove = Some(probe.name.clone());
+                                }
+                            });
+                            if !probe.samples.is_empty() {
+                                draw_waveform(ui, probe);
+                            }
+                        }
+                    });
+            });
+        if add {
+            let name = match self.probe_name.trim() {
+                "" => self.probe_path.trim().to_string(),
+                name => name.to_string(),
+            };
+            let result = self
+                .probe_path
+                .parse::<ProbePath>()
+                .and_then(|path| self.watch.add(&name, path, &self.chip));
+            if self.report(result).is_some() {
+                self.probe_name.clear();
+            }
+        }
+        if add_breakpoint {
+            let result = parse_value(&self.breakpoint_value).and_then(|value| {
+                if !self
+                    .watch
+                    .probes
+                    .iter()
+                    .any(|p| p.name == self.breakpoint_probe)
+                {
+                    return Err(LgsimError::UnknownProbe(self.breakpoint_probe.clone()));
+                }
+                self.debugger
+                    .breakpoints
+                    .push(Breakpoint::new(&self.breakpoint_probe, value));
+                Ok(())
+            });
+            self.report(result);
+        }
+        if let Some(name) = remove {
+            self.watch.remove(&name);
+            self.debugger.breakpoints.retain(|bp| bp.probe != name);
+        }
+        if !open {
+            self.show_watch = false;
+        }
+    }
+
+    fn memory_window(&mut self, ctx: &eframe::egui::Context) {
+        const COLUMNS: usize = 8;
+        let mut open = true;
+        let mut action: Option<bool> = None;
+        let edit = &mut self.memory_edit;
+        let path = &mut self.memory_path;
+        let format = &mut self.memory_format;
+        let Some(Gate::Memory(m)) = self
+            .memory_view
+            .as_ref()
+            .and_then(|path| self.chip.gate_at_mut(path))
+        else {
+            self.memory_view = None;
+            return;
+        };
+        eframe::egui::Window::new("Memory Contents")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let current = m.address();
+                ui.label(format!(
+                    "{} words x {} bits, address {:#x}",
+                    m.words(),
+                    m.data_bits,
+                    current
+                ));
+                let digits = m.data_bits.div_ceil(4);
+                let rows = m.words().div_ceil(COLUMNS);
+                let row_height = ui.text_style_height(&eframe::egui::TextStyle::Monospace) + 6.0;
+                eframe::egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show_rows(ui, row_height, rows, |ui, range| {
+                        for row in range {
+                            ui.horizontal(|ui| {
+                                ui.monospace(format!("{:04x}:", row * COLUMNS));
+                                for address in
+                                    row * COLUMNS..(row * COLUMNS + COLUMNS).min(m.words())
+                                {
+                                    match edit {
+                                        Some((a, text)) if *a == address => {
+                                            let response = ui.add(
+                                                eframe::egui::TextEdit::singleline(text)
+                                                    .font(eframe::egui::TextStyle::Monospace)
+                                                    .desired_width(digits as f32 * 8.0 + 8.0),
+                                            );
+                                            if !response.has_focus() && !response.lost_focus() {
+                                                response.request_focus();
+                                            }
+                                            if response.lost_focus() {
+                                                if let Ok(v) = u64::from_str_radix(text.trim(), 16)
+                                                {
+                                                    m.write(address, v);
+                                                }
+                                                *edit = None;
+                                            }
+                                        }
+                                        _ => {
+                                            let text = eframe::egui::RichText::new(format!(
+                                                "{:0digits$x}",
+                                                m.read(address),
+                                                digits = digits
+                                            ))
+                                            .monospace();
+                                            if ui
+                                                .selectable_label(address == current, text)
+                                                .clicked()
+                                            {
+                                                *edit = Some((
+                                                    address,
+                                                    format!("{:x}", m.read(address)),
+                                                ));
+                                            }
+                                        }
+                                    }
+                                }
+                            });
+                        }
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.text_edit_singleline(path);
+                    eframe::egui::ComboBox::from_id_source("memory_format")
+                        .selected_text(format.label())
+                        .show_ui(ui, |ui| {
+                            for f in MemoryFormat::ALL {
+                                ui.selectable_value(format, f, f.label());
+                            }
+                        });
+                    if ui.button("Load").clicked() {
+                        action = Some(true);
+                    }
+                    if ui.button("Save").clicked() {
+                        action = Some(false);
+                    }
+                });
+            });
+        match action {
+            Some(true) => {
+                let result = self.load_memory();
+                self.report(result);
+            }
+            Some(false) => {
+                let result = self.save_memory();
+                self.report(result);
+            }
+            None => {}
+        }
+        if !open {
+            self.memory_view = None;
+            self.memory_edit = None;
+        }
+    }
+
+    /// The board with the position of every gate and the bends of its wires.
+    /// Board inputs and outputs that were removed are left out.
+    pub fn project(&self) -> Project {
+        let mut positions: HashMap<GateId, Pos2> =
+            self.nodes.iter().map(|n| (n.gate_id, n.pos)).collect();
+        for id in self.global_input_ids.iter().chain(&self.global_output_ids) {
+            if let Some(&pos) = self.io_positions.get(id) {
+                positions.insert(*id, pos);
+            }
+        }
+        let mut board = self.chip.clone();
+        let removed: Vec<GateId> = board
+            .gates
+            .keys()
+            .filter(|id| !positions.contains_key(id))
+            .copied()
+            .collect();
+        for id in removed {
+            board.remove_gate(&id);
+        }
+        Project {
+            board,
+            positions,
+            wire_bends: self.wire_bends.clone(),
+        }
+    }
+
+    /// Replaces the board with `project`, dropping everything that refers to
+    /// gates of the old one.
+    pub fn set_project(&mut self, project: Project) {
+        let Project {
+            board,
+            positions,
+            wire_bends,
+        } = project;
+        let mut ids: Vec<GateId> = board.gates.keys().copied().collect();
+        ids.sort();
+        self.nodes.clear();
+        self.global_input_ids.clear();
+        self.global_output_ids.clear();
+        self.io_positions.clear();
+        for id in ids {
+            let gate = &board.gates[&id];
+            let pos = positions.get(&id).copied().unwrap_or(Pos2::ZERO);
+            match gate {
+                Gate::Source(_) => {
+                    self.global_input_ids.push(id);
+                    self.io_positions.insert(id, pos);
+                }
+                Gate::Output(_) => {
+                    self.global_output_ids.push(id);
+                    self.io_positions.insert(id, pos);
+                }
+                _ => self.nodes.push(VisualNode {
+                    gate_id: id,
+                    pos,
+                    inputs: gate.input().to_vec(),
+                    outputs: gate.output().to_vec(),
+                    label: gate_label(gate),
+                }),
+            }
+        }
+        self.input_count = self.global_input_ids.len();
+        self.output_count = self.global_output_ids.len();
+        self.chip = board;
+        self.wire_bends = wire_bends;
+        self.selected.clear();
+        self.memory_view = None;
+        self.memory_edit = None;
+        self.terminal_view = None;
+        self.program_view = None;
+        self.watch = WatchList::default();
+        self.debugger = Debugger::default();
+        self.fault_report = None;
+        self.atpg_report = None;
+        self.timing = None;
+        self.critical_gates.clear();
+        self.critical_wires.clear();
+        self.stats = None;
+    }
+
+    /// Writes the board, its layout and its wire bends to `project_path`.
+    pub fn save_project(&self) -> Result<(), LgsimError> {
+        let text = project::write_project(&self.project())?;
+        std::fs::write(&self.project_path, text)?;
+        Ok(())
+    }
+
+    /// Replaces the board with the one saved at `project_path`.
+    pub fn load_project(&mut self) -> Result<(), LgsimError> {
+        let text = std::fs::read_to_string(&self.project_path)?;
+        self.set_project(project::read_project(&text)?);
+        Ok(())
+    }
+
+    /// Writes every template to `library_path` as library `library_source`.
+    pub fn export_library(&self) -> Result<(), LgsimError> {
+        let names: Vec<&str> = self.library.names().collect();
+        let text = self.library.export(&names, &self.library_source)?;
+        std::fs::write(&self.library_path, text)?;
+        Ok(())
+    }
+
+    /// Imports the library file at `library_path`, returning how many
+    /// templates it brought in.
+    pub fn import_library(&mut self) -> Result<usize, LgsimError> {
+        let text = std::fs::read_to_string(&self.library_path)?;
+        Ok(self.library.import(&text)?.len())
+    }
+
+    fn library_entry(&mut self, ui: &mut Ui, name: &str) {
+        let mut response = ui.button(format!("Add {}", name));
+        if let Some(source) = self.library.template(name).and_then(|t| t.source.as_ref()) {
+            response = response.on_hover_text(format!(
+                "From library \"{}\" version {}",
+                source.name, source.version
+            ));
+        }
+        if response.clicked() {
+            let result = self.add_custom_chip(name, self.view_center());
+            self.report(result);
+        }
+        response.context_menu(|ui| {
+            if ui.button("Rename...").clicked() {
+                self.library_edit = Some(LibraryEdit::Rename {
+                    name: name.to_string(),
+                    new_name: name.to_string(),
+                });
+                ui.close_menu();
+            }
+            if ui.button("Duplicate...").clicked() {
+                self.library_edit = Some(LibraryEdit::Duplicate {
+                    name: name.to_string(),
+                    new_name: format!("{} copy", name),
+                });
+                ui.close_menu();
+            }
+            if ui.button("Move to Folder...").clicked() {
+                let folder = self
+                    .library
+                    .template(name)
+                    .map(|t| t.folder.clone())
+                    .unwrap_or_default();
+                self.library_edit = Some(LibraryEdit::Move {
+                    name: name.to_string(),
+                    folder,
+                });
+                ui.close_menu();
+            }
+            if ui.button("Delete").clicked() {
+                let result = self.library.remove(name, &self.chip);
+                self.report(result);
+                ui.close_menu();
+            }
+        });
+    }
+
+    pub fn register_visual_node(&mut self, gate: Gate, pos: Pos2, custom_label: String) {
+        let id = gate.id();
+        let inputs = gate.input().to_vec();
+        let outputs = gate.output().to_vec();
+        let label = match gate {
+            Gate::Chip(_) => custom_label,
+            _ => gate_label(&gate),
+        };
+
+        self.chip.add_gate(gate);
+        self.nodes.push(VisualNode {
+            gate_id: id,
+            pos,
+            inputs,
+            outputs,
+            label,
+        });
+    }
+
+    pub fn sync_io(&mut self) {
+        while self.global_input_ids.len() < self.input_count {
+            let gate = Gate::new(GateType::Source, vec![], &mut self.chip.ids);
+            let y = IO_TOP + self.global_input_ids.len() as f32 * IO_SPACING;
+            let id = self.chip.add_gate(gate);
+            self.io_positions.insert(id, Pos2::new(IO_INPUT_X, y));
+            self.global_input_ids.push(id);
+        }
+        while self.global_input_ids.len() > self.input_count {
+            self.global_input_ids.pop();
+        }
+        while self.global_output_ids.len() < self.output_count {
+            let gate = Gate::new(GateType::Output, vec![], &mut self.chip.ids);
+            let y = IO_TOP + self.global_output_ids.len() as f32 * IO_SPACING;
+            let id = self.chip.add_gate(gate);
+            self.io_positions.insert(id, Pos2::new(IO_OUTPUT_X, y));
+            self.global_output_ids.push(id);
+        }
+        while self.global_output_ids.len() > self.output_count {
+            self.global_output_ids.pop();
+        }
+    }
+
+    pub fn create_abstract_chip(&mut self) -> Result<(), LgsimError> {
+        self.check_template_name()?;
+        // Use 0 temporarily, id assignment happens in deep_copy for components
+        let mut template = Chip::new(ChipId(0));
+        let mut id_map: HashMap<PinId, PinId> = HashMap::new();
+
+        let mut shell_input_map: HashMap<PinId, PinId> = HashMap::new();
+        for &gid in &self.global_input_ids {
+            let src_gate = self
+                .chip
+                .gates
+                .get(&gid)
+                .ok_or(LgsimError::UnknownGate(gid))?;
+            let src_pin = src_gate.output()[0];
+            let new_shell_id = template.add_shell_pin(crate::pin::PinType::ChipInput);
+            shell_input_map.insert(src_pin, new_shell_id);
+        }
+
+        let mut shell_output_map: HashMap<PinId, PinId> = HashMap::new();
+        for &gid in &self.global_output_ids {
+            let dest_gate = self
+                .chip
+                .gates
+                .get(&gid)
+                .ok_or(LgsimError::UnknownGate(gid))?;
+            let dest_pin = dest_gate.input()[0];
+            let new_shell_id = template.add_shell_pin(crate::pin::PinType::ChipOutput);
+            shell_output_map.insert(dest_pin, new_shell_id);
+        }
+
+        for node in &self.nodes {
+            if let Some(gate) = self.chip.gates.get(&node.gate_id) {
+                let new_gate = gate.clone_with_new_ids(&mut id_map, &mut template.ids)?;
+                template.add_gate(new_gate);
+            }
+        }
+
+        for (src, dests) in &self.chip.connections {
+            if let Some(&shell_in) = shell_input_map.get(src) {
+                for dst in dests {
+                    if let Some(&mapped_dst) = id_map.get(dst) {
+                        template.connect_pins(shell_in, mapped_dst)?;
+                    }
+                }
+                continue;
+            }
+
+            if let Some(&mapped_src) = id_map.get(src) {
+                for dst in dests {
+                    if let Some(&shell_out) = shell_output_map.get(dst) {
+                        template.connect_pins(mapped_src, shell_out)?;
+                    } else if let Some(&mapped_dst) = id_map.get(dst) {
+                        template.connect_pins(mapped_src, mapped_dst)?;
+                    }
+                }
+            }
+        }
+
+        self.store_template(template);
+
+        // Optional: Reset board after abstracting
+        self.nodes.clear();
+        self.chip = Chip::new(ChipId(0));
+        self.global_input_ids.clear();
+        self.global_output_ids.clear();
+        self.io_positions.clear();
+        self.selected.clear();
+        self.wire_bends.clear();
+        Ok(())
+    }
+
+    /// Turns the selected gates into a new chip named `abstract_name` and
+    /// puts one instance of it where they were, wired to the same nets.
+    pub fn extract_selection(&mut self) -> Result<(), LgsimError> {
+        self.check_template_name()?;
+        let positions: Vec<Pos2> = self
+            .nodes
+            .iter()
+            .filter(|n| self.selected.contains(&n.gate_id))
+            .map(|n| n.pos)
+            .collect();
+        let extraction =
+            edit::extract_subcircuit(&mut self.chip, &self.selected, &self.abstract_name)?;
+        let center = positions
+            .iter()
+            .fold(Vec2::ZERO, |acc, p| acc + p.to_vec2())
+            / positions.len().max(1) as f32;
+        let center = if self.snap_to_grid {
+            snap_to_grid(center.to_pos2())
+        } else {
+            center.to_pos2()
+        };
+
+        let selected = std::mem::take(&mut self.selected);
+        self.nodes.retain(|n| !selected.contains(&n.gate_id));
+        let gate = self
+            .chip
+            .gates
+            .get(&extraction.instance)
+            .ok_or(LgsimError::UnknownGate(extraction.instance))?;
+        self.nodes.push(VisualNode {
+            gate_id: extraction.instance,
+            pos: center,
+            inputs: gate.input().to_vec(),
+            outputs: gate.output().to_vec(),
+            label: self.abstract_name.clone(),
+        });
+        self.store_template(extraction.template);
+        self.prune_wire_bends();
+        self.selected.insert(extraction.instance);
+        Ok(())
+    }
+
+    /// Replaces every selected chip instance with its internal gates, laid
+    /// out in a grid where the instance was. Other selected gates are left
+    /// alone.
+    pub fn inline_selection(&mut self) -> Result<(), LgsimError> {
+        let mut instances: Vec<GateId> = self
+            .selected
+            .iter()
+            .filter(|id| matches!(self.chip.gates.get(id), Some(Gate::Chip(_))))
+            .copied()
+            .collect();
+        if instances.is_empty() {
+            return Err(LgsimError::EmptySelection);
+        }
+        instances.sort();
+        for instance in instances {
+            let Some(idx) = self.nodes.iter().position(|n| n.gate_id == instance) else {
+                continue;
+            };
+            let center = self.nodes[idx].pos;
+            let moved = edit::inline_chip(&mut self.chip, &instance)?;
+            self.nodes.remove(idx);
+            self.selected.remove(&instance);
+
+            let columns = (moved.len() as f32).sqrt().ceil().max(1.0) as usize;
+            let rows = moved.len().div_ceil(columns);
+            let step = NODE_SIZE + Vec2::new(40.0, 20.0);
+            let origin = center
+                - Vec2::new(
+                    (columns - 1) as f32 * step.x / 2.0,
+                    rows.saturating_sub(1) as f32 * step.y / 2.0,
+                );
+            for (k, gid) in moved.into_iter().enumerate() {
+                let Some(gate) = self.chip.gates.get(&gid) else {
+                    continue;
+                };
+                let pos = origin + Vec2::new((k % columns) as f32, (k / columns) as f32) * step;
+                let label = gate_label(gate);
+                self.nodes.push(VisualNode {
+                    gate_id: gid,
+                    pos: if self.snap_to_grid {
+                        snap_to_grid(pos)
+                    } else {
+                        pos
+                    },
+                    inputs: gate.input().to_vec(),
+                    outputs: gate.output().to_vec(),
+                    label,
+                });
+                self.selected.insert(gid);
+            }
+        }
+        self.prune_wire_bends();
+        Ok(())
+    }
+
+    /// World polyline of the wire `src -> dest`.
+    pub fn wire_path(&self, src: &PinId, dest: &PinId) -> Option<Vec<Pos2>> {
+        let from = self.pin_position(src)?;
+        let to = self.pin_position(dest)?;
+        let bends = self
+            .wire_bends
+            .get(&(*src, *dest))
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        Some(routing::manhattan_path(from, to, bends))
+    }
+
+    /// Adds a bend point to the wire under `screen`, if there is one.
+    pub fn add_bend_at(&mut self, screen: Pos2) {
+        let world = self.camera.to_world(screen);
+        let tolerance = 6.0 / self.camera.zoom;
+        let hit = self
+            .chip
+            .wires()
+            .into_iter()
+            .filter_map(|(src, dest)| {
+                let path = self.wire_path(&src, &dest)?;
+                Some(((src, dest), routing::distance_to_path(world, &path)))
+            })
+            .filter(|(_, d)| *d <= tolerance)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some(((src, dest), _)) = hit else {
+            return;
+        };
+        let (Some(from), Some(to)) = (self.pin_position(&src), self.pin_position(&dest)) else {
+            return;
+        };
+        let bend = if self.snap_to_grid {
+            snap_to_grid(world)
+        } else {
+            world
+        };
+        let bends = self.wire_bends.entry((src, dest)).or_default();
+        let idx = routing::bend_insert_index(from, to, bends, bend);
+        bends.insert(idx, bend);
+    }
+
+    /// Replaces the bend points of every wire with auto-routed ones that keep
+    /// clear of gate bodies. Wires the router cannot place are left alone.
+    pub fn auto_route_wires(&mut self) {
+        let io_bodies = self
+            .io_positions
+            .values()
+            .map(|p| Rect::from_center_size(*p, Vec2::new(30.0, 24.0)));
+        let obstacles: Vec<Rect> = self
+            .nodes
+            .iter()
+            .map(VisualNode::rect)
+            .chain(io_bodies)
+            .collect();
+        let mut failed = 0;
+        for (src, dest) in self.chip.wires() {
+            let (Some(from), Some(to)) = (self.pin_position(&src), self.pin_position(&dest)) else {
+                continue;
+            };
+            match routing::auto_route(from, to, &obstacles) {
+                Some(bends) => {
+                    self.wire_bends.insert((src, dest), bends);
+                }
+                None => failed += 1,
+            }
+        }
+        if failed > 0 {
+            self.toasts.push(Toast {
+                text: format!("could not route {} wire(s)", failed),
+                shown_at: None,
+            });
+        }
+    }
+
+    /// Forgets bend points of wires that no longer exist.
+    fn prune_wire_bends(&mut self) {
+        let connections = &self.chip.connections;
+        self.wire_bends
+            .retain(|(src, dest), _| connections.get(src).is_some_and(|d| d.contains(dest)));
+    }
+
+    pub fn select(&mut self, gate: GateId, additive: bool) {
+        if !additive {
+            self.selected.clear();
+        } else if self.selected.remove(&gate) {
+            return;
+        }
+        self.selected.insert(gate);
+    }
+
+    /// Selects every node touching `area` (world coordinates).
+    pub fn select_in_rect(&mut self, area: Rect, additive: bool) {
+        if !additive {
+            self.selected.clear();
+        }
+        for node in &self.nodes {
+            if node.rect().intersects(area) {
+                self.selected.insert(node.gate_id);
+            }
+        }
+    }
+
+    /// Whether the wire `src -> dest` runs between two selected nodes.
+    pub fn is_wire_selected(&self, src: &PinId, dest: &PinId) -> bool {
+        let on_selected = |pin: &PinId| {
+            self.nodes.iter().any(|n| {
+                self.selected.contains(&n.gate_id)
+                    && (n.inputs.contains(pin) || n.outputs.contains(pin))
+            })
+        };
+        on_selected(src) && on_selected(dest)
+    }
+
+    /// Copies the selected nodes and the wires between them to the clipboard.
+    pub fn copy_selection(&mut self) {
+        let picked: Vec<&VisualNode> = self
+            .nodes
+            .iter()
+            .filter(|n| self.selected.contains(&n.gate_id))
+            .collect();
+        let Some(bounds) = picked.iter().map(|n| n.rect()).reduce(|a, b| a.union(b)) else {
+            return;
+        };
+        let mut entries = Vec::new();
+        let mut pins = HashSet::new();
+        for node in picked {
+            let Some(gate) = self.chip.gates.get(&node.gate_id) else {
+                continue;
+            };
+            pins.extend(gate.input().iter().chain(gate.output()).copied());
+            entries.push(ClipboardEntry {
+                gate: gate.clone(),
+                label: node.label.clone(),
+                offset: node.pos - bounds.center(),
+            });
+        }
+        let mut connections = Vec::new();
+        let mut wire_bends = HashMap::new();
+        for (src, dests) in &self.chip.connections {
+            if pins.contains(src) {
+                for dest in dests.iter().filter(|d| pins.contains(*d)) {
+                    connections.push((*src, *dest));
+                    if let Some(bends) = self.wire_bends.get(&(*src, *dest)) {
+                        let relative = bends.iter().map(|b| *b - bounds.center()).collect();
+                        wire_bends.insert((*src, *dest), relative);
+                    }
+                }
+            }
+        }
+        self.clipboard = Clipboard {
+            entries,
+            connections,
+            wire_bends,
+        };
+    }
+
+    /// Pastes fresh copies of the clipboard centered on `at` (world
+    /// coordinates) and selects them.
+    pub fn paste_clipboard(&mut self, at: Pos2) -> Result<(), LgsimError> {
+        let clipboard = self.clipboard.clone();
+        let mut id_map: HashMap<PinId, PinId> = HashMap::new();
+        let mut pasted = HashSet::new();
+        for entry in &clipboard.entries {
+            let gate = entry
+                .gate
+                .clone_with_new_ids(&mut id_map, &mut self.chip.ids)?;
+            let mut pos = at + entry.offset;
+            if self.snap_to_grid {
+                pos = snap_to_grid(pos);
+            }
+            pasted.insert(gate.id());
+            self.register_visual_node(gate, pos, entry.label.clone());
+        }
+        for (src, dest) in &clipboard.connections {
+            if let (Some(&new_src), Some(&new_dest)) = (id_map.get(src), id_map.get(dest)) {
+                self.chip.connect_pins(new_src, new_dest)?;
+                if let Some(bends) = clipboard.wire_bends.get(&(*src, *dest)) {
+                    let placed = bends.iter().map(|b| at + *b).collect();
+                    self.wire_bends.insert((new_src, new_dest), placed);
+                }
+            }
+        }
+        self.selected = pasted;
+        Ok(())
+    }
+
+    fn handle_clipboard_keys(&mut self, ctx: &eframe::egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let command = eframe::egui::Modifiers::COMMAND;
+        let (copy, paste) = ctx.input_mut(|i| {
+            (
+                i.consume_key(command, eframe::egui::Key::C),
+                i.consume_key(command, eframe::egui::Key::V),
+            )
+        });
+        if copy {
+            self.copy_selection();
+        }
+        if paste {
+            let at = ctx
+                .pointer_hover_pos()
+                .filter(|p| self.camera.viewport.contains(*p))
+                .map(|p| self.camera.to_world(p))
+                .unwrap_or_else(|| self.view_center());
+            let result = self.paste_clipboard(at);
+            self.report(result);
+        }
+    }
+
+    /// Sends typed text to the selected keyboards, Enter as a newline and
+    /// Backspace as 8.
+    fn handle_keyboard_typing(&mut self, ctx: &eframe::egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let has_keyboard = self
+            .selected
+            .iter()
+            .any(|id| matches!(self.chip.gates.get(id), Some(Gate::Keyboard(_))));
+        if !has_keyboard {
+            return;
+        }
+        let typed: Vec<u8> = ctx.input(|i| {
+            i.events
+                .iter()
+                .flat_map(|event| match event {
+                    eframe::egui::Event::Text(text) => text.bytes().filter(u8::is_ascii).collect(),
+                    eframe::egui::Event::Key {
+                        key: eframe::egui::Key::Enter,
+                        pressed: true,
+                        ..
+                    } => vec![b'\n'],
+                    eframe::egui::Event::Key {
+                        key: eframe::egui::Key::Backspace,
+                        pressed: true,
+                        ..
+                    } => vec![8],
+                    _ => vec![],
+                })
+                .collect()
+        });
+        for id in &self.selected {
+            if let Some(Gate::Keyboard(k)) = self.chip.gates.get_mut(id) {
+                k.type_bytes(&typed);
+            }
+        }
+    }
+
+    /// World position of a pin on the board, if it is drawn.
+    pub fn pin_position(&self, pin: &PinId) -> Option<Pos2> {
+        let has_pin = |gid: &GateId, on_output: bool| {
+            self.chip.gates.get(gid).is_some_and(|g| {
+                let pins = if on_output { g.output() } else { g.input() };
+                pins.contains(pin)
+            })
+        };
+        if let Some(gid) = self.global_input_ids.iter().find(|g| has_pin(g, true)) {
+            return Some(*self.io_positions.get(gid)? + Vec2::new(IO_PIN_OFFSET, 0.0));
+        }
+        if let Some(gid) = self.global_output_ids.iter().find(|g| has_pin(g, false)) {
+            return Some(*self.io_positions.get(gid)? - Vec2::new(IO_PIN_OFFSET, 0.0));
+        }
+        self.nodes.iter().find_map(|n| {
+            if let Some(idx) = n.inputs.iter().position(|p| p == pin) {
+                Some(n.input_pos(idx))
+            } else {
+                let idx = n.outputs.iter().position(|p| p == pin)?;
+                Some(n.output_pos(idx))
+            }
+        })
+    }
+
+    /// World rect covering every node and global input/output.
+    pub fn board_bounds(&self) -> Option<Rect> {
+        let io = self
+            .io_positions
+            .values()
+            .map(|p| Rect::from_center_size(*p, Vec2::splat(2.0 * IO_PIN_OFFSET + 10.0)));
+        self.nodes
+            .iter()
+            .map(VisualNode::rect)
+            .chain(io)
+            .reduce(|a, b| a.union(b))
+    }
+
+    /// Middle-drag or space-drag pans, the scroll wheel zooms around the
+    /// cursor. Returns whether the view is being panned this frame.
+    fn handle_camera_input(&mut self, ui: &Ui) -> bool {
+        let (hover, scroll, middle, space, primary, delta) = ui.input(|i| {
+            (
+                i.pointer.hover_pos(),
+                i.scroll_delta.y,
+                i.pointer.middle_down(),
+                i.key_down(eframe::egui::Key::Space),
+                i.pointer.primary_down(),
+                i.pointer.delta(),
+            )
+        });
+        let Some(hover) = hover.filter(|p| self.camera.viewport.contains(*p)) else {
+            return false;
+        };
+        let panning = middle || (space && primary);
+        if panning {
+            self.camera.pan += delta;
+        }
+        if scroll != 0.0 {
+            self.camera.zoom_around(hover, (scroll * 0.002).exp());
+        }
+        panning
+    }
+
+    fn draw_grid(&self, ui: &Ui) {
+        let step = GRID_SIZE * self.camera.zoom;
+        if step < 8.0 {
+            return;
+        }
+        let view = self.camera.viewport;
+        let start = snap_to_grid(self.camera.to_world(view.min));
+        let color = Color32::from_gray(45);
+        let mut x = self.camera.to_screen(start).x;
+        while x <= view.max.x {
+            let mut y = self.camera.to_screen(start).y;
+            while y <= view.max.y {
+                ui.painter().circle_filled(Pos2::new(x, y), 1.0, color);
+                y += step;
+            }
+            x += step;
+        }
+    }
+
+    fn view_center(&self) -> Pos2 {
+        self.camera.to_world(self.camera.viewport.center())
+    }
+
+    fn draw_toasts(&mut self, ctx: &eframe::egui::Context) {
+        let now = ctx.input(|i| i.time);
+        self.toasts
+            .retain(|t| t.shown_at.is_none_or(|at| now - at < TOAST_SECONDS));
+        if self.toasts.is_empty() {
+            return;
+        }
+        eframe::egui::Area::new("toasts")
+            .anchor(eframe::egui::Align2::RIGHT_BOTTOM, Vec2::new(-10.0, -10.0))
+            .show(ctx, |ui| {
+                for toast in &mut self.toasts {
+                    toast.shown_at.get_or_insert(now);
+                    eframe::egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.colored_label(Color32::LIGHT_RED, &toast.text);
+                    });
+                }
+            });
+        ctx.request_repaint();
+    }
+}
+
+impl eframe::App for LogicApp {
+    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        self.sync_io();
+        self.handle_clipboard_keys(ctx);
+        self.handle_keyboard_typing(ctx);
+        if self.auto_sim && !self.step() {
+            self.auto_sim = false;
+        }
+        if self.run_clock && !self.clock_step() {
+            self.run_clock = false;
+        }
+
+        eframe::egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Controls:");
+                if ui.button("RUN").clicked() {
+                    self.step();
+                }
+                ui.checkbox(&mut self.auto_sim, "Auto-Sim");
+                if ui.selectable_label(self.show_watch, "Watch").clicked() {
+                    self.show_watch = !self.show_watch;
+                }
+                if ui.selectable_label(self.show_faults, "Faults").clicked() {
+                    self.show_faults = !self.show_faults;
+                }
+                if ui.selectable_label(self.show_timing, "Timing").clicked() {
+                    self.show_timing = !self.show_timing;
+                }
+                if ui.selectable_label(self.show_stats, "Stats").clicked() {
+                    self.show_stats = !self.show_stats;
+                }
+                ui.separator();
+                if ui.button("ABSTRACT CIRCUIT").clicked() {
+                    self.abstract_selection = false;
+                    self.show_abstract_window = true;
+                }
+                if ui.button("Extract Selection").clicked() {
+                    if self.selected.is_empty() {
+                        self.report::<()>(Err(LgsimError::EmptySelection));
+                    } else {
+                        self.abstract_selection = true;
+                        self.show_abstract_window = true;
+                    }
+                }
+                if ui.button("Inline Chip").clicked() {
+                    let result = self.inline_selection();
+                    self.report(result);
+                }
+                ui.separator();
+                ui.label("In:");
+                ui.add(eframe::egui::Slider::new(&mut self.input_count, 1..=16));
+                ui.label("Out:");
+                ui.add(eframe::egui::Slider::new(&mut self.output_count, 1..=16));
+                ui.separator();
+                ui.checkbox(&mut self.snap_to_grid, "Snap");
+                if ui.button("Fit").clicked()
+                    && let Some(bounds) = self.board_bounds()
+                {
+                    self.camera.fit(bounds.expand(20.0));
+                }
+                ui.label(format!("{:.0}%", self.camera.zoom * 100.0));
+                ui.separator();
+                if ui.button("Copy").clicked() {
+                    self.copy_selection();
+                }
+                if ui.button("Paste").clicked() {
+                    let result = self.paste_clipboard(self.view_center());
+                    self.report(result);
+                }
+                ui.separator();
+                if ui.button("Route Wires").clicked() {
+                    self.auto_route_wires();
+                }
+                ui.separator();
+                if ui.button("Library File").clicked() {
+                    self.show_library_file = true;
+                }
+                if ui.button("Project File").clicked() {
+                    self.show_project_file = true;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Step:");
+                for mode in StepMode::ALL {
+                    if ui
+                        .selectable_label(self.debugger.mode == mode, mode.label())
+                        .clicked()
+                    {
+                        self.debugger.mode = mode;
+                    }
+                }
+                if ui.button("Step").clicked() {
+                    self.debug_step();
+                }
+                ui.separator();
+                let clock_label = |id: Option<GateId>, inputs: &[GateId]| {
+                    id.and_then(|id| inputs.iter().position(|g| *g == id))
+                        .map_or("none".to_string(), |k| format!("In {}", k))
+                };
+                eframe::egui::ComboBox::from_label("Clock")
+                    .selected_text(clock_label(self.debugger.clock, &self.global_input_ids))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.debugger.clock, None, "none");
+                        for (k, id) in self.global_input_ids.iter().enumerate() {
+                            ui.selectable_value(
+                                &mut self.debugger.clock,
+                                Some(*id),
+                                format!("In {}", k),
+                            );
+                        }
+                    });
+                ui.checkbox(&mut self.run_clock, "Run Clock");
+            });
+        });
+
+        eframe::egui::SidePanel::right("right_panel").show(ctx, |ui| {
+            ui.heading("Tools");
+            if ui.button("Add AND").clicked() {
+                self.add_gate(GateType::And, self.view_center());
+            }
+            if ui.button("Add NOT").clicked() {
+                self.add_gate(GateType::Not, self.view_center());
+            }
+
+            ui.separator();
+            ui.heading("Memory");
+            ui.horizontal(|ui| {
+                ui.label("Address bits:");
+                ui.add(
+                    eframe::egui::DragValue::new(&mut self.memory_address_bits)
+                        .clamp_range(1..=memory::MAX_ADDRESS_BITS),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Data bits:");
+                ui.add(
+                    eframe::egui::DragValue::new(&mut self.memory_data_bits)
+                        .clamp_range(1..=memory::MAX_DATA_BITS),
+                );
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Add RAM").clicked() {
+                    let result = self.add_memory(MemoryKind::Ram, self.view_center());
+                    self.report(result);
+                }
+                if ui.button("Add ROM").clicked() {
+                    let result = self.add_memory(MemoryKind::Rom, self.view_center());
+                    self.report(result);
+                }
+            });
+
+            ui.separator();
+            ui.heading("Sequential");
+            eframe::egui::ComboBox::from_id_source("register_kind")
+                .selected_text(self.register_kind.label())
+                .show_ui(ui, |ui| {
+                    for k in RegisterKind::ALL {
+                        ui.selectable_value(&mut self.register_kind, k, k.label());
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.label("Width:");
+                ui.add(
+                    eframe::egui::DragValue::new(&mut self.register_width)
+                        .clamp_range(1..=register::MAX_REGISTER_BITS),
+                );
+            });
+            if ui.button(format!("Add {}", self.register_kind.label())).clicked() {
+                let result = self.add_register(self.view_center());
+                self.report(result);
+            }
+
+            ui.separator();
+            ui.heading("Inputs");
+            eframe::egui::ComboBox::from_id_source("switch_kind")
+                .selected_text(self.switch_kind.label())
+                .show_ui(ui, |ui| {
+                    for k in SwitchKind::ALL {
+                        ui.selectable_value(&mut self.switch_kind, k, k.label());
+                    }
+                });
+            match self.switch_kind {
+                SwitchKind::DipSwitch => {
+                    ui.horizontal(|ui| {
+                        ui.label("Switches:");
+                        ui.add(
+                            eframe::egui::DragValue::new(&mut self.dip_switches)
+                                .clamp_range(1..=switch::MAX_DIP_SWITCHES),
+                        );
+                    });
+                }
+                SwitchKind::Constant => {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.tie_high, false, "0");
+                        ui.radio_value(&mut self.tie_high, true, "1");
+                    });
+                }
+                _ => {}
+            }
+            if ui.button(format!("Add {}", self.switch_kind.label())).clicked() {
+                let result = self.add_switch(self.view_center());
+                self.report(result);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add Keyboard").clicked() {
+                    self.add_gate(GateType::Keyboard, self.view_center());
+                }
+                if ui.button("Add Terminal").clicked() {
+                    self.add_gate(GateType::Terminal, self.view_center());
+                }
+            });
+
+            ui.separator();
+            ui.heading("Displays");
+            eframe::egui::ComboBox::from_id_source("display_kind")
+                .selected_text(self.display_kind.label())
+                .show_ui(ui, |ui| {
+                    for k in DisplayKind::ALL {
+                        ui.selectable_value(&mut self.display_kind, k, k.label());
+                    }
+                });
+            match self.display_kind {
+                DisplayKind::Led => {
+                    ui.horizontal(|ui| {
+                        ui.label("Color:");
+                        ui.color_edit_button_srgb(&mut self.led_color);
+                    });
+                }
+                DisplayKind::PixelMatrix => {
+                    ui.horizontal(|ui| {
+                        ui.label("Size:");
+                        ui.add(
+                            eframe::egui::DragValue::new(&mut self.matrix_columns)
+                                .clamp_range(1..=display::MAX_MATRIX_SIZE),
+                        );
+                        ui.label("x");
+                        ui.add(
+                            eframe::egui::DragValue::new(&mut self.matrix_rows)
+                                .clamp_range(1..=display::MAX_MATRIX_SIZE),
+                        );
+                    });
+                }
+                _ => {}
+            }
+            if ui.button(format!("Add {}", self.display_kind.label())).clicked() {
+                let result = self.add_display(self.view_center());
+                self.report(result);
+            }
+
+            ui.separator();
+            ui.heading("Components");
+            eframe::egui::ComboBox::from_id_source("component")
+                .selected_text(self.component.label())
+                .show_ui(ui, |ui| {
+                    for c in Component::ALL {
+                        ui.selectable_value(&mut self.component, c, c.label());
+                    }
+                });
+            if let Some(max) = self.component.max_width() {
+                ui.horizontal(|ui| {
+                    ui.label("Width:");
+                    ui.add(eframe::egui::DragValue::new(&mut self.component_width).clamp_range(1..=max));
+                });
+                self.component_width = self.component_width.clamp(1, max);
+            }
+            if ui
+                .button(format!("Add {}", self.component.template_name(self.component_width)))
+                .clicked()
+            {
+                let result = self.add_component(self.view_center());
+                self.report(result);
+            }
+
+            ui.separator();
+            ui.heading("Chip Library");
+
+            let folders: Vec<(String, Vec<String>)> = self
+                .library
+                .folders()
+                .into_iter()
+                .map(|(folder, names)| {
+                    let names = names.into_iter().map(str::to_string).collect();
+                    (folder.to_string(), names)
+                })
+                .collect();
+            for (folder, names) in folders {
+                if folder.is_empty() {
+                    for name in &names {
+                        self.library_entry(ui, name);
+                    }
+                } else {
+                    eframe::egui::CollapsingHeader::new(&folder)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for name in &names {
+                                self.library_entry(ui, name);
+                            }
+                        });
+                }
+            }
+
+            ui.separator();
+            ui.label(
+                "Drag gates to move.\nDrag Output -> Input.\nMiddle/Space-drag to pan, scroll to zoom.\nDrag on empty space to select, Ctrl+C/Ctrl+V to copy.\nDouble-click a wire to add a bend.\nRight-click a chip to rename, duplicate, move or delete it.\nDouble-click a RAM/ROM to edit its contents, a terminal to read it,\na chip with a ROM (e.g. the Reference Computer) to program it;\nright-click a chip to edit the RAMs and ROMs inside it.\nSelect a keyboard and type to send it keys.\nClick buttons, switches and keypads to operate them.\nRight-click a gate to probe its pins; Watch lists probes and records waveforms.\nBreakpoints in Watch pause Auto-Sim and Run Clock; Step advances one event,\ndelta cycle or clock edge and highlights the nets that changed.\nFaults simulates stuck-at faults against test vectors and outlines gates\nwith undetected faults; Generate (ATPG) writes a compact test set for\ncombinational boards, which `lgsim run --vectors` can check.\nTiming reports logic depth per output and highlights the critical path.\nStats counts gates by type through nested chips and estimates transistors.\nProject File saves the board with its layout and wire bends, and loads it back.",
+            );
+        });
+
+        if self.show_abstract_window {
+            eframe::egui::Window::new("Name Your Chip")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.text_edit_singleline(&mut self.abstract_name);
+                    ui.horizontal(|ui| {
+                        if ui.button("Create").clicked() && !self.abstract_name.is_empty() {
+                            self.finish_abstraction();
+                            self.show_abstract_window = false;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.show_abstract_window = false;
+                        }
+                    });
+                });
         }
-        while self.global_output_ids.len() < self.output_count {
-            let gate = Gate::new(GateType::Output, vec![]);
-            self.global_output_ids.push(self.chip.add_gate(gate));
+
+        if self.memory_view.is_some() {
+            self.memory_window(ctx);
         }
-        while self.global_output_ids.len() > self.output_count {
-            self.global_output_ids.pop();
+
+        if self.program_view.is_some() {
+            self.program_window(ctx);
         }
-    }
 
-    pub fn create_abstract_chip(&mut self) {
-        // Use 0 temporarily, id assignment happens in deep_copy for components
-        let mut template = Chip::new(0);
-        let mut id_map: HashMap<usize, usize> = HashMap::new();
+        if self.show_watch {
+            self.watch_window(ctx);
+        }
 
-        let mut shell_input_map: HashMap<usize, usize> = HashMap::new();
-        for &gid in &self.global_input_ids {
-            let src_gate = self.chip.gates.get(&gid).unwrap();
-            let src_pin = src_gate.output()[0];
-            let new_shell_id = template.add_shell_pin(crate::pin::PinType::ChipInput);
-            shell_input_map.insert(src_pin, new_shell_id);
+        if self.show_faults {
+            self.fault_window(ctx);
         }
 
-        let mut shell_output_map: HashMap<usize, usize> = HashMap::new();
-        for &gid in &self.global_output_ids {
-            let dest_gate = self.chip.gates.get(&gid).unwrap();
-            let dest_pin = dest_gate.input()[0];
-            let new_shell_id = template.add_shell_pin(crate::pin::PinType::ChipOutput);
-            shell_output_map.insert(dest_pin, new_shell_id);
+        if self.show_timing {
+            self.timing_window(ctx);
         }
 
-        for node in &self.nodes {
-            if let Some(gate) = self.chip.gates.get(&node.gate_id) {
-                let new_gate = gate.clone_with_new_ids(&mut id_map);
-                template.add_gate(new_gate);
-            }
+        if self.show_stats {
+            self.stats_window(ctx);
         }
 
-        for (src, dests) in &self.chip.connections {
-            if let Some(&shell_in) = shell_input_map.get(src) {
-                for dst in dests {
-                    if let Some(&mapped_dst) = id_map.get(dst) {
-                        template.connect_pins(shell_in, mapped_dst);
-                    }
+        if let Some(id) = self.terminal_view {
+            let mut open = true;
+            match self.chip.gates.get_mut(&id) {
+                Some(Gate::Terminal(t)) => {
+                    eframe::egui::Window::new("Terminal")
+                        .open(&mut open)
+                        .default_size([480.0, 320.0])
+                        .show(ctx, |ui| {
+                            eframe::egui::ScrollArea::vertical()
+                                .stick_to_bottom(true)
+                                .show(ui, |ui| {
+                                    ui.add(
+                                        eframe::egui::Label::new(
+                                            eframe::egui::RichText::new(&t.text).monospace(),
+                                        )
+                                        .wrap(true),
+                                    );
+                                });
+                            if ui.button("Clear").clicked() {
+                                t.text.clear();
+                            }
+                        });
                 }
-                continue;
+                _ => open = false,
             }
-
-            if let Some(&mapped_src) = id_map.get(src) {
-                for dst in dests {
-                    if let Some(&shell_out) = shell_output_map.get(dst) {
-                        template.connect_pins(mapped_src, shell_out);
-                    } else if let Some(&mapped_dst) = id_map.get(dst) {
-                        template.connect_pins(mapped_src, mapped_dst);
-                    }
-                }
+            if !open {
+                self.terminal_view = None;
             }
         }
 
-        self.chip_templates
-            .insert(self.abstract_name.clone(), template);
-        self.abstract_name.clear();
-
-        // Optional: Reset board after abstracting
-        self.nodes.clear();
-        self.chip = Chip::new(next_uuid());
-        self.global_input_ids.clear();
-        self.global_output_ids.clear();
-    }
-}
-
-impl eframe::App for LogicApp {
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-        self.sync_io();
-        if self.auto_sim {
-            self.chip.simulate();
+        if self.show_library_file {
+            let mut open = true;
+            eframe::egui::Window::new("Library File")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    eframe::egui::Grid::new("library_file").show(ui, |ui| {
+                        ui.label("File:");
+                        ui.text_edit_singleline(&mut self.library_path);
+                        ui.end_row();
+                        ui.label("Library name:");
+                        ui.text_edit_singleline(&mut self.library_source.name);
+                        ui.end_row();
+                        ui.label("Version:");
+                        ui.add(eframe::egui::DragValue::new(
+                            &mut self.library_source.version,
+                        ));
+                        ui.end_row();
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Export All").clicked() {
+                            let result = self.export_library();
+                            self.report(result);
+                        }
+                        if ui.button("Import").clicked() {
+                            let result = self.import_library();
+                            if let Some(count) = self.report(result) {
+                                self.toasts.push(Toast {
+                                    text: format!("imported {} chip(s)", count),
+                                    shown_at: None,
+                                });
+                            }
+                        }
+                    });
+                });
+            self.show_library_file = open;
         }
 
-        eframe::egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.heading("Controls:");
-                if ui.button("RUN").clicked() {
-                    self.chip.simulate();
-                }
-                ui.checkbox(&mut self.auto_sim, "Auto-Sim");
-                ui.separator();
-                if ui.button("ABSTRACT CIRCUIT").clicked() {
-                    self.show_abstract_window = true;
-                }
-                ui.separator();
-                ui.label("In:");
-                ui.add(eframe::egui::Slider::new(&mut self.input_count, 1..=16));
-                ui.label("Out:");
-                ui.add(eframe::egui::Slider::new(&mut self.output_count, 1..=16));
-            });
-        });
-
-        eframe::egui::SidePanel::right("right_panel").show(ctx, |ui| {
-            ui.heading("Tools");
-            if ui.button("Add AND").clicked() {
-                self.add_gate(GateType::And, eframe::egui::Pos2::new(400.0, 200.0));
-            }
-            if ui.button("Add NOT").clicked() {
-                self.add_gate(GateType::Not, eframe::egui::Pos2::new(400.0, 300.0));
-            }
+        if self.show_project_file {
+            let mut open = true;
+            eframe::egui::Window::new("Project File")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("File:");
+                        ui.text_edit_singleline(&mut self.project_path);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Save").clicked() {
+                            let result = self.save_project();
+                            self.report(result);
+                        }
+                        if ui.button("Load").clicked() {
+                            let result = self.load_project();
+                            self.report(result);
+                        }
+                    });
+                });
+            self.show_project_file = open;
+        }
 
-            ui.separator();
-            ui.heading("Custom Chips");
+        if self.confirm_overwrite {
+            eframe::egui::Window::new("Replace Chip?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "A chip named \"{}\" already exists. Placed copies of it are not affected.",
+                        self.abstract_name
+                    ));
+                    ui.horizontal(|ui| {
+                        if ui.button("Replace").clicked() {
+                            self.confirm_overwrite = false;
+                            self.overwrite_template = true;
+                            self.finish_abstraction();
+                            self.overwrite_template = false;
+                        }
+                        if ui.button("Choose Another Name").clicked() {
+                            self.confirm_overwrite = false;
+                            self.show_abstract_window = true;
+                        }
+                    });
+                });
+        }
 
-            let names: Vec<String> = self.chip_templates.keys().cloned().collect();
-            for name in names {
-                if ui.button(format!("Add {}", name)).clicked() {
-                    self.add_custom_chip(&name, eframe::egui::Pos2::new(400.0, 400.0));
+        if let Some(mut edit) = self.library_edit.take() {
+            let allow_empty = matches!(edit, LibraryEdit::Move { .. });
+            let (title, text) = match &mut edit {
+                LibraryEdit::Rename { new_name, .. } => ("Rename Chip", new_name),
+                LibraryEdit::Duplicate { new_name, .. } => ("Duplicate Chip", new_name),
+                LibraryEdit::Move { folder, .. } => ("Move to Folder", folder),
+            };
+            let mut done = None;
+            eframe::egui::Window::new(title)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.text_edit_singleline(text);
+                    ui.horizontal(|ui| {
+                        if ui.button("OK").clicked() && (allow_empty || !text.is_empty()) {
+                            done = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            done = Some(false);
+                        }
+                    });
+                });
+            match done {
+                Some(true) => {
+                    let result = self.apply_library_edit(edit);
+                    self.report(result);
                 }
+                Some(false) => {}
+                None => self.library_edit = Some(edit),
             }
+        }
 
-            ui.separator();
-            ui.label("Drag gates to move.\nDrag Output -> Input.");
-        });
-
-        if self.show_abstract_window {
-            eframe::egui::Window::new("Name Your Chip")
+        if let Some((src, dest)) = self.pending_replace {
+            eframe::egui::Window::new("Replace Driver?")
                 .collapsible(false)
                 .resizable(false)
                 .show(ctx, |ui| {
-                    ui.text_edit_singleline(&mut self.abstract_name);
+                    ui.label("This input is already driven by another wire.");
                     ui.horizontal(|ui| {
-                        if ui.button("Create").clicked() {
-                            if !self.abstract_name.is_empty() {
-                                self.create_abstract_chip();
-                                self.show_abstract_window = false;
-                            }
+                        if ui.button("Replace").clicked() {
+                            let result = self
+                                .chip
+                                .replace_driver(src, dest)
+                                .and_then(|_| self.chip.simulate());
+                            self.report(result);
+                            self.prune_wire_bends();
+                            self.pending_replace = None;
                         }
                         if ui.button("Cancel").clicked() {
-                            self.show_abstract_window = false;
+                            self.pending_replace = None;
                         }
                     });
                 });
         }
 
         eframe::egui::CentralPanel::default().show(ctx, |ui| {
-            let mut connection_made: Option<(usize, usize)> = None;
+            let mut connection_made: Option<(PinId, PinId)> = None;
 
-            let available_rect = ui.available_rect_before_wrap();
-            let center_y = available_rect.center().y;
-            let left_x = available_rect.min.x + 20.0;
-            let right_x = available_rect.max.x - 40.0;
+            self.camera.viewport = ui.available_rect_before_wrap();
+            let panning = self.handle_camera_input(ui);
+            let zoom = self.camera.zoom;
+            let dot_radius = (6.0 * zoom).clamp(3.0, 10.0);
 
-            let input_start_y = center_y - (self.global_input_ids.len() as f32 * 40.0 / 2.0);
-            let output_start_y = center_y - (self.global_output_ids.len() as f32 * 40.0 / 2.0);
+            if self.snap_to_grid {
+                self.draw_grid(ui);
+            }
 
             // 1. DRAW GLOBAL INPUTS
-            for (i, &gid) in self.global_input_ids.iter().enumerate() {
-                let pos = eframe::egui::Pos2::new(left_x, input_start_y + i as f32 * 40.0);
-                let gate = self.chip.gates.get_mut(&gid).unwrap();
-                let out_pin = gate.output()[0];
-                let val = gate.pins().get(&out_pin).unwrap().val.unwrap_or(0);
-
-                let btn_rect = eframe::egui::Rect::from_center_size(pos, eframe::egui::Vec2::new(30.0, 20.0));
-                if ui
-                    .interact(btn_rect, ui.id().with("input").with(gid), eframe::egui::Sense::click())
-                    .clicked()
+            for &gid in &self.global_input_ids {
+                let Some(&out_pin) = self.chip.gates.get(&gid).and_then(|g| g.output().first())
+                else {
+                    continue;
+                };
+                let Some(world) = self.io_positions.get_mut(&gid) else {
+                    continue;
+                };
+                let pos = self.camera.to_screen(*world);
+                let val = self
+                    .chip
+                    .find_pin(&out_pin)
+                    .and_then(|p| p.val)
+                    .unwrap_or(0);
+
+                let btn_rect = eframe::egui::Rect::from_center_size(
+                    pos,
+                    eframe::egui::Vec2::new(30.0, 20.0) * zoom,
+                );
+                let interact = ui.interact(
+                    btn_rect,
+                    ui.id().with("input").with(gid),
+                    eframe::egui::Sense::click_and_drag(),
+                );
+                if interact.dragged() && !panning {
+                    *world += interact.drag_delta() / zoom;
+                }
+                if interact.drag_released() && self.snap_to_grid {
+                    *world = snap_to_grid(*world);
+                }
+                if interact.clicked()
+                    && let Some(crate::gate::Gate::Source(g)) = self.chip.gates.get_mut(&gid)
                 {
-                    if let crate::gate::Gate::Source(g) = self.chip.gates.get_mut(&gid).unwrap() {
-                        let new = if val == 1 { 0 } else { 1 };
-                        g.set_pin(&out_pin, Some(new));
-                    }
+                    let new = if val == 1 { 0 } else { 1 };
+                    g.set_pin(&out_pin, Some(new));
                 }
                 ui.painter().rect_filled(
                     btn_rect,
-                    4.0,
+                    4.0 * zoom,
                     if val == 1 {
                         eframe::egui::Color32::DARK_GREEN
                     } else {
                         eframe::egui::Color32::DARK_RED
                     },
                 );
-                ui.painter()
-                    .rect_stroke(btn_rect, 4.0, eframe::egui::Stroke::new(1.0, eframe::egui::Color32::WHITE));
-                draw_connection_dot(
+                ui.painter().rect_stroke(
+                    btn_rect,
+                    4.0 * zoom,
+                    eframe::egui::Stroke::new(1.0, eframe::egui::Color32::WHITE),
+                );
+                let drop = self
+                    .dragging_wire_from
+                    .filter(|(src, _)| *src != out_pin)
+                    .map(|(src, _)| DropTarget::classify(&self.chip, src, out_pin));
+                if let Some(t) = draw_connection_dot(
                     ui,
-                    pos + eframe::egui::Vec2::new(25.0, 0.0),
-                    out_pin,
-                    val,
-                    false,
+                    PinDot {
+                        id: out_pin,
+                        pos: pos + eframe::egui::Vec2::new(IO_PIN_OFFSET * zoom, 0.0),
+                        val,
+                        is_input: false,
+                        radius: dot_radius,
+                    },
+                    drop,
                     &mut self.dragging_wire_from,
-                );
+                ) && let Some((src, _)) = self.dragging_wire_from
+                {
+                    connection_made = Some((src, t));
+                }
             }
 
             // 2. DRAW GLOBAL OUTPUTS
-            for (i, &gid) in self.global_output_ids.iter().enumerate() {
-                let pos = eframe::egui::Pos2::new(right_x, output_start_y + i as f32 * 40.0);
-                let gate = self.chip.gates.get(&gid).unwrap();
-                let in_pin = gate.input()[0];
-                let val = gate.pins().get(&in_pin).unwrap().val.unwrap_or(0);
+            for &gid in &self.global_output_ids {
+                let Some(&in_pin) = self.chip.gates.get(&gid).and_then(|g| g.input().first())
+                else {
+                    continue;
+                };
+                let Some(world) = self.io_positions.get_mut(&gid) else {
+                    continue;
+                };
+                let pos = self.camera.to_screen(*world);
+                let val = self.chip.find_pin(&in_pin).and_then(|p| p.val).unwrap_or(0);
 
+                let body = eframe::egui::Rect::from_center_size(
+                    pos,
+                    eframe::egui::Vec2::splat(24.0 * zoom),
+                );
+                let interact = ui.interact(
+                    body,
+                    ui.id().with("output").with(gid),
+                    eframe::egui::Sense::drag(),
+                );
+                if interact.dragged() && !panning {
+                    *world += interact.drag_delta() / zoom;
+                }
+                if interact.drag_released() && self.snap_to_grid {
+                    *world = snap_to_grid(*world);
+                }
+
+                let drop = self
+                    .dragging_wire_from
+                    .map(|(src, _)| DropTarget::classify(&self.chip, src, in_pin));
                 if let Some(t) = draw_connection_dot(
                     ui,
-                    pos - eframe::egui::Vec2::new(25.0, 0.0),
-                    in_pin,
-                    val,
-                    true,
+                    PinDot {
+                        id: in_pin,
+                        pos: pos - eframe::egui::Vec2::new(IO_PIN_OFFSET * zoom, 0.0),
+                        val,
+                        is_input: true,
+                        radius: dot_radius,
+                    },
+                    drop,
                     &mut self.dragging_wire_from,
-                ) {
-                    if let Some((src, _)) = self.dragging_wire_from {
-                        connection_made = Some((src, t));
-                    }
+                ) && let Some((src, _)) = self.dragging_wire_from
+                {
+                    connection_made = Some((src, t));
                 }
                 let color = if val == 1 {
                     eframe::egui::Color32::YELLOW
                 } else {
                     eframe::egui::Color32::from_gray(30)
                 };
-                ui.painter().circle_filled(pos, 12.0, color);
+                ui.painter().circle_filled(pos, 12.0 * zoom, color);
             }
 
             // 3. DRAW NODES
+            // Dragging a selected node moves the whole selection, applied after the loop.
+            let mut group_delta = eframe::egui::Vec2::ZERO;
+            let mut group_released = false;
+            let mut picked: Option<GateId> = None;
+            let faulty = self.faulty_gates();
+            let critical = match self.highlight_critical {
+                true => self.critical_gates.clone(),
+                false => HashSet::new(),
+            };
             for node in &mut self.nodes {
-                let rect = eframe::egui::Rect::from_center_size(node.pos, eframe::egui::Vec2::new(80.0, 50.0));
-                let interact =
-                    ui.interact(rect, ui.id().with("gate").with(node.gate_id), eframe::egui::Sense::drag());
-                if interact.dragged() {
-                    node.pos += interact.drag_delta();
+                let rect = self.camera.rect_to_screen(node.rect());
+                let interact = ui.interact(
+                    rect,
+                    ui.id().with("gate").with(node.gate_id),
+                    eframe::egui::Sense::click_and_drag(),
+                );
+                let selected = self.selected.contains(&node.gate_id);
+                if interact.clicked() || (interact.drag_started() && !selected) {
+                    picked = Some(node.gate_id);
+                }
+                if interact.double_clicked() {
+                    match self.chip.gates.get(&node.gate_id) {
+                        Some(Gate::Memory(_)) => {
+                            self.memory_view = Some(vec![node.gate_id]);
+                            self.memory_edit = None;
+                        }
+                        Some(Gate::Terminal(_)) => self.terminal_view = Some(node.gate_id),
+                        Some(gate @ Gate::Chip(_)) if find_gate(gate, &is_rom).is_some() => {
+                            self.program_view = Some(node.gate_id);
+                            self.program_error = None;
+                        }
+                        _ => {}
+                    }
+                }
+                interact.clone().context_menu(|ui| {
+                    for (label, side, count) in [
+                        ("Probe Inputs", "in", node.inputs.len()),
+                        ("Probe Outputs", "out", node.outputs.len()),
+                    ] {
+                        if count > 0 && ui.button(label).clicked() {
+                            self.probe_path = match count {
+                                1 => format!("{}/{}0", node.gate_id.0, side),
+                                _ => format!("{}/{}0-{}", node.gate_id.0, side, count - 1),
+                            };
+                            self.show_watch = true;
+                            ui.close_menu();
+                        }
+                    }
+                    // Memories inside a chip open in the hex editor from here.
+                    if let Some(Gate::Chip(inner)) = self.chip.gates.get(&node.gate_id) {
+                        let mut memories = Vec::new();
+                        memory_paths(inner, &mut vec![node.gate_id], &mut memories);
+                        if !memories.is_empty() {
+                            ui.menu_button("Memory Contents", |ui| {
+                                for (path, label) in memories {
+                                    if ui.button(label).clicked() {
+                                        self.memory_view = Some(path);
+                                        self.memory_edit = None;
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                        }
+                    }
+                });
+                if let Some(Gate::Switch(w)) = self.chip.gates.get_mut(&node.gate_id) {
+                    let pointer = interact.interact_pointer_pos();
+                    match w.kind {
+                        SwitchKind::PushButton => w.press(interact.is_pointer_button_down_on()),
+                        SwitchKind::Toggle if interact.clicked() => w.toggle(0),
+                        SwitchKind::DipSwitch if interact.clicked() => {
+                            // The switch level with the click.
+                            if let Some(p) = pointer {
+                                let nearest = (0..node.outputs.len()).min_by(|&a, &b| {
+                                    let dy = |k| {
+                                        (self.camera.to_screen(node.output_pos(k)).y - p.y).abs()
+                                    };
+                                    dy(a).total_cmp(&dy(b))
+                                });
+                                if let Some(k) = nearest {
+                                    w.toggle(k);
+                                }
+                            }
+                        }
+                        SwitchKind::HexKeypad => {
+                            let key = pointer
+                                .filter(|_| interact.is_pointer_button_down_on())
+                                .and_then(|p| keypad_key(rect.shrink(6.0 * zoom), p));
+                            w.press_key(key);
+                        }
+                        _ => {}
+                    }
+                    w.evaluate();
+                }
+                if interact.dragged() && !panning {
+                    if selected {
+                        group_delta += interact.drag_delta() / zoom;
+                    } else {
+                        node.pos += interact.drag_delta() / zoom;
+                    }
+                }
+                if interact.drag_released() {
+                    if selected {
+                        group_released = true;
+                    } else if self.snap_to_grid {
+                        node.pos = snap_to_grid(node.pos);
+                    }
                 }
 
-                ui.painter().rect_filled(rect, 5.0, eframe::egui::Color32::from_gray(60));
+                let outline = if selected {
+                    eframe::egui::Stroke::new(2.0, eframe::egui::Color32::LIGHT_BLUE)
+                } else if critical.contains(&node.gate_id) {
+                    eframe::egui::Stroke::new(2.5, eframe::egui::Color32::from_rgb(255, 0, 200))
+                } else if faulty.contains(&node.gate_id) {
+                    eframe::egui::Stroke::new(2.5, eframe::egui::Color32::from_rgb(255, 140, 0))
+                } else {
+                    eframe::egui::Stroke::new(1.0, eframe::egui::Color32::WHITE)
+                };
                 ui.painter()
-                    .rect_stroke(rect, 5.0, eframe::egui::Stroke::new(1.0, eframe::egui::Color32::WHITE));
-                ui.painter().text(
-                    node.pos,
-                    eframe::egui::Align2::CENTER_CENTER,
-                    &node.label,
-                    eframe::egui::FontId::proportional(16.0),
-                    eframe::egui::Color32::WHITE,
-                );
+                    .rect_filled(rect, 5.0 * zoom, eframe::egui::Color32::from_gray(60));
+                ui.painter().rect_stroke(rect, 5.0 * zoom, outline);
+                // Registers show their stored value in hex under the label.
+                let value = match self.chip.gates.get(&node.gate_id) {
+                    Some(Gate::Register(r)) => {
+                        Some(format!("{:0w$X}", r.value, w = r.width.div_ceil(4)))
+                    }
+                    _ => None,
+                };
+                let label_pos = match value {
+                    Some(_) => rect.center() - Vec2::new(0.0, 9.0 * zoom),
+                    None => rect.center(),
+                };
+                // Displays and input devices are drawn in place of the label.
+                match self.chip.gates.get(&node.gate_id) {
+                    Some(Gate::Display(d)) => {
+                        draw_display(ui.painter(), rect.shrink(8.0 * zoom), d);
+                    }
+                    Some(Gate::Terminal(t)) if !t.text.is_empty() => {
+                        // The tail of the last line; the full text is in the
+                        // terminal window.
+                        let line = t.text.rsplit('\n').next().unwrap_or("");
+                        let tail = &line[line.len().saturating_sub(8)..];
+                        ui.painter().text(
+                            rect.center(),
+                            eframe::egui::Align2::CENTER_CENTER,
+                            format!("{}_", tail),
+                            eframe::egui::FontId::monospace(12.0 * zoom),
+                            eframe::egui::Color32::LIGHT_GREEN,
+                        );
+                    }
+                    Some(Gate::Switch(w)) if w.kind != SwitchKind::Constant => {
+                        let rows: Vec<f32> = (0..node.outputs.len())
+                            .map(|k| self.camera.to_screen(node.output_pos(k)).y)
+                            .collect();
+                        draw_switch(ui.painter(), rect.shrink(6.0 * zoom), &rows, w);
+                    }
+                    _ => {
+                        ui.painter().text(
+                            label_pos,
+                            eframe::egui::Align2::CENTER_CENTER,
+                            &node.label,
+                            eframe::egui::FontId::proportional(16.0 * zoom),
+                            eframe::egui::Color32::WHITE,
+                        );
+                    }
+                }
+                if let Some(value) = value {
+                    ui.painter().text(
+                        rect.center() + Vec2::new(0.0, 9.0 * zoom),
+                        eframe::egui::Align2::CENTER_CENTER,
+                        value,
+                        eframe::egui::FontId::monospace(14.0 * zoom),
+                        eframe::egui::Color32::LIGHT_GREEN,
+                    );
+                }
 
                 // Inputs
-                let inputs = node.inputs.clone();
-                for (j, &pid) in inputs.iter().enumerate() {
-                    let y_off = (j as f32 - (inputs.len() as f32 - 1.0) / 2.0) * 15.0;
+                for (j, &pid) in node.inputs.iter().enumerate() {
                     let val = self
                         .chip
                         .gates
                         .get(&node.gate_id)
-                        .unwrap()
-                        .pins()
-                        .get(&pid)
-                        .unwrap()
-                        .val
+                        .and_then(|g| g.pins().get(&pid))
+                        .and_then(|p| p.val)
                         .unwrap_or(0);
+                    let drop = self
+                        .dragging_wire_from
+                        .map(|(src, _)| DropTarget::classify(&self.chip, src, pid));
                     if let Some(t) = draw_connection_dot(
                         ui,
-                        node.pos + eframe::egui::Vec2::new(-40.0, y_off),
-                        pid,
-                        val,
-                        true,
+                        PinDot {
+                            id: pid,
+                            pos: self.camera.to_screen(node.input_pos(j)),
+                            val,
+                            is_input: true,
+                            radius: dot_radius,
+                        },
+                        drop,
                         &mut self.dragging_wire_from,
-                    ) {
-                        if let Some((src, _)) = self.dragging_wire_from {
-                            connection_made = Some((src, t));
-                        }
+                    ) && let Some((src, _)) = self.dragging_wire_from
+                    {
+                        connection_made = Some((src, t));
                     }
                 }
                 // Outputs
-                let outputs = node.outputs.clone();
-                for (j, &pid) in outputs.iter().enumerate() {
-                    let y_off = (j as f32 - (outputs.len() as f32 - 1.0) / 2.0) * 15.0;
+                for (j, &pid) in node.outputs.iter().enumerate() {
                     let val = self
                         .chip
                         .gates
                         .get(&node.gate_id)
-                        .unwrap()
-                        .pins()
-                        .get(&pid)
-                        .unwrap()
-                        .val
+                        .and_then(|g| g.pins().get(&pid))
+                        .and_then(|p| p.val)
                         .unwrap_or(0);
-                    draw_connection_dot(
+                    let drop = self
+                        .dragging_wire_from
+                        .filter(|(src, _)| *src != pid)
+                        .map(|(src, _)| DropTarget::classify(&self.chip, src, pid));
+                    if let Some(t) = draw_connection_dot(
                         ui,
-                        node.pos + eframe::egui::Vec2::new(40.0, y_off),
-                        pid,
-                        val,
-                        false,
+                        PinDot {
+                            id: pid,
+                            pos: self.camera.to_screen(node.output_pos(j)),
+                            val,
+                            is_input: false,
+                            radius: dot_radius,
+                        },
+                        drop,
                         &mut self.dragging_wire_from,
-                    );
+                    ) && let Some((src, _)) = self.dragging_wire_from
+                    {
+                        connection_made = Some((src, t));
+                    }
+                }
+            }
+
+            if let Some(gid) = picked {
+                let shift = ui.input(|i| i.modifiers.shift);
+                self.select(gid, shift);
+            }
+            for node in &mut self.nodes {
+                if self.selected.contains(&node.gate_id) {
+                    node.pos += group_delta;
+                    if group_released && self.snap_to_grid {
+                        node.pos = snap_to_grid(node.pos);
+                    }
+                }
+            }
+            if group_delta != eframe::egui::Vec2::ZERO {
+                let moved: Vec<(PinId, PinId)> = self
+                    .wire_bends
+                    .keys()
+                    .filter(|(src, dest)| self.is_wire_selected(src, dest))
+                    .copied()
+                    .collect();
+                for wire in moved {
+                    if let Some(bends) = self.wire_bends.get_mut(&wire) {
+                        bends.iter_mut().for_each(|b| *b += group_delta);
+                    }
                 }
             }
 
             // 4. DRAW WIRES
             for (src, dests) in &self.chip.connections {
-                let mut src_pos = eframe::egui::Pos2::ZERO;
-                let mut val = 0;
-
-                if let Some(idx) = self
-                    .global_input_ids
-                    .iter()
-                    .position(|gid| self.chip.gates.get(gid).unwrap().output().contains(src))
-                {
-                    src_pos = eframe::egui::Pos2::new(left_x + 25.0, input_start_y + idx as f32 * 40.0);
-                    val = self
-                        .chip
-                        .gates
-                        .get(&self.global_input_ids[idx])
-                        .unwrap()
-                        .pins()
-                        .get(src)
-                        .unwrap()
-                        .val
-                        .unwrap_or(0);
-                } else if let Some(node) = self.nodes.iter().find(|n| n.outputs.contains(src)) {
-                    src_pos = node.pos + eframe::egui::Vec2::new(40.0, 0.0);
-                    if let Some(idx) = node.outputs.iter().position(|x| x == src) {
-                        src_pos.y += (idx as f32 - (node.outputs.len() as f32 - 1.0) / 2.0) * 15.0;
+                let val = self.chip.find_pin(src).and_then(|p| p.val).unwrap_or(0);
+                let color = if val == 1 {
+                    eframe::egui::Color32::GREEN
+                } else {
+                    eframe::egui::Color32::from_rgb(100, 0, 0)
+                };
+                let mut net = Vec::new();
+                for dest in dests {
+                    let Some(path) = self.wire_path(src, dest) else {
+                        continue;
+                    };
+                    let path: Vec<Pos2> = path.iter().map(|p| self.camera.to_screen(*p)).collect();
+                    let width = if self.is_wire_selected(src, dest) {
+                        4.0
+                    } else {
+                        2.0
+                    };
+                    if self.highlight_critical && self.critical_wires.contains(&(*src, *dest)) {
+                        ui.painter().add(eframe::egui::Shape::line(
+                            path.clone(),
+                            eframe::egui::Stroke::new(
+                                (width + 4.0) * zoom.sqrt(),
+                                Color32::from_rgba_unmultiplied(255, 0, 200, 140),
+                            ),
+                        ));
                     }
-                    val = self
-                        .chip
-                        .gates
-                        .get(&node.gate_id)
-                        .unwrap()
-                        .pins()
-                        .get(src)
-                        .unwrap()
-                        .val
-                        .unwrap_or(0);
+                    if self.debugger.changed.contains(src) {
+                        ui.painter().add(eframe::egui::Shape::line(
+                            path.clone(),
+                            eframe::egui::Stroke::new(
+                                (width + 4.0) * zoom.sqrt(),
+                                Color32::from_rgba_unmultiplied(255, 220, 0, 140),
+                            ),
+                        ));
+                    }
+                    ui.painter().add(eframe::egui::Shape::line(
+                        path.clone(),
+                        eframe::egui::Stroke::new(width * zoom.sqrt(), color),
+                    ));
+                    net.push(path);
+                }
+                for junction in routing::junctions(&net) {
+                    ui.painter()
+                        .circle_filled(junction, 4.0 * zoom.sqrt(), color);
                 }
+            }
 
-                if src_pos != eframe::egui::Pos2::ZERO {
-                    let color = if val == 1 {
-                        eframe::egui::Color32::GREEN
+            // Bend handles: drag to move, double-click to remove.
+            let mut removed_bend = None;
+            for (&(src, dest), bends) in &mut self.wire_bends {
+                for (k, bend) in bends.iter_mut().enumerate() {
+                    let handle = eframe::egui::Rect::from_center_size(
+                        self.camera.to_screen(*bend),
+                        eframe::egui::Vec2::splat(8.0),
+                    );
+                    let interact = ui.interact(
+                        handle,
+                        ui.id().with("bend").with(src).with(dest).with(k),
+                        eframe::egui::Sense::click_and_drag(),
+                    );
+                    if interact.dragged() && !panning {
+                        *bend += interact.drag_delta() / zoom;
+                    }
+                    if interact.drag_released() && self.snap_to_grid {
+                        *bend = snap_to_grid(*bend);
+                    }
+                    if interact.double_clicked() {
+                        removed_bend = Some(((src, dest), k));
+                    }
+                    let fill = if interact.hovered() {
+                        eframe::egui::Color32::WHITE
                     } else {
-                        eframe::egui::Color32::from_rgb(100, 0, 0)
+                        eframe::egui::Color32::GRAY
                     };
-                    for dest in dests {
-                        let mut dest_pos = eframe::egui::Pos2::ZERO;
-                        if let Some(idx) = self.global_output_ids.iter().position(|gid| {
-                            self.chip.gates.get(gid).unwrap().input().contains(dest)
-                        }) {
-                            dest_pos =
-                                eframe::egui::Pos2::new(right_x - 25.0, output_start_y + idx as f32 * 40.0);
-                        } else if let Some(node) =
-                            self.nodes.iter().find(|n| n.inputs.contains(dest))
-                        {
-                            let idx = node.inputs.iter().position(|x| x == dest).unwrap_or(0);
-                            let y_off =
-                                (idx as f32 - (node.inputs.len() as f32 - 1.0) / 2.0) * 15.0;
-                            dest_pos = node.pos + eframe::egui::Vec2::new(-40.0, y_off);
-                        }
+                    ui.painter().rect_filled(handle, 1.0, fill);
+                }
+            }
+            if let Some((wire, k)) = removed_bend
+                && let Some(bends) = self.wire_bends.get_mut(&wire)
+            {
+                bends.remove(k);
+                if bends.is_empty() {
+                    self.wire_bends.remove(&wire);
+                }
+            }
 
-                        if dest_pos != eframe::egui::Pos2::ZERO {
-                            let scale = (dest_pos.x - src_pos.x).max(20.0) / 2.0;
-                            let points = [
-                                src_pos,
-                                src_pos + eframe::egui::Vec2::new(scale, 0.0),
-                                dest_pos - eframe::egui::Vec2::new(scale, 0.0),
-                                dest_pos,
-                            ];
-                            ui.painter().add(eframe::egui::Shape::CubicBezier(
-                                eframe::epaint::CubicBezierShape::from_points_stroke(
-                                    points,
-                                    false,
-                                    eframe::egui::Color32::TRANSPARENT,
-                                    eframe::egui::Stroke::new(2.0, color),
-                                ),
-                            ));
-                        }
-                    }
+            // 5. RUBBER-BAND SELECTION
+            // Registered last so it only gets clicks and drags nothing else took.
+            let background = ui.interact(
+                self.camera.viewport,
+                ui.id().with("canvas"),
+                eframe::egui::Sense::click_and_drag(),
+            );
+            if background.clicked() {
+                self.selected.clear();
+            }
+            if background.double_clicked()
+                && let Some(pos) = background.interact_pointer_pos()
+            {
+                self.add_bend_at(pos);
+            }
+            if background.drag_started() && !panning {
+                self.rubber_band = background.interact_pointer_pos();
+            }
+            if let Some(start) = self.rubber_band {
+                let curr = ctx.pointer_hover_pos().unwrap_or(start);
+                let band = eframe::egui::Rect::from_two_pos(start, curr);
+                ui.painter().rect(
+                    band,
+                    0.0,
+                    eframe::egui::Color32::from_rgba_unmultiplied(100, 150, 255, 30),
+                    eframe::egui::Stroke::new(1.0, eframe::egui::Color32::LIGHT_BLUE),
+                );
+                if !background.dragged() {
+                    let world = eframe::egui::Rect::from_two_pos(
+                        self.camera.to_world(band.min),
+                        self.camera.to_world(band.max),
+                    );
+                    let shift = ui.input(|i| i.modifiers.shift);
+                    self.select_in_rect(world, shift);
+                    self.rubber_band = None;
                 }
             }
 
             if let Some((_, start)) = self.dragging_wire_from {
                 if let Some(curr) = ctx.pointer_hover_pos() {
-                    ui.painter()
-                        .line_segment([start, curr], eframe::egui::Stroke::new(2.0, eframe::egui::Color32::YELLOW));
+                    ui.painter().line_segment(
+                        [start, curr],
+                        eframe::egui::Stroke::new(2.0, eframe::egui::Color32::YELLOW),
+                    );
                 }
                 if ui.input(|i| i.pointer.any_released()) {
                     self.dragging_wire_from = None;
                 }
             }
             if let Some((src, dest)) = connection_made {
-                self.chip.connect_pins(src, dest);
                 self.dragging_wire_from = None;
-                self.chip.simulate();
+                if DropTarget::classify(&self.chip, src, dest) == DropTarget::NeedsConfirm {
+                    self.pending_replace = Some((src, dest));
+                } else {
+                    let result = self
+                        .chip
+                        .connect_pins(src, dest)
+                        .and_then(|_| self.chip.simulate());
+                    self.report(result);
+                }
             }
         });
+
+        self.draw_toasts(ctx);
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,27 @@
+pub mod atpg;
+pub mod builder;
+pub mod camera;
 pub mod circuit;
+pub mod cpu;
+pub mod debugger;
+pub mod display;
+pub mod edit;
+pub mod error;
+pub mod fault;
+pub mod format;
 pub mod gate;
 pub mod gate_ui;
+pub mod library;
+pub mod memory;
+pub mod peripheral;
 pub mod pin;
+pub mod probe;
+pub mod project;
+pub mod register;
+pub mod routing;
+pub mod runner;
+pub mod stats;
+pub mod stdlib;
+pub mod switch;
+pub mod timing;
 pub mod types;
@@ -0,0 +1,547 @@
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::format::{self, Lines, Token, parse_error};
+use crate::gate::Gate;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Version of the library file layout, not of the library's contents.
+const FILE_FORMAT: u32 = 1;
+/// Separates a library's name from the template names it brings in.
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// The library file a template was imported from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibrarySource {
+    pub name: String,
+    pub version: u32,
+}
+
+/// A chip template together with where it is filed in the library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub chip: Chip,
+    /// Folder path such as "Arithmetic/Adders"; empty for the top level.
+    pub folder: String,
+    /// `None` for templates made in this project.
+    pub source: Option<LibrarySource>,
+}
+
+/// The named chip templates of a project. Templates stamp their name on the
+/// chip (see `Chip::name`), and instances carry it along, which is how the
+/// library finds out where a template is used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChipLibrary {
+    templates: BTreeMap<String, Template>,
+}
+
+impl ChipLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.templates.contains_key(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Chip> {
+        self.templates.get(name).map(|t| &t.chip)
+    }
+
+    pub fn template(&self, name: &str) -> Option<&Template> {
+        self.templates.get(name)
+    }
+
+    /// Template names in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.templates.keys().map(String::as_str)
+    }
+
+    /// Template names grouped by folder, both in alphabetical order.
+    pub fn folders(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut folders: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (name, template) in &self.templates {
+            folders
+                .entry(template.folder.as_str())
+                .or_default()
+                .push(name.as_str());
+        }
+        folders
+    }
+
+    /// Adds `chip` under `name`, failing with `NameTaken` rather than
+    /// overwriting an existing template.
+    pub fn insert(&mut self, name: &str, chip: Chip, folder: &str) -> Result<(), LgsimError> {
+        if self.contains(name) {
+            return Err(LgsimError::NameTaken(name.to_string()));
+        }
+        self.replace(name, chip, folder);
+        Ok(())
+    }
+
+    /// Adds `chip` under `name`, overwriting any template of that name.
+    pub fn replace(&mut self, name: &str, mut chip: Chip, folder: &str) {
+        chip.name = Some(name.to_string());
+        let folder = folder.to_string();
+        let template = Template {
+            chip,
+            folder,
+            source: None,
+        };
+        self.templates.insert(name.to_string(), template);
+    }
+
+    /// Renames a template and every instance of it, in other templates and
+    /// on `board`.
+    pub fn rename(&mut self, old: &str, new: &str, board: &mut Chip) -> Result<(), LgsimError> {
+        if old == new {
+            return Ok(());
+        }
+        if self.contains(new) {
+            return Err(LgsimError::NameTaken(new.to_string()));
+        }
+        let mut template = self
+            .templates
+            .remove(old)
+            .ok_or_else(|| LgsimError::UnknownTemplate(old.to_string()))?;
+        template.chip.name = Some(new.to_string());
+        template.source = None;
+        rename_instances(&mut template.chip, old, new);
+        for other in self.templates.values_mut() {
+            rename_instances(&mut other.chip, old, new);
+        }
+        rename_instances(board, old, new);
+        self.templates.insert(new.to_string(), template);
+        Ok(())
+    }
+
+    /// Copies a template under a new name in the same folder. Instances of
+    /// the original are unaffected.
+    pub fn duplicate(&mut self, name: &str, copy_name: &str) -> Result<(), LgsimError> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))?
+            .clone();
+        self.insert(copy_name, template.chip, &template.folder)
+    }
+
+    pub fn set_folder(&mut self, name: &str, folder: &str) -> Result<(), LgsimError> {
+        let template = self
+            .templates
+            .get_mut(name)
+            .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))?;
+        template.folder = folder.to_string();
+        Ok(())
+    }
+
+    /// Names of the templates that contain an instance of `name` at any
+    /// depth, plus "the board" when `board` does.
+    pub fn users(&self, name: &str, board: &Chip) -> Vec<String> {
+        let mut users: Vec<String> = self
+            .templates
+            .iter()
+            .filter(|(other, t)| other.as_str() != name && contains_instance(&t.chip, name))
+            .map(|(other, _)| other.clone())
+            .collect();
+        if contains_instance(board, name) {
+            users.push("the board".to_string());
+        }
+        users
+    }
+
+    /// Deletes a template, unless something still uses it.
+    pub fn remove(&mut self, name: &str, board: &Chip) -> Result<Template, LgsimError> {
+        if !self.contains(name) {
+            return Err(LgsimError::UnknownTemplate(name.to_string()));
+        }
+        let users = self.users(name, board);
+        if !users.is_empty() {
+            return Err(LgsimError::TemplateInUse {
+                name: name.to_string(),
+                users,
+            });
+        }
+        self.templates
+            .remove(name)
+            .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))
+    }
+
+    /// Writes `names` and every template they use to a library file called
+    /// `library`. Templates that came from `library` are written under their
+    /// short names so the file can be re-imported.
+    pub fn export(&self, names: &[&str], library: &LibrarySource) -> Result<String, LgsimError> {
+        let mut pending: Vec<&str> = names.to_vec();
+        let mut included: BTreeSet<&str> = BTreeSet::new();
+        while let Some(name) = pending.pop() {
+            let template = self
+                .templates
+                .get_key_value(name)
+                .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))?;
+            if included.insert(template.0) {
+                let mut used = Vec::new();
+                instance_names(&template.1.chip, &mut used);
+                pending.extend(used.into_iter().filter(|n| self.contains(n)));
+            }
+        }
+
+        let prefix = format!("{}{}", library.name, NAMESPACE_SEPARATOR);
+        let short = |name: &str| name.strip_prefix(&prefix).unwrap_or(name).to_string();
+        let mut out = format!(
+            "lgsim-library {}\nlibrary {} {}\n",
+            FILE_FORMAT,
+            format::quote(&library.name),
+            library.version
+        );
+        for name in included {
+            let template = &self.templates[name];
+            let mut chip = template.chip.clone();
+            map_names(&mut chip, &short);
+            // Only the library's own folder is dropped, not `stdlib` for `std`.
+            let folder = match template.folder.strip_prefix(library.name.as_str()) {
+                Some("") => "",
+                Some(rest) => rest.strip_prefix('/').unwrap_or(&template.folder),
+                None => &template.folder,
+            };
+            out.push_str(&format!(
+                "\ntemplate {} {}\n",
+                format::quote(&short(name)),
+                format::quote(folder)
+            ));
+            format::write_chip(&mut out, &chip, 0)?;
+        }
+        Ok(out)
+    }
+
+    /// Adds the templates of a library file as `<library>::<name>`, filed
+    /// under a folder named after the library. Templates from an earlier
+    /// import of the same library are replaced; an older version is
+    /// refused. Returns the names of the imported templates.
+    pub fn import(&mut self, text: &str) -> Result<Vec<String>, LgsimError> {
+        let mut lines = Lines::new(text);
+        match lines.next_tokens()? {
+            Some((_, t)) if t == [word("lgsim-library"), word(&FILE_FORMAT.to_string())] => {}
+            Some((line, _)) => return Err(parse_error(line, "not an lgsim library file")),
+            None => return Err(parse_error(1, "file is empty")),
+        }
+        let source = match lines.next_tokens()? {
+            Some((line, t)) => match t.as_slice() {
+                [Token::Word(kw), Token::Text(name), Token::Word(version)] if kw == "library" => {
+                    let version = version
+                        .parse()
+                        .map_err(|_| parse_error(line, "bad library version"))?;
+                    LibrarySource {
+                        name: name.clone(),
+                        version,
+                    }
+                }
+                _ => return Err(parse_error(line, "expected: library \"name\" <version>")),
+            },
+            None => return Err(parse_error(2, "missing library line")),
+        };
+
+        let mut parsed = Vec::new();
+        while let Some((line, t)) = lines.next_tokens()? {
+            let [Token::Word(kw), Token::Text(name), Token::Text(folder)] = t.as_slice() else {
+                return Err(parse_error(line, "expected: template \"name\" \"folder\""));
+            };
+            if kw != "template" {
+                return Err(parse_error(line, "expected: template \"name\" \"folder\""));
+            }
+            let Some((chip_line, header)) = lines.next_tokens()? else {
+                return Err(parse_error(line, "template has no chip"));
+            };
+            if header.first() != Some(&word("chip")) {
+                return Err(parse_error(chip_line, "expected a chip block"));
+            }
+            let node = format::parse_chip(&mut lines, &header, chip_line)?;
+            if node.has_board_io() {
+                return Err(parse_error(
+                    chip_line,
+                    "templates cannot have board inputs or outputs",
+                ));
+            }
+            parsed.push((name.clone(), folder.clone(), node, chip_line));
+        }
+
+        let short_names: BTreeSet<String> = parsed.iter().map(|p| p.0.clone()).collect();
+        let namespaced = |name: &str| {
+            if short_names.contains(name) {
+                format!("{}{}{}", source.name, NAMESPACE_SEPARATOR, name)
+            } else {
+                name.to_string()
+            }
+        };
+        for (name, ..) in &parsed {
+            let full = namespaced(name);
+            let Some(existing) = self.templates.get(&full) else {
+                continue;
+            };
+            match &existing.source {
+                Some(old) if old.name == source.name && old.version > source.version => {
+                    return Err(LgsimError::OlderLibrary {
+                        name: source.name.clone(),
+                        installed: old.version,
+                        imported: source.version,
+                    });
+                }
+                Some(old) if old.name == source.name => {}
+                _ => return Err(LgsimError::NameTaken(full)),
+            }
+        }
+
+        let mut built = Vec::new();
+        for (name, folder, node, line) in &parsed {
+            let mut chip = format::build_chip(node, &namespaced, *line)?;
+            chip.name = Some(namespaced(name));
+            let folder = if folder.is_empty() {
+                source.name.clone()
+            } else {
+                format!("{}/{}", source.name, folder)
+            };
+            built.push((namespaced(name), chip, folder));
+        }
+        let mut imported = Vec::new();
+        for (name, chip, folder) in built {
+            self.replace(&name, chip, &folder);
+            if let Some(template) = self.templates.get_mut(&name) {
+                template.source = Some(source.clone());
+            }
+            imported.push(name);
+        }
+        Ok(imported)
+    }
+}
+
+fn word(w: &str) -> Token {
+    Token::Word(w.to_string())
+}
+
+fn contains_instance(chip: &Chip, name: &str) -> bool {
+    chip.gates.values().any(|g| match g {
+        Gate::Chip(c) => c.name.as_deref() == Some(name) || contains_instance(c, name),
+        _ => false,
+    })
+}
+
+fn rename_instances(chip: &mut Chip, old: &str, new: &str) {
+    for gate in chip.gates.values_mut() {
+        if let Gate::Chip(c) = gate {
+            if c.name.as_deref() == Some(old) {
+                c.name = Some(new.to_string());
+            }
+            rename_instances(c, old, new);
+        }
+    }
+}
+
+/// Names of the chips nested anywhere in `chip`.
+fn instance_names<'a>(chip: &'a Chip, out: &mut Vec<&'a str>) {
+    for gate in chip.gates.values() {
+        if let Gate::Chip(c) = gate {
+            if let Some(name) = &c.name {
+                out.push(name);
+            }
+            instance_names(c, out);
+        }
+    }
+}
+
+fn map_names(chip: &mut Chip, f: &dyn Fn(&str) -> String) {
+    chip.name = chip.name.as_deref().map(f);
+    for gate in chip.gates.values_mut() {
+        if let Gate::Chip(c) = gate {
+            map_names(c, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+    use crate::types::ChipId;
+
+    fn nand() -> Chip {
+        crate::circuit! {
+            inputs: [a, b];
+            let y = nand(a, b);
+            outputs: [y];
+        }
+        .unwrap()
+    }
+
+    /// A chip holding one instance of `template`.
+    fn user_of(template: &Chip) -> Chip {
+        let mut b = ChipBuilder::new();
+        let inputs: Vec<_> = template.input.iter().map(|_| b.input()).collect();
+        for out in b.instance(template, &inputs) {
+            b.output(out);
+        }
+        b.build().unwrap()
+    }
+
+    /// A library with "NAND" and "AND" built from it, and a board using
+    /// "AND".
+    fn library() -> (ChipLibrary, Chip) {
+        let mut library = ChipLibrary::new();
+        library.insert("NAND", nand(), "Gates").unwrap();
+        let and = user_of(library.get("NAND").unwrap());
+        library.insert("AND", and, "Gates").unwrap();
+        let board = user_of(library.get("AND").unwrap());
+        (library, board)
+    }
+
+    fn instance_names_of(chip: &Chip) -> Vec<&str> {
+        let mut names = Vec::new();
+        instance_names(chip, &mut names);
+        names
+    }
+
+    #[test]
+    fn rename_follows_instances() {
+        let (mut library, mut board) = library();
+        library.rename("NAND", "Nand2", &mut board).unwrap();
+        assert!(!library.contains("NAND"));
+        assert_eq!(library.get("Nand2").unwrap().name.as_deref(), Some("Nand2"));
+        assert_eq!(instance_names_of(library.get("AND").unwrap()), ["Nand2"]);
+        assert_eq!(instance_names_of(&board), ["AND", "Nand2"]);
+
+        assert_eq!(
+            library.rename("Nand2", "AND", &mut board),
+            Err(LgsimError::NameTaken("AND".to_string()))
+        );
+        assert_eq!(
+            library.rename("XOR", "X", &mut board),
+            Err(LgsimError::UnknownTemplate("XOR".to_string()))
+        );
+    }
+
+    #[test]
+    fn duplicate_copies_into_the_same_folder() {
+        let (mut library, _) = library();
+        library.duplicate("AND", "AND copy").unwrap();
+        let copy = library.template("AND copy").unwrap();
+        assert_eq!(copy.folder, "Gates");
+        assert_eq!(copy.chip.name.as_deref(), Some("AND copy"));
+        assert_eq!(copy.chip.gates, library.get("AND").unwrap().gates);
+        assert_eq!(
+            library.duplicate("AND", "NAND"),
+            Err(LgsimError::NameTaken("NAND".to_string()))
+        );
+    }
+
+    #[test]
+    fn remove_refuses_templates_in_use() {
+        let (mut library, board) = library();
+        assert_eq!(
+            library.remove("NAND", &board).err(),
+            Some(LgsimError::TemplateInUse {
+                name: "NAND".to_string(),
+                users: vec!["AND".to_string(), "the board".to_string()],
+            })
+        );
+        let empty = Chip::new(ChipId(0));
+        assert!(library.remove("AND", &board).is_err());
+        library.remove("AND", &empty).unwrap();
+        library.remove("NAND", &empty).unwrap();
+        assert_eq!(library.names().count(), 0);
+    }
+
+    #[test]
+    fn folders_group_templates() {
+        let (mut library, _) = library();
+        library.set_folder("AND", "Gates/Derived").unwrap();
+        let folders = library.folders();
+        assert_eq!(folders["Gates"], ["NAND"]);
+        assert_eq!(folders["Gates/Derived"], ["AND"]);
+        assert!(library.set_folder("XOR", "").is_err());
+    }
+
+    fn source(version: u32) -> LibrarySource {
+        LibrarySource {
+            name: "std".to_string(),
+            version,
+        }
+    }
+
+    #[test]
+    fn export_pulls_in_dependencies() {
+        let (library, _) = library();
+        let text = library.export(&["AND"], &source(2)).unwrap();
+        assert!(text.starts_with("lgsim-library 1\nlibrary \"std\" 2\n"));
+        assert!(text.contains("template \"AND\" \"Gates\""));
+        assert!(text.contains("template \"NAND\" \"Gates\""));
+
+        let mut imported = ChipLibrary::new();
+        let names = imported.import(&text).unwrap();
+        assert_eq!(names, ["std::AND", "std::NAND"]);
+        let and = imported.template("std::AND").unwrap();
+        assert_eq!(and.folder, "std/Gates");
+        assert_eq!(and.source, Some(source(2)));
+        assert_eq!(and.chip.name.as_deref(), Some("std::AND"));
+        assert_eq!(instance_names_of(&and.chip), ["std::NAND"]);
+
+        // Exporting the imported templates strips the namespace again. Pins
+        // are relabelled on import, so compare from the second export on.
+        let again = imported.export(&["std::AND"], &source(2)).unwrap();
+        assert!(again.contains("chip \"NAND\" in"));
+        assert!(again.contains("template \"NAND\" \"Gates\""));
+        let mut reimported = ChipLibrary::new();
+        reimported.import(&again).unwrap();
+        assert_eq!(reimported.export(&["std::AND"], &source(2)).unwrap(), again);
+    }
+
+    #[test]
+    fn export_strips_only_the_library_folder() {
+        let (mut library, _) = library();
+        library.set_folder("NAND", "stdlib/Gates").unwrap();
+        library.set_folder("AND", "std").unwrap();
+        let text = library.export(&["AND"], &source(1)).unwrap();
+        assert!(text.contains("template \"NAND\" \"stdlib/Gates\""));
+        assert!(text.contains("template \"AND\" \"\""));
+    }
+
+    #[test]
+    fn import_checks_versions_and_names() {
+        let (library, _) = library();
+        let export = |version| library.export(&["AND"], &source(version)).unwrap();
+        let mut imported = ChipLibrary::new();
+        imported.import(&export(2)).unwrap();
+        assert_eq!(
+            imported.import(&export(1)),
+            Err(LgsimError::OlderLibrary {
+                name: "std".to_string(),
+                installed: 2,
+                imported: 1,
+            })
+        );
+        imported.import(&export(3)).unwrap();
+        assert_eq!(
+            imported.template("std::NAND").unwrap().source,
+            Some(source(3))
+        );
+        assert_eq!(imported.names().count(), 2);
+
+        let mut local = ChipLibrary::new();
+        local.insert("std::NAND", nand(), "").unwrap();
+        assert_eq!(
+            local.import(&export(1)),
+            Err(LgsimError::NameTaken("std::NAND".to_string()))
+        );
+        assert!(!local.contains("std::AND"));
+    }
+
+    #[test]
+    fn import_rejects_bad_files() {
+        let line_of = |text: &str| match ChipLibrary::new().import(text) {
+            Err(LgsimError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(line_of("lgsim-project 1\n"), 1);
+        assert_eq!(line_of("lgsim-library 1\n"), 2);
+        assert_eq!(line_of("lgsim-library 1\nlibrary std 1\n"), 2);
+        let board_io = "lgsim-library 1\nlibrary \"std\" 1\n\ntemplate \"IO\" \"\"\n\
+                        chip \"IO\" in out\n  input in out 1\nend\n";
+        assert_eq!(line_of(board_io), 5);
+    }
+}
@@ -1,4 +1,28 @@
+use lgsim::runner::{Runner, load_chip, parse_vectors};
+use lgsim::stats::{self, CostTable};
+use std::io::{Read, Write};
+
+const USAGE: &str = "usage: lgsim run <library.lgl> <chip> [--cycles N] [--clock INPUT|none] \
+                     [--input FILE|-] [--vectors FILE]\n       \
+                     lgsim stats <library.lgl> <chip> [--costs FILE]";
+
 fn main() -> Result<(), eframe::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "run") {
+        if let Err(message) = run(&args[1..]) {
+            eprintln!("lgsim: {}", message);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.first().is_some_and(|a| a == "stats") {
+        if let Err(message) = print_stats(&args[1..]) {
+            eprintln!("lgsim: {}", message);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default().with_inner_size([800.0, 600.0]),
         renderer: eframe::Renderer::Glow,
@@ -11,3 +35,122 @@ fn main() -> Result<(), eframe::Error> {
     )
 }
 
+/// Headless mode: clocks a chip from a library file for a number of cycles,
+/// typing `--input` into its keyboards and printing its terminals to stdout.
+/// With `--vectors` it instead applies a test vector file and checks the
+/// outputs.
+fn run(args: &[String]) -> Result<(), String> {
+    let [library, chip, options @ ..] = args else {
+        return Err(USAGE.to_string());
+    };
+    let mut cycles: usize = 1000;
+    let mut clock = Some(0);
+    let mut input: Option<&str> = None;
+    let mut vectors: Option<&str> = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", option, USAGE))?;
+        match option.as_str() {
+            "--cycles" => {
+                cycles = value
+                    .parse()
+                    .map_err(|_| format!("bad cycle count {}", value))?
+            }
+            "--clock" if value == "none" => clock = None,
+            "--clock" => clock = Some(value.parse().map_err(|_| format!("bad input {}", value))?),
+            "--input" => input = Some(value),
+            "--vectors" => vectors = Some(value),
+            _ => return Err(format!("unknown option {}\n{}", option, USAGE)),
+        }
+    }
+
+    let text = std::fs::read_to_string(library).map_err(|e| format!("{}: {}", library, e))?;
+    let chip = load_chip(&text, chip).map_err(|e| format!("{}: {}", library, e))?;
+    if let Some(path) = vectors {
+        return check_vectors(chip, path);
+    }
+    let mut runner = Runner::new(chip, clock).map_err(|e| e.to_string())?;
+    match input {
+        Some("-") => {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| format!("stdin: {}", e))?;
+            runner.type_bytes(&bytes);
+        }
+        Some(path) => {
+            let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            runner.type_bytes(&bytes);
+        }
+        None => {}
+    }
+
+    let mut stdout = std::io::stdout();
+    for _ in 0..cycles {
+        let printed = runner.cycle().map_err(|e| e.to_string())?;
+        stdout.write_all(&printed).map_err(|e| e.to_string())?;
+    }
+    stdout.flush().map_err(|e| e.to_string())
+}
+
+/// Applies every vector in the file at `path` and reports the ones whose
+/// outputs differ from those expected.
+fn check_vectors(chip: lgsim::circuit::Chip, path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let vectors = parse_vectors(&text, chip.input.len(), chip.output.len())
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let mut runner = Runner::new(chip, None).map_err(|e| e.to_string())?;
+    let bits = |values: &[Option<u8>]| -> String {
+        values
+            .iter()
+            .map(|v| v.map_or('x', |b| char::from(b'0' + b)))
+            .collect()
+    };
+    let mut failed = 0;
+    for (i, vector) in vectors.iter().enumerate() {
+        let outputs = runner.apply(vector).map_err(|e| e.to_string())?;
+        let matches = vector
+            .outputs
+            .iter()
+            .zip(&outputs)
+            .all(|(want, got)| want.is_none() || want == got);
+        if !matches {
+            failed += 1;
+            println!(
+                "vector {}: expected {}, got {}",
+                i + 1,
+                bits(&vector.outputs),
+                bits(&outputs)
+            );
+        }
+    }
+    println!(
+        "{} of {} vectors passed",
+        vectors.len() - failed,
+        vectors.len()
+    );
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} vectors failed", failed)),
+    }
+}
+
+/// Prints gate counts and the cost of a chip from a library file, using the
+/// `NAME=COST` table in `--costs` or the default one.
+fn print_stats(args: &[String]) -> Result<(), String> {
+    let costs = match args {
+        [_, _] => CostTable::default(),
+        [_, _, option, path] if option == "--costs" => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            CostTable::parse(&text).map_err(|e| format!("{}: {}", path, e))?
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+    let text = std::fs::read_to_string(&args[0]).map_err(|e| format!("{}: {}", args[0], e))?;
+    let chip = load_chip(&text, &args[1]).map_err(|e| format!("{}: {}", args[0], e))?;
+    let stats = stats::stats(&chip, &costs).map_err(|e| e.to_string())?;
+    print!("{}", stats);
+    Ok(())
+}
@@ -0,0 +1,535 @@
+//! RAM and ROM gates and the file formats their contents can be loaded from.
+
+use crate::error::LgsimError;
+use crate::format::parse_error;
+use crate::pin::*;
+use crate::types::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+pub const MAX_ADDRESS_BITS: usize = 16;
+pub const MAX_DATA_BITS: usize = 64;
+pub const DEFAULT_ADDRESS_BITS: usize = 4;
+pub const DEFAULT_DATA_BITS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    Ram,
+    Rom,
+}
+
+/// Addressable memory. Reads are asynchronous: the data outputs always show
+/// the word at the current address. A RAM writes its data inputs on the
+/// rising edge of its clock while write-enable is high, so address and data
+/// should be stable before the clock rises.
+///
+/// Inputs are `a0..a(n-1)`, then for RAM `d0..d(m-1), write_enable, clock`.
+/// Outputs are `q0..q(m-1)`. Bits are least significant first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryGate {
+    pub id: GateId,
+    pub kind: MemoryKind,
+    pub address_bits: usize,
+    pub data_bits: usize,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
+    pub output: Vec<PinId>,
+    pub contents: Vec<u64>,
+    last_clock: PinValue,
+}
+
+/// Fails unless both widths are within the supported range.
+pub fn check_widths(address_bits: usize, data_bits: usize) -> Result<(), LgsimError> {
+    if !(1..=MAX_ADDRESS_BITS).contains(&address_bits) {
+        return Err(LgsimError::InvalidWidth {
+            width: address_bits,
+            max: MAX_ADDRESS_BITS,
+        });
+    }
+    if !(1..=MAX_DATA_BITS).contains(&data_bits) {
+        return Err(LgsimError::InvalidWidth {
+            width: data_bits,
+            max: MAX_DATA_BITS,
+        });
+    }
+    Ok(())
+}
+
+impl MemoryGate {
+    /// Creates a zero-filled memory. Widths outside the supported range are
+    /// clamped; use `check_widths` to report them instead.
+    pub fn new(
+        kind: MemoryKind,
+        address_bits: usize,
+        data_bits: usize,
+        id: GateId,
+        ids: &mut IdAllocator,
+    ) -> Self {
+        let address_bits = address_bits.clamp(1, MAX_ADDRESS_BITS);
+        let data_bits = data_bits.clamp(1, MAX_DATA_BITS);
+        let input_count = match kind {
+            MemoryKind::Ram => address_bits + data_bits + 2,
+            MemoryKind::Rom => address_bits,
+        };
+        let (pins, input, output) = gate_pins(id, input_count, data_bits, ids);
+        Self {
+            id,
+            kind,
+            address_bits,
+            data_bits,
+            pins,
+            input,
+            output,
+            contents: vec![0; 1 << address_bits],
+            last_clock: 0,
+        }
+    }
+
+    pub fn words(&self) -> usize {
+        self.contents.len()
+    }
+
+    pub fn data_mask(&self) -> u64 {
+        u64::MAX >> (64 - self.data_bits)
+    }
+
+    pub fn read(&self, address: usize) -> u64 {
+        self.contents.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn write(&mut self, address: usize, value: u64) {
+        let mask = self.data_mask();
+        if let Some(word) = self.contents.get_mut(address) {
+            *word = value & mask;
+        }
+    }
+
+    /// Replaces the contents with `words`, zero-filling the rest. Fails if
+    /// `words` does not fit.
+    pub fn load(&mut self, words: &[u64]) -> Result<(), LgsimError> {
+        if words.len() > self.words() {
+            return Err(LgsimError::MemoryOverflow {
+                words: words.len(),
+                capacity: self.words(),
+            });
+        }
+        let mask = self.data_mask();
+        self.contents.fill(0);
+        for (word, &value) in self.contents.iter_mut().zip(words) {
+            *word = value & mask;
+        }
+        Ok(())
+    }
+
+    fn bits(&self, pins: &[PinId]) -> u64 {
+        read_bus(&self.pins, pins)
+    }
+
+    /// The address currently on the address inputs.
+    pub fn address(&self) -> usize {
+        self.bits(&self.input[..self.address_bits]) as usize
+    }
+
+    /// Writes the data inputs on a rising clock edge. `Chip::simulate` calls
+    /// this for every gate before propagating anything, so all clocked gates
+    /// sample their inputs as they were before the edge.
+    pub fn clock(&mut self) {
+        if self.kind == MemoryKind::Ram {
+            let address = self.address();
+            let data_end = self.address_bits + self.data_bits;
+            let data = self.bits(&self.input[self.address_bits..data_end]);
+            let write_enable = self.bits(&self.input[data_end..data_end + 1]);
+            let clock = self.bits(&self.input[data_end + 1..]) as PinValue;
+            if clock == 1 && self.last_clock == 0 && write_enable == 1 {
+                self.write(address, data);
+            }
+            self.last_clock = clock;
+        }
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        self.clock();
+        let word = self.read(self.address());
+        write_bus(&mut self.pins, &self.output, word);
+        word != 0
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryFormat {
+    IntelHex,
+    /// Words back to back, each `ceil(data_bits / 8)` bytes, little-endian.
+    RawBinary,
+    /// Whitespace-separated hexadecimal words; `#` starts a comment.
+    PlainHex,
+}
+
+impl MemoryFormat {
+    pub const ALL: [MemoryFormat; 3] = [
+        MemoryFormat::IntelHex,
+        MemoryFormat::RawBinary,
+        MemoryFormat::PlainHex,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MemoryFormat::IntelHex => "Intel HEX",
+            MemoryFormat::RawBinary => "Raw binary",
+            MemoryFormat::PlainHex => "Plain hex",
+        }
+    }
+}
+
+fn bytes_per_word(data_bits: usize) -> usize {
+    data_bits.div_ceil(8)
+}
+
+/// Packs a byte image into little-endian words.
+fn words_from_bytes(bytes: &[u8], data_bits: usize) -> Vec<u64> {
+    bytes
+        .chunks(bytes_per_word(data_bits))
+        .map(|chunk| chunk.iter().rev().fold(0u64, |acc, &b| acc << 8 | b as u64))
+        .collect()
+}
+
+fn bytes_from_words(words: &[u64], data_bits: usize) -> Vec<u8> {
+    let width = bytes_per_word(data_bits);
+    words
+        .iter()
+        .flat_map(|w| w.to_le_bytes().into_iter().take(width))
+        .collect()
+}
+
+/// Decodes memory contents from a file in `format`. Byte-addressed formats
+/// (Intel HEX, raw binary) hold each word in `ceil(data_bits / 8)` bytes,
+/// little-endian. Fails if the contents need more than `max_words` words.
+pub fn parse_contents(
+    format: MemoryFormat,
+    data: &[u8],
+    data_bits: usize,
+    max_words: usize,
+) -> Result<Vec<u64>, LgsimError> {
+    let text = || {
+        std::str::from_utf8(data).map_err(|e| LgsimError::NotText {
+            offset: e.valid_up_to(),
+        })
+    };
+    let words = match format {
+        MemoryFormat::RawBinary => words_from_bytes(data, data_bits),
+        MemoryFormat::IntelHex => {
+            let text = text()?;
+            let max_bytes = max_words * bytes_per_word(data_bits);
+            words_from_bytes(&parse_intel_hex(text, max_bytes)?, data_bits)
+        }
+        MemoryFormat::PlainHex => parse_plain_hex(text()?)?,
+    };
+    if words.len() > max_words {
+        return Err(LgsimError::MemoryOverflow {
+            words: words.len(),
+            capacity: max_words,
+        });
+    }
+    Ok(words)
+}
+
+/// Encodes memory contents in `format`. Trailing zero words are left out.
+pub fn write_contents(format: MemoryFormat, words: &[u64], data_bits: usize) -> Vec<u8> {
+    let used = words.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+    let words = &words[..used];
+    match format {
+        MemoryFormat::RawBinary => bytes_from_words(words, data_bits),
+        MemoryFormat::IntelHex => write_intel_hex(&bytes_from_words(words, data_bits)).into_bytes(),
+        MemoryFormat::PlainHex => {
+            let digits = data_bits.div_ceil(4);
+            let mut out = String::new();
+            for row in words.chunks(16) {
+                let line: Vec<String> = row
+                    .iter()
+                    .map(|w| format!("{:0digits$x}", w, digits = digits))
+                    .collect();
+                let _ = writeln!(out, "{}", line.join(" "));
+            }
+            out.into_bytes()
+        }
+    }
+}
+
+fn parse_plain_hex(text: &str) -> Result<Vec<u64>, LgsimError> {
+    let mut words = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() {
+                continue;
+            }
+            let digits = token.trim_start_matches("0x");
+            let word = u64::from_str_radix(digits, 16)
+                .map_err(|_| parse_error(idx + 1, format!("{} is not a hex number", token)))?;
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+fn parse_intel_hex(text: &str, max_bytes: usize) -> Result<Vec<u8>, LgsimError> {
+    let mut image: Vec<u8> = Vec::new();
+    let mut base = 0usize;
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(hex) = line.strip_prefix(':') else {
+            return Err(parse_error(line_no, "record does not start with ':'"));
+        };
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(parse_error(line_no, "malformed record"));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| parse_error(line_no, "record is not hexadecimal"))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(parse_error(line_no, "record length does not match"));
+        }
+        if bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)) != 0 {
+            return Err(parse_error(line_no, "checksum mismatch"));
+        }
+        let offset = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        let payload = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => {
+                let start = base + offset;
+                let end = start + payload.len();
+                if end > max_bytes {
+                    return Err(parse_error(
+                        line_no,
+                        format!("address {:#x} is beyond the end of memory", end - 1),
+                    ));
+                }
+                if image.len() < end {
+                    image.resize(end, 0);
+                }
+                image[start..end].copy_from_slice(payload);
+            }
+            0x01 => break,
+            0x02 | 0x04 if payload.len() == 2 => {
+                let value = (payload[0] as usize) << 8 | payload[1] as usize;
+                base = if bytes[3] == 0x02 {
+                    value << 4
+                } else {
+                    value << 16
+                };
+            }
+            // Start address records do not affect memory contents.
+            0x03 | 0x05 => {}
+            kind => {
+                return Err(parse_error(
+                    line_no,
+                    format!("unsupported record type {:02x}", kind),
+                ));
+            }
+        }
+    }
+    Ok(image)
+}
+
+fn intel_hex_record(out: &mut String, kind: u8, offset: u16, payload: &[u8]) {
+    let mut bytes = vec![payload.len() as u8, (offset >> 8) as u8, offset as u8, kind];
+    bytes.extend_from_slice(payload);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |acc, &b| acc.wrapping_add(b))
+        .wrapping_neg();
+    bytes.push(checksum);
+    out.push(':');
+    for b in bytes {
+        let _ = write!(out, "{:02X}", b);
+    }
+    out.push('\n');
+}
+
+fn write_intel_hex(image: &[u8]) -> String {
+    let mut out = String::new();
+    let mut upper = 0usize;
+    for (row, chunk) in image.chunks(16).enumerate() {
+        let address = row * 16;
+        if address >> 16 != upper {
+            upper = address >> 16;
+            intel_hex_record(&mut out, 0x04, 0, &(upper as u16).to_be_bytes());
+        }
+        intel_hex_record(&mut out, 0x00, address as u16, chunk);
+    }
+    intel_hex_record(&mut out, 0x01, 0, &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(format: MemoryFormat, text: &str, data_bits: usize, max_words: usize) -> Vec<u64> {
+        parse_contents(format, text.as_bytes(), data_bits, max_words).unwrap()
+    }
+
+    fn error_line(result: Result<Vec<u64>, LgsimError>) -> usize {
+        match result {
+            Err(LgsimError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn intel_hex_reads_data_records() {
+        let text = ":03000000010203F7\n\n:020010000405E5\n:00000001FF\n:01000000AA55\n";
+        let words = parse(MemoryFormat::IntelHex, text, 8, 32);
+        let mut expected = vec![0; 0x12];
+        expected[..3].copy_from_slice(&[1, 2, 3]);
+        expected[0x10..].copy_from_slice(&[4, 5]);
+        // Everything after the end-of-file record is ignored.
+        assert_eq!(words, expected);
+
+        // Two bytes per 16-bit word, low byte first.
+        assert_eq!(
+            parse(MemoryFormat::IntelHex, text, 16, 16),
+            [0x0201, 0x0003, 0, 0, 0, 0, 0, 0, 0x0504]
+        );
+    }
+
+    #[test]
+    fn intel_hex_applies_extended_addresses() {
+        // Linear base 0x0001 puts the record at 0x10000.
+        let text = ":020000040001F9\n:01000000AB54\n:00000001FF\n";
+        let words = parse(MemoryFormat::IntelHex, text, 8, 1 << 17);
+        assert_eq!(words.len(), 0x10001);
+        assert_eq!(words[0x10000], 0xab);
+        assert!(words[..0x10000].iter().all(|&w| w == 0));
+    }
+
+    #[test]
+    fn intel_hex_errors_name_the_line() {
+        let hex = |text: &str| parse_contents(MemoryFormat::IntelHex, text.as_bytes(), 8, 16);
+        assert_eq!(error_line(hex(":03000000010203F7\n:03000000010203F8\n")), 2);
+        assert_eq!(error_line(hex("03000000010203F7\n")), 1);
+        assert_eq!(error_line(hex(":030000000102F7\n")), 1);
+        assert_eq!(error_line(hex(":0300000001020\n")), 1);
+        assert_eq!(error_line(hex(":0300000001G203F7\n")), 1);
+        assert_eq!(error_line(hex("\n:00000006FA\n")), 2);
+        // One byte past a 16-word memory.
+        assert_eq!(error_line(hex(":01001000AA45\n")), 1);
+    }
+
+    #[test]
+    fn intel_hex_round_trips() {
+        let words: Vec<u64> = (0..300).map(|k| k * 7 % 256).collect();
+        let text = write_contents(MemoryFormat::IntelHex, &words, 8);
+        assert_eq!(
+            parse_contents(MemoryFormat::IntelHex, &text, 8, 1 << 16).unwrap(),
+            words
+        );
+        let wide: Vec<u64> = (0..40).map(|k| k << 12 | k).collect();
+        let text = write_contents(MemoryFormat::IntelHex, &wide, 24);
+        assert_eq!(
+            parse_contents(MemoryFormat::IntelHex, &text, 24, 64).unwrap(),
+            wide
+        );
+    }
+
+    #[test]
+    fn plain_hex_reads_words() {
+        let text = "0x1f, 2 # comment\n\nABCD ff\n";
+        assert_eq!(
+            parse(MemoryFormat::PlainHex, text, 16, 4),
+            [0x1f, 2, 0xabcd, 0xff]
+        );
+        let bad = parse_contents(MemoryFormat::PlainHex, b"1 2\n3 x4\n", 8, 16);
+        assert_eq!(error_line(bad), 2);
+
+        let words = [0x1, 0xfe, 0, 0x7, 0, 0];
+        let text = write_contents(MemoryFormat::PlainHex, &words, 8);
+        assert_eq!(text, b"01 fe 00 07\n");
+        assert_eq!(
+            parse_contents(MemoryFormat::PlainHex, &text, 8, 16).unwrap(),
+            words[..4]
+        );
+    }
+
+    #[test]
+    fn raw_binary_packs_little_endian_words() {
+        let bytes = [0x34, 0x12, 0x78, 0x56, 0x09];
+        assert_eq!(
+            parse_contents(MemoryFormat::RawBinary, &bytes, 12, 8).unwrap(),
+            [0x1234, 0x5678, 0x09]
+        );
+        assert_eq!(
+            write_contents(MemoryFormat::RawBinary, &[0x1234, 0x5678, 0x09, 0], 12),
+            bytes.iter().chain(&[0]).copied().collect::<Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn contents_must_fit() {
+        assert_eq!(
+            parse_contents(MemoryFormat::PlainHex, b"1 2 3", 8, 2),
+            Err(LgsimError::MemoryOverflow {
+                words: 3,
+                capacity: 2,
+            })
+        );
+        assert_eq!(
+            parse_contents(MemoryFormat::RawBinary, &[0; 5], 16, 2),
+            Err(LgsimError::MemoryOverflow {
+                words: 3,
+                capacity: 2,
+            })
+        );
+        assert_eq!(
+            parse_contents(MemoryFormat::PlainHex, b"12 \xff", 8, 16),
+            Err(LgsimError::NotText { offset: 3 })
+        );
+
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        let mut rom = MemoryGate::new(MemoryKind::Rom, 2, 4, id, &mut ids);
+        assert_eq!(
+            rom.load(&[0; 5]),
+            Err(LgsimError::MemoryOverflow {
+                words: 5,
+                capacity: 4,
+            })
+        );
+        // Words are cut to the data width and the rest is cleared.
+        rom.load(&[0x1f, 0x2]).unwrap();
+        assert_eq!(rom.contents, [0xf, 0x2, 0, 0]);
+    }
+
+    #[test]
+    fn ram_writes_on_the_rising_edge() {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        let mut ram = MemoryGate::new(MemoryKind::Ram, 2, 8, id, &mut ids);
+        let input = ram.input.clone();
+        // Address 2, data 0x5a, write enable on, clock low.
+        write_bus(&mut ram.pins, &input[..2], 2);
+        write_bus(&mut ram.pins, &input[2..10], 0x5a);
+        write_bus(&mut ram.pins, &input[10..], 0b01);
+        ram.evaluate();
+        assert_eq!(ram.read(2), 0);
+        write_bus(&mut ram.pins, &input[10..], 0b11);
+        ram.evaluate();
+        assert_eq!(ram.read(2), 0x5a);
+        assert_eq!(read_bus(&ram.pins, &ram.output), 0x5a);
+        // Holding the clock high writes nothing more.
+        write_bus(&mut ram.pins, &input[2..10], 0x11);
+        ram.evaluate();
+        assert_eq!(ram.read(2), 0x5a);
+    }
+}
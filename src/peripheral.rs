@@ -0,0 +1,238 @@
+//! Character I/O for CPU projects: a keyboard that queues typed keys and a
+//! terminal that prints the characters written to it. Both act on the rising
+//! edge of their own clock input, like `MemoryGate`. In the GUI keys come
+//! from typing while a keyboard is selected; the headless runner feeds them
+//! from stdin or a file.
+
+use crate::pin::*;
+use crate::types::*;
+use std::collections::{HashMap, VecDeque};
+
+/// Keys a keyboard holds before further typing is dropped.
+pub const KEYBOARD_BUFFER: usize = 256;
+/// Characters a terminal keeps; older text scrolls away.
+pub const TERMINAL_CHARS: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardGate {
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    /// Inputs `ack, clk`.
+    pub input: Vec<PinId>,
+    /// Outputs `d0..d7, ready`. `d` is the oldest unread key and `ready` is
+    /// high while there is one; a clock edge with `ack` high consumes it.
+    pub output: Vec<PinId>,
+    pub buffer: VecDeque<u8>,
+    last_clock: PinValue,
+}
+
+impl KeyboardGate {
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let (pins, input, output) = gate_pins(id, 2, 9, ids);
+        Self {
+            id,
+            pins,
+            input,
+            output,
+            buffer: VecDeque::new(),
+            last_clock: 0,
+        }
+    }
+
+    /// Queues typed bytes, dropping any that do not fit the buffer.
+    pub fn type_bytes(&mut self, bytes: &[u8]) {
+        let room = KEYBOARD_BUFFER.saturating_sub(self.buffer.len());
+        self.buffer.extend(bytes.iter().take(room));
+    }
+
+    /// Consumes the current key on a rising clock edge with `ack` high. See
+    /// `MemoryGate::clock`.
+    pub fn clock(&mut self) {
+        let clock = read_bus(&self.pins, &self.input[1..]) as PinValue;
+        if clock == 1 && self.last_clock == 0 && read_bus(&self.pins, &self.input[..1]) == 1 {
+            self.buffer.pop_front();
+        }
+        self.last_clock = clock;
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        self.clock();
+        let key = self.buffer.front().map_or(0, |&k| k as u64 | 1 << 8);
+        write_bus(&mut self.pins, &self.output, key);
+        !self.buffer.is_empty()
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerminalGate {
+    pub id: GateId,
+    pub pins: HashMap<PinId, Pin>,
+    /// Inputs `d0..d7, we, clear, clk`. A clock edge with `we` high writes
+    /// `d`: newline starts a line, backspace (8) erases, and other control
+    /// characters are ignored. A clock edge with `clear` high empties the
+    /// screen.
+    pub input: Vec<PinId>,
+    /// Text on the screen.
+    pub text: String,
+    /// Bytes written since the last `take_output`, for the headless runner.
+    unread: Vec<u8>,
+    last_clock: PinValue,
+}
+
+impl TerminalGate {
+    pub fn new(id: GateId, ids: &mut IdAllocator) -> Self {
+        let (pins, input, _) = gate_pins(id, 11, 0, ids);
+        Self {
+            id,
+            pins,
+            input,
+            text: String::new(),
+            unread: Vec::new(),
+            last_clock: 0,
+        }
+    }
+
+    /// Bytes written since the last call, exactly as the circuit sent them.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.unread)
+    }
+
+    fn write(&mut self, byte: u8) {
+        match byte {
+            b'\n' | b' '..=b'~' => self.text.push(byte as char),
+            8 => {
+                self.text.pop();
+            }
+            _ => return,
+        }
+        if self.unread.len() < TERMINAL_CHARS {
+            self.unread.push(byte);
+        }
+        if self.text.len() > TERMINAL_CHARS {
+            // Only ASCII is ever pushed, so any index is a char boundary.
+            self.text.drain(..self.text.len() - TERMINAL_CHARS);
+        }
+    }
+
+    /// Writes or clears on a rising clock edge. See `MemoryGate::clock`.
+    pub fn clock(&mut self) {
+        let clock = read_bus(&self.pins, &self.input[10..]) as PinValue;
+        if clock == 1 && self.last_clock == 0 {
+            if read_bus(&self.pins, &self.input[9..10]) == 1 {
+                self.text.clear();
+            } else if read_bus(&self.pins, &self.input[8..9]) == 1 {
+                self.write(read_bus(&self.pins, &self.input[..8]) as u8);
+            }
+        }
+        self.last_clock = clock;
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        self.clock();
+        !self.text.is_empty()
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::Gate;
+
+    fn drive(pins: &mut HashMap<PinId, Pin>, bus: &[PinId], value: u64) {
+        write_bus(pins, bus, value);
+    }
+
+    #[test]
+    fn keyboard_hands_out_keys_in_order() {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        let mut k = KeyboardGate::new(id, &mut ids);
+        let (ack, clk) = (k.input[..1].to_vec(), k.input[1..].to_vec());
+        assert!(!k.evaluate());
+        assert_eq!(read_bus(&k.pins, &k.output), 0);
+
+        k.type_bytes(b"ab");
+        k.evaluate();
+        assert_eq!(read_bus(&k.pins, &k.output), b'a' as u64 | 1 << 8);
+        // A clock edge without ack keeps the key.
+        drive(&mut k.pins, &clk, 1);
+        k.evaluate();
+        drive(&mut k.pins, &clk, 0);
+        drive(&mut k.pins, &ack, 1);
+        k.evaluate();
+        assert_eq!(k.buffer.front(), Some(&b'a'));
+        drive(&mut k.pins, &clk, 1);
+        k.evaluate();
+        assert_eq!(read_bus(&k.pins, &k.output), b'b' as u64 | 1 << 8);
+
+        k.type_bytes(&[b'x'; KEYBOARD_BUFFER + 10]);
+        assert_eq!(k.buffer.len(), KEYBOARD_BUFFER);
+    }
+
+    #[test]
+    fn terminal_prints_on_the_clock() {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        let mut t = TerminalGate::new(id, &mut ids);
+        let input = t.input.clone();
+        let send = |t: &mut TerminalGate, byte: u8, we: u64, clear: u64| {
+            drive(&mut t.pins, &input[..8], byte as u64);
+            drive(&mut t.pins, &input[8..9], we);
+            drive(&mut t.pins, &input[9..10], clear);
+            drive(&mut t.pins, &input[10..], 0);
+            t.evaluate();
+            drive(&mut t.pins, &input[10..], 1);
+            t.evaluate();
+        };
+        for byte in *b"hi\x07\nyo\x08u" {
+            send(&mut t, byte, 1, 0);
+        }
+        send(&mut t, b'!', 0, 0);
+        assert_eq!(t.text, "hi\nyu");
+        // The runner sees the bytes as sent, backspace included.
+        assert_eq!(t.take_output(), b"hi\nyo\x08u");
+        assert!(t.take_output().is_empty());
+
+        send(&mut t, b'z', 1, 1);
+        assert_eq!(t.text, "");
+        assert!(!t.evaluate());
+
+        for _ in 0..TERMINAL_CHARS + 5 {
+            send(&mut t, b'.', 1, 0);
+        }
+        assert_eq!(t.text.len(), TERMINAL_CHARS);
+    }
+
+    #[test]
+    fn copies_keep_keys_and_text() {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        let mut k = KeyboardGate::new(id, &mut ids);
+        k.type_bytes(b"ok");
+        let id = ids.gate();
+        let mut t = TerminalGate::new(id, &mut ids);
+        t.text.push_str("hello");
+
+        let mut id_map = HashMap::new();
+        match Gate::Keyboard(k).clone_with_new_ids(&mut id_map, &mut ids) {
+            Ok(Gate::Keyboard(copy)) => assert_eq!(copy.buffer, b"ok"),
+            other => panic!("expected a keyboard, got {:?}", other),
+        }
+        match Gate::Terminal(t).clone_with_new_ids(&mut id_map, &mut ids) {
+            Ok(Gate::Terminal(copy)) => assert_eq!(copy.text, "hello"),
+            other => panic!("expected a terminal, got {:?}", other),
+        }
+    }
+}
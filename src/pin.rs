@@ -1,12 +1,72 @@
 use crate::types::*;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-// --- GLOBAL ID GENERATOR ---
-// This ensures every single object (Gate, Pin, Chip Instance) gets a unique number.
-static GLOBAL_ID_COUNTER: AtomicUsize = AtomicUsize::new(1);
+// --- ID ALLOCATOR ---
+// Every chip owns one of these, so IDs only depend on how that chip was built.
+// Rebuilding the same circuit in the same order always yields the same IDs.
+// A clone carries on from the same number with a counter of its own; only a
+// chip instance shares its parent's counter (see `share`), so IDs stay unique
+// across the hierarchy.
+#[derive(Debug)]
+pub struct IdAllocator {
+    next: Arc<AtomicUsize>,
+}
 
-pub fn next_uuid() -> usize {
-    GLOBAL_ID_COUNTER.fetch_add(1, Ordering::Relaxed)
+impl Default for IdAllocator {
+    fn default() -> Self {
+        // 0 is left for the root chip itself.
+        Self {
+            next: Arc::new(AtomicUsize::new(1)),
+        }
+    }
+}
+
+impl Clone for IdAllocator {
+    fn clone(&self) -> Self {
+        Self {
+            next: Arc::new(AtomicUsize::new(self.peek())),
+        }
+    }
+}
+
+impl PartialEq for IdAllocator {
+    fn eq(&self, other: &Self) -> bool {
+        self.peek() == other.peek()
+    }
+}
+
+impl Eq for IdAllocator {}
+
+impl IdAllocator {
+    /// An allocator drawing from the same counter, for a chip instance placed
+    /// in the chip that owns this one.
+    pub fn share(&self) -> Self {
+        Self {
+            next: Arc::clone(&self.next),
+        }
+    }
+
+    fn peek(&self) -> usize {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    pub fn next_id(&mut self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn pin(&mut self) -> PinId {
+        PinId(self.next_id())
+    }
+
+    pub fn gate(&mut self) -> GateId {
+        GateId(self.next_id())
+    }
+
+    pub fn chip(&mut self) -> ChipId {
+        ChipId(self.next_id())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -19,15 +79,14 @@ pub enum PinType {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Pin {
-    pub id: usize,
+    pub id: PinId,
     pub kind: PinType,
     pub val: Option<PinValue>,
 }
 
 impl Pin {
-    pub fn new(kind: PinType, _gate_id: usize, val: PinValue) -> Self {
-        // FIX: Use global counter
-        let id = next_uuid();
+    pub fn new(kind: PinType, _gate_id: GateId, val: PinValue, ids: &mut IdAllocator) -> Self {
+        let id = ids.pin();
         let val_opt = if val == 42 { None } else { Some(val) };
         Pin {
             id,
@@ -36,3 +95,44 @@ impl Pin {
         }
     }
 }
+
+/// Allocates `inputs` input pins and `outputs` output pins for gate `id`.
+/// Inputs start at 0 and outputs unset, like the pins of the basic gates.
+pub fn gate_pins(
+    id: GateId,
+    inputs: usize,
+    outputs: usize,
+    ids: &mut IdAllocator,
+) -> (HashMap<PinId, Pin>, Vec<PinId>, Vec<PinId>) {
+    let mut pins = HashMap::new();
+    let mut add = |kind: PinType, val: PinValue, ids: &mut IdAllocator| {
+        let pin = Pin::new(kind, id, val, ids);
+        pins.insert(pin.id, pin);
+        pin.id
+    };
+    let input = (0..inputs)
+        .map(|_| add(PinType::GateInput, 0, ids))
+        .collect();
+    let output = (0..outputs)
+        .map(|_| add(PinType::GateOutput, 42, ids))
+        .collect();
+    (pins, input, output)
+}
+
+/// Reads the pins of `bus` as a number, least significant bit first. Unset
+/// pins read as 0.
+pub fn read_bus(pins: &HashMap<PinId, Pin>, bus: &[PinId]) -> u64 {
+    bus.iter().enumerate().fold(0, |acc, (bit, pin)| {
+        let val = pins.get(pin).and_then(|p| p.val).unwrap_or(0) as u64;
+        acc | (val & 1) << bit
+    })
+}
+
+/// Drives the pins of `bus` with the bits of `value`.
+pub fn write_bus(pins: &mut HashMap<PinId, Pin>, bus: &[PinId], value: u64) {
+    for (bit, pin) in bus.iter().enumerate() {
+        if let Some(p) = pins.get_mut(pin) {
+            p.val = Some((value >> bit & 1) as PinValue);
+        }
+    }
+}
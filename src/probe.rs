@@ -0,0 +1,413 @@
+//! Probes on pins anywhere in the chip hierarchy, the watch list that shows
+//! their values and the waveform recorder they feed.
+//!
+//! A probe path names gates by ID from the board down, then a pin range of
+//! the last one: `12/40/out0-7` is outputs 0 to 7 of gate 40 inside chip
+//! instance 12, and `12/in3` is input 3 of the instance itself. A range is
+//! read as a bus, first pin in bit 0.
+
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::Gate;
+use crate::types::*;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Samples kept per probe; older ones scroll away.
+pub const MAX_SAMPLES: usize = 1024;
+/// Widest bus a probe can read.
+pub const MAX_PROBE_BITS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Hex,
+    Decimal,
+}
+
+impl Radix {
+    pub const ALL: [Radix; 3] = [Radix::Binary, Radix::Hex, Radix::Decimal];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Radix::Binary => "bin",
+            Radix::Hex => "hex",
+            Radix::Decimal => "dec",
+        }
+    }
+
+    /// `value` as read from a bus of `width` bits; `None` (a pin that has no
+    /// value yet) shows as `x`.
+    pub fn format(self, value: Option<u64>, width: usize) -> String {
+        let Some(value) = value else {
+            return "x".to_string();
+        };
+        match self {
+            Radix::Binary => format!("{:0w$b}", value, w = width),
+            Radix::Hex => format!("0x{:0w$X}", value, w = width.div_ceil(4)),
+            Radix::Decimal => value.to_string(),
+        }
+    }
+}
+
+/// A value typed for a probe: decimal, or hex/binary with `0x`/`0b`.
+pub fn parse_value(text: &str) -> Result<u64, LgsimError> {
+    let text = text.trim();
+    let lower = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| LgsimError::InvalidValue(text.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinSide {
+    Input,
+    Output,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbePath {
+    /// Gates from the board down; every one but the last is a chip instance.
+    pub gates: Vec<GateId>,
+    pub side: PinSide,
+    /// First and last pin index, inclusive.
+    pub first: usize,
+    pub last: usize,
+}
+
+impl ProbePath {
+    pub fn width(&self) -> usize {
+        self.last - self.first + 1
+    }
+
+    /// The probed gate, looked up from `board`.
+    fn gate<'a>(&self, board: &'a Chip) -> Option<&'a Gate> {
+        board.gate_at(&self.gates)
+    }
+
+    /// The probed pins, or an error naming what the path does not match.
+    pub fn pins(&self, board: &Chip) -> Result<Vec<PinId>, LgsimError> {
+        let gate = self
+            .gate(board)
+            .ok_or_else(|| LgsimError::UnknownProbe(self.to_string()))?;
+        let pins = match self.side {
+            PinSide::Input => gate.input(),
+            PinSide::Output => gate.output(),
+        };
+        pins.get(self.first..=self.last)
+            .map(<[PinId]>::to_vec)
+            .ok_or_else(|| LgsimError::UnknownProbe(self.to_string()))
+    }
+
+    /// The bus value, or `None` if the path no longer matches or a pin has
+    /// no value yet.
+    pub fn read(&self, board: &Chip) -> Option<u64> {
+        let gate = self.gate(board)?;
+        let pins = match self.side {
+            PinSide::Input => gate.input(),
+            PinSide::Output => gate.output(),
+        };
+        pins.get(self.first..=self.last)?
+            .iter()
+            .enumerate()
+            .try_fold(0, |acc, (bit, pin)| {
+                let val = gate.pins().get(pin)?.val?;
+                Some(acc | (val as u64 & 1) << bit)
+            })
+    }
+}
+
+impl fmt::Display for ProbePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for id in &self.gates {
+            write!(f, "{}/", id.0)?;
+        }
+        let side = match self.side {
+            PinSide::Input => "in",
+            PinSide::Output => "out",
+        };
+        if self.first == self.last {
+            write!(f, "{}{}", side, self.first)
+        } else {
+            write!(f, "{}{}-{}", side, self.first, self.last)
+        }
+    }
+}
+
+impl std::str::FromStr for ProbePath {
+    type Err = LgsimError;
+
+    fn from_str(text: &str) -> Result<Self, LgsimError> {
+        let bad = || LgsimError::UnknownProbe(text.to_string());
+        let mut segments: Vec<&str> = text.trim().split('/').collect();
+        let pins = segments.pop().ok_or_else(bad)?;
+        let gates = segments
+            .iter()
+            .map(|s| s.trim().parse().map(GateId).map_err(|_| bad()))
+            .collect::<Result<Vec<GateId>, _>>()?;
+        if gates.is_empty() {
+            return Err(bad());
+        }
+        let (side, range) = if let Some(range) = pins.strip_prefix("in") {
+            (PinSide::Input, range)
+        } else if let Some(range) = pins.strip_prefix("out") {
+            (PinSide::Output, range)
+        } else {
+            return Err(bad());
+        };
+        let (first, last) = match range.split_once('-') {
+            Some((a, b)) => (a.parse(), b.parse()),
+            None => (range.parse(), range.parse()),
+        };
+        let (first, last): (usize, usize) = (first.map_err(|_| bad())?, last.map_err(|_| bad())?);
+        if last < first || last - first >= MAX_PROBE_BITS {
+            return Err(bad());
+        }
+        Ok(Self {
+            gates,
+            side,
+            first,
+            last,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub name: String,
+    pub path: ProbePath,
+    pub radix: Radix,
+    /// Recorded values, oldest first.
+    pub samples: VecDeque<Option<u64>>,
+}
+
+/// The probes of a board and their recorded waveforms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchList {
+    pub probes: Vec<Probe>,
+    /// Whether `sample` records anything.
+    pub recording: bool,
+}
+
+impl WatchList {
+    /// Adds a probe after checking that `path` matches pins of `board`.
+    /// Buses default to hex, single pins to binary.
+    pub fn add(&mut self, name: &str, path: ProbePath, board: &Chip) -> Result<(), LgsimError> {
+        if self.probes.iter().any(|p| p.name == name) {
+            return Err(LgsimError::ProbeNameTaken(name.to_string()));
+        }
+        path.pins(board)?;
+        let radix = if path.width() > 1 {
+            Radix::Hex
+        } else {
+            Radix::Binary
+        };
+        self.probes.push(Probe {
+            name: name.to_string(),
+            path,
+            radix,
+            samples: VecDeque::new(),
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.probes.retain(|p| p.name != name);
+    }
+
+    /// Records the current value of every probe, if recording.
+    pub fn sample(&mut self, board: &Chip) {
+        if !self.recording {
+            return;
+        }
+        for probe in &mut self.probes {
+            if probe.samples.len() == MAX_SAMPLES {
+                probe.samples.pop_front();
+            }
+            probe.samples.push_back(probe.path.read(board));
+        }
+    }
+
+    pub fn clear_samples(&mut self) {
+        for probe in &mut self.probes {
+            probe.samples.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+
+    /// A board with one input driving an inverter chip instance. Returns the
+    /// board, the instance and the NOT gate inside it.
+    fn board() -> (Chip, GateId, GateId) {
+        let inverter = crate::circuit! {
+            inputs: [a];
+            let y = not(a);
+            outputs: [y];
+        }
+        .unwrap();
+        let mut b = ChipBuilder::new();
+        let x = b.input();
+        let instance = b.chip(&inverter);
+        b.connect(x, instance.input(0));
+        b.output(instance.output(0));
+        let board = b.build().unwrap();
+        let Some(Gate::Chip(inner)) = board.gates.get(&instance.id) else {
+            panic!("expected a chip instance");
+        };
+        let not = *inner.gates.keys().next().unwrap();
+        (board, instance.id, not)
+    }
+
+    fn path(text: &str) -> ProbePath {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn values_parse_and_format() {
+        assert_eq!(parse_value("42"), Ok(42));
+        assert_eq!(parse_value(" 0x2a "), Ok(42));
+        assert_eq!(parse_value("0B101010"), Ok(42));
+        for bad in ["", "0x", "12a", "-1", "0b2"] {
+            assert_eq!(
+                parse_value(bad),
+                Err(LgsimError::InvalidValue(bad.to_string()))
+            );
+        }
+
+        assert_eq!(Radix::Binary.format(Some(5), 4), "0101");
+        assert_eq!(Radix::Hex.format(Some(5), 9), "0x005");
+        assert_eq!(Radix::Decimal.format(Some(300), 9), "300");
+        assert_eq!(Radix::Hex.format(None, 8), "x");
+    }
+
+    #[test]
+    fn paths_parse_and_display() {
+        let p = path("12/40/out0-7");
+        assert_eq!(
+            p,
+            ProbePath {
+                gates: vec![GateId(12), GateId(40)],
+                side: PinSide::Output,
+                first: 0,
+                last: 7,
+            }
+        );
+        assert_eq!(p.width(), 8);
+        assert_eq!(p.to_string(), "12/40/out0-7");
+
+        let p = path(" 12/in3 ");
+        assert_eq!((p.side, p.first, p.last), (PinSide::Input, 3, 3));
+        assert_eq!(p.to_string(), "12/in3");
+        assert_eq!(path("5/out2-2").to_string(), "5/out2");
+        assert_eq!(path("5/out0-63").width(), MAX_PROBE_BITS);
+
+        for bad in [
+            "in3",
+            "12/x3",
+            "12/out",
+            "a/out0",
+            "12/out3-1",
+            "12/out0-64",
+            "12//out0",
+        ] {
+            assert_eq!(
+                bad.parse::<ProbePath>(),
+                Err(LgsimError::UnknownProbe(bad.to_string())),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn probes_read_nested_pins() {
+        let (mut board, instance, not) = board();
+        let outer = path(&format!("{}/in0", instance.0));
+        let inner = path(&format!("{}/{}/out0", instance.0, not.0));
+        assert_eq!(outer.pins(&board).unwrap().len(), 1);
+        assert_eq!(inner.pins(&board).unwrap().len(), 1);
+
+        let input = board.input[0];
+        for x in [0, 1] {
+            board.set_pin(&input, Some(x));
+            board.simulate().unwrap();
+            assert_eq!(outer.read(&board), Some(x as u64));
+            assert_eq!(inner.read(&board), Some(1 - x as u64));
+        }
+        let both = path(&format!("{}/{}/in0-0", instance.0, not.0));
+        assert_eq!(both.read(&board), Some(1));
+
+        // Paths that run past a gate's pins or through a plain gate.
+        for text in [
+            format!("{}/out1", instance.0),
+            format!("{}/{}/in0", instance.0, instance.0),
+            format!("{}/{}/in0", instance.0, not.0 + 1000),
+            format!("{}/{}/in0", not.0, not.0),
+        ] {
+            let p = path(&text);
+            assert_eq!(p.pins(&board), Err(LgsimError::UnknownProbe(text)));
+            assert_eq!(p.read(&board), None);
+        }
+    }
+
+    #[test]
+    fn watch_list_records_samples() {
+        let (mut board, instance, not) = board();
+        let mut watch = WatchList::default();
+        watch
+            .add("x", path(&format!("{}/in0", instance.0)), &board)
+            .unwrap();
+        watch
+            .add(
+                "y",
+                path(&format!("{}/{}/out0-0", instance.0, not.0)),
+                &board,
+            )
+            .unwrap();
+        assert_eq!(
+            watch.add("x", path(&format!("{}/out0", instance.0)), &board),
+            Err(LgsimError::ProbeNameTaken("x".to_string()))
+        );
+        assert_eq!(
+            watch.add("z", path("999/out0"), &board),
+            Err(LgsimError::UnknownProbe("999/out0".to_string()))
+        );
+        assert_eq!(watch.probes[0].radix, Radix::Binary);
+
+        // Nothing is recorded until recording starts.
+        watch.sample(&board);
+        assert!(watch.probes[0].samples.is_empty());
+
+        watch.recording = true;
+        let input = board.input[0];
+        for x in [1, 0, 1] {
+            board.set_pin(&input, Some(x));
+            board.simulate().unwrap();
+            watch.sample(&board);
+        }
+        let samples = |w: &WatchList, k: usize| w.probes[k].samples.iter().copied().collect();
+        let got: Vec<Option<u64>> = samples(&watch, 0);
+        assert_eq!(got, [Some(1), Some(0), Some(1)]);
+        let got: Vec<Option<u64>> = samples(&watch, 1);
+        assert_eq!(got, [Some(0), Some(1), Some(0)]);
+
+        for _ in 0..MAX_SAMPLES {
+            watch.sample(&board);
+        }
+        assert_eq!(watch.probes[0].samples.len(), MAX_SAMPLES);
+        watch.clear_samples();
+        assert!(watch.probes[1].samples.is_empty());
+        watch.remove("x");
+        assert_eq!(watch.probes.len(), 1);
+        assert_eq!(watch.probes[0].name, "y");
+    }
+}
@@ -0,0 +1,217 @@
+//! Project files: the board with everything on it, where each gate sits and
+//! the bend points of its wires.
+//!
+//! ```text
+//! lgsim-project 1
+//! chip - in out
+//!   input in out 1
+//!   and in 2 3 out 4
+//!   ...
+//! end
+//! at 0 -320 40
+//! at 1 80 40
+//! bend 1 2 40 40 40 80
+//! ```
+//!
+//! The board is a chip block in the library format (see `format`), board
+//! inputs and outputs included. `at <gate> <x> <y>` places the block's gates,
+//! counted from 0 in the order they are listed, and `bend <from> <to> <x>
+//! <y> ...` lists the bend points of the wire between two pin labels. Gates
+//! and pins get fresh IDs when the file is read.
+
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::format::{self, Lines, Token, parse_error};
+use crate::types::*;
+use eframe::egui::Pos2;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Version of the project file layout.
+const FILE_FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub board: Chip,
+    /// Where each gate of the board is drawn, board inputs and outputs
+    /// included.
+    pub positions: HashMap<GateId, Pos2>,
+    pub wire_bends: HashMap<(PinId, PinId), Vec<Pos2>>,
+}
+
+pub fn write_project(project: &Project) -> Result<String, LgsimError> {
+    let mut out = format!("lgsim-project {}\n", FILE_FORMAT);
+    format::write_chip(&mut out, &project.board, 0)?;
+    // `write_chip` lists gates in ID order and labels pins with their IDs.
+    let mut ids: Vec<&GateId> = project.board.gates.keys().collect();
+    ids.sort();
+    for (k, id) in ids.into_iter().enumerate() {
+        if let Some(pos) = project.positions.get(id) {
+            let _ = writeln!(out, "at {} {} {}", k, pos.x, pos.y);
+        }
+    }
+    let wires = project.board.wires();
+    let mut bent: Vec<(&(PinId, PinId), &Vec<Pos2>)> = project
+        .wire_bends
+        .iter()
+        .filter(|(wire, bends)| !bends.is_empty() && wires.contains(wire))
+        .collect();
+    bent.sort_by_key(|(wire, _)| **wire);
+    for ((src, dest), bends) in bent {
+        let points: String = bends.iter().map(|p| format!(" {} {}", p.x, p.y)).collect();
+        let _ = writeln!(out, "bend {} {}{}", src.0, dest.0, points);
+    }
+    Ok(out)
+}
+
+pub fn read_project(text: &str) -> Result<Project, LgsimError> {
+    let mut lines = Lines::new(text);
+    let header = [
+        Token::Word("lgsim-project".to_string()),
+        Token::Word(FILE_FORMAT.to_string()),
+    ];
+    match lines.next_tokens()? {
+        Some((_, t)) if t == header => {}
+        Some((line, _)) => return Err(parse_error(line, "not an lgsim project file")),
+        None => return Err(parse_error(1, "file is empty")),
+    }
+    let (line, tokens) = match lines.next_tokens()? {
+        Some((line, t)) if t.first() == Some(&Token::Word("chip".to_string())) => (line, t),
+        Some((line, _)) => return Err(parse_error(line, "expected the board's chip block")),
+        None => return Err(parse_error(2, "missing the board")),
+    };
+    let node = format::parse_chip(&mut lines, &tokens, line)?;
+    let built = format::build_labeled(&node, &|name| name.to_string(), line)?;
+
+    let mut positions = HashMap::new();
+    let mut wire_bends = HashMap::new();
+    while let Some((line, tokens)) = lines.next_tokens()? {
+        let words: Vec<&str> = tokens
+            .iter()
+            .map(|t| match t {
+                Token::Word(w) => Ok(w.as_str()),
+                Token::Text(_) => Err(parse_error(line, "unexpected name")),
+            })
+            .collect::<Result<_, _>>()?;
+        let number = |w: &str| {
+            w.parse::<f32>()
+                .map_err(|_| parse_error(line, format!("{} is not a number", w)))
+        };
+        let label = |w: &str| {
+            w.parse::<usize>()
+                .ok()
+                .and_then(|l| built.pins.get(&l).copied())
+                .ok_or_else(|| parse_error(line, format!("unknown pin {}", w)))
+        };
+        match words.as_slice() {
+            ["at", gate, x, y] => {
+                let id = gate
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|k| built.gates.get(k))
+                    .ok_or_else(|| parse_error(line, format!("unknown gate {}", gate)))?;
+                positions.insert(*id, Pos2::new(number(x)?, number(y)?));
+            }
+            ["bend", src, dest, points @ ..] if !points.is_empty() && points.len() % 2 == 0 => {
+                let wire = (label(src)?, label(dest)?);
+                if !built.chip.wires().contains(&wire) {
+                    return Err(parse_error(line, "bend points on a missing wire"));
+                }
+                let bends = points
+                    .chunks(2)
+                    .map(|p| Ok(Pos2::new(number(p[0])?, number(p[1])?)))
+                    .collect::<Result<Vec<_>, LgsimError>>()?;
+                wire_bends.insert(wire, bends);
+            }
+            _ => {
+                return Err(parse_error(
+                    line,
+                    "expected: at <gate> <x> <y> or bend <from> <to> <x> <y> ...",
+                ));
+            }
+        }
+    }
+    Ok(Project {
+        board: built.chip,
+        positions,
+        wire_bends,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::{Gate, GateType};
+    use crate::memory::{MemoryGate, MemoryKind};
+
+    /// An input driving a NOT gate that drives an output, next to a ROM.
+    fn project() -> Project {
+        let mut board = Chip::new(ChipId(0));
+        let input = Gate::new(GateType::Source, vec![], &mut board.ids);
+        let not = Gate::new(GateType::Not, vec![], &mut board.ids);
+        let output = Gate::new(GateType::Output, vec![], &mut board.ids);
+        let id = board.ids.gate();
+        let mut rom = MemoryGate::new(MemoryKind::Rom, 4, 8, id, &mut board.ids);
+        rom.load(&[0x12, 0x34, 0xff]).unwrap();
+        let wire = (input.output()[0], not.input()[0]);
+        let driven = (not.output()[0], output.input()[0]);
+        let mut positions = HashMap::new();
+        for (k, gate) in [input, not, output, Gate::Memory(rom)]
+            .into_iter()
+            .enumerate()
+        {
+            positions.insert(gate.id(), Pos2::new(k as f32 * 40.0, -12.5));
+            board.add_gate(gate);
+        }
+        board.connect_pins(wire.0, wire.1).unwrap();
+        board.connect_pins(driven.0, driven.1).unwrap();
+        let bends = vec![Pos2::new(20.0, 60.0), Pos2::new(60.0, 60.0)];
+        Project {
+            board,
+            positions,
+            wire_bends: HashMap::from([(wire, bends)]),
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_layout_bends_and_memory() {
+        let original = project();
+        let text = write_project(&original).unwrap();
+        let loaded = read_project(&text).unwrap();
+        assert_eq!(write_project(&loaded).unwrap(), text);
+
+        let sorted = |p: &Project| {
+            let mut ids: Vec<GateId> = p.board.gates.keys().copied().collect();
+            ids.sort();
+            ids
+        };
+        for (old, new) in sorted(&original).iter().zip(sorted(&loaded)) {
+            assert_eq!(original.positions[old], loaded.positions[&new]);
+            let kind = |p: &Project, id: &GateId| p.board.gates[id].gate_type();
+            assert_eq!(kind(&original, old), kind(&loaded, &new));
+        }
+        let bends: Vec<&Vec<Pos2>> = loaded.wire_bends.values().collect();
+        assert_eq!(bends, original.wire_bends.values().collect::<Vec<_>>());
+        let (wire, _) = loaded.wire_bends.iter().next().unwrap();
+        assert!(loaded.board.wires().contains(wire));
+
+        let rom = loaded.board.gates.values().find_map(|g| match g {
+            Gate::Memory(m) => Some(m),
+            _ => None,
+        });
+        assert_eq!(rom.unwrap().contents[..3], [0x12, 0x34, 0xff]);
+    }
+
+    #[test]
+    fn rejects_unknown_gates_and_wires() {
+        let text = write_project(&project()).unwrap();
+        let bad_gate = format!("{}at 9 0 0\n", text);
+        assert!(matches!(
+            read_project(&bad_gate),
+            Err(LgsimError::Parse { .. })
+        ));
+        let bad_wire = text.replace("bend ", "bend 999 ");
+        assert!(read_project(&bad_wire).is_err());
+        assert!(read_project("lgsim-library 1\n").is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Clocked multi-bit components: registers, counters and shift registers.
+//!
+//! The simulator has no global clock, so each of these has a clock input and
+//! acts on its rising edge, like `MemoryGate`. `reset` is asynchronous and
+//! clears the stored value as soon as it is high. Buses are listed least
+//! significant bit first.
+
+use crate::error::LgsimError;
+use crate::pin::*;
+use crate::types::*;
+use std::collections::HashMap;
+
+pub const MAX_REGISTER_BITS: usize = 64;
+pub const DEFAULT_REGISTER_BITS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    /// Inputs `d0..d(n-1), load, enable, reset, clock`; outputs `q0..q(n-1)`.
+    /// Stores `d` on a clock edge while both `load` and `enable` are high.
+    Register,
+    /// Inputs `up, enable, reset, clock`; outputs `q0..q(n-1), carry`.
+    /// Counts up while `up` is high, down otherwise; `carry` is high when
+    /// the next count wraps around.
+    Counter,
+    /// Serial in, parallel out. Inputs `serial_in, enable, reset, clock`;
+    /// outputs `q0..q(n-1)`. Shifts towards the most significant bit.
+    ShiftSipo,
+    /// Parallel in, serial out. Inputs `d0..d(n-1), load, enable, reset,
+    /// clock`; output `serial_out`, the most significant bit. A clock edge
+    /// with `load` high stores `d`, otherwise it shifts towards the MSB.
+    ShiftPiso,
+}
+
+impl RegisterKind {
+    pub const ALL: [RegisterKind; 4] = [
+        RegisterKind::Register,
+        RegisterKind::Counter,
+        RegisterKind::ShiftSipo,
+        RegisterKind::ShiftPiso,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RegisterKind::Register => "Register",
+            RegisterKind::Counter => "Counter",
+            RegisterKind::ShiftSipo => "Shift Register (SIPO)",
+            RegisterKind::ShiftPiso => "Shift Register (PISO)",
+        }
+    }
+
+    /// Keyword used in the chip text format.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            RegisterKind::Register => "register",
+            RegisterKind::Counter => "counter",
+            RegisterKind::ShiftSipo => "sipo",
+            RegisterKind::ShiftPiso => "piso",
+        }
+    }
+
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.keyword() == keyword)
+    }
+
+    fn pin_counts(self, width: usize) -> (usize, usize) {
+        match self {
+            RegisterKind::Register => (width + 4, width),
+            RegisterKind::Counter => (4, width + 1),
+            RegisterKind::ShiftSipo => (4, width),
+            RegisterKind::ShiftPiso => (width + 4, 1),
+        }
+    }
+}
+
+pub fn check_width(width: usize) -> Result<(), LgsimError> {
+    if !(1..=MAX_REGISTER_BITS).contains(&width) {
+        return Err(LgsimError::InvalidWidth {
+            width,
+            max: MAX_REGISTER_BITS,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterGate {
+    pub id: GateId,
+    pub kind: RegisterKind,
+    pub width: usize,
+    pub pins: HashMap<PinId, Pin>,
+    pub input: Vec<PinId>,
+    pub output: Vec<PinId>,
+    /// The stored value.
+    pub value: u64,
+    last_clock: PinValue,
+}
+
+impl RegisterGate {
+    /// Creates a cleared register. A width outside the supported range is
+    /// clamped; use `check_width` to report it instead.
+    pub fn new(kind: RegisterKind, width: usize, id: GateId, ids: &mut IdAllocator) -> Self {
+        let width = width.clamp(1, MAX_REGISTER_BITS);
+        let (inputs, outputs) = kind.pin_counts(width);
+        let (pins, input, output) = gate_pins(id, inputs, outputs, ids);
+        Self {
+            id,
+            kind,
+            width,
+            pins,
+            input,
+            output,
+            value: 0,
+            last_clock: 0,
+        }
+    }
+
+    pub fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+
+    /// Reads a control input by its position from the end. Every kind ends
+    /// with `enable, reset, clock` (3, 2, 1), preceded by `load`, `up` or
+    /// `serial_in` (4).
+    fn control(&self, from_end: usize) -> bool {
+        let pin = self.input[self.input.len() - from_end];
+        read_bus(&self.pins, &[pin]) == 1
+    }
+
+    /// Updates the stored value on a rising clock edge, or clears it while
+    /// `reset` is high. See `MemoryGate::clock`.
+    pub fn clock(&mut self) {
+        let clock = self.control(1) as PinValue;
+        let edge = clock == 1 && self.last_clock == 0;
+        self.last_clock = clock;
+        let mask = self.mask();
+        if self.control(2) {
+            self.value = 0;
+        } else if edge && self.control(3) {
+            self.value = match self.kind {
+                RegisterKind::Register if self.control(4) => {
+                    read_bus(&self.pins, &self.input[..self.width])
+                }
+                RegisterKind::Register => self.value,
+                RegisterKind::Counter if self.control(4) => self.value.wrapping_add(1) & mask,
+                RegisterKind::Counter => self.value.wrapping_sub(1) & mask,
+                RegisterKind::ShiftSipo => (self.value << 1 | self.control(4) as u64) & mask,
+                RegisterKind::ShiftPiso if self.control(4) => {
+                    read_bus(&self.pins, &self.input[..self.width])
+                }
+                RegisterKind::ShiftPiso => (self.value << 1) & mask,
+            };
+        }
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        self.clock();
+        let mask = self.mask();
+
+        match self.kind {
+            RegisterKind::Register | RegisterKind::ShiftSipo => {
+                write_bus(&mut self.pins, &self.output, self.value);
+            }
+            RegisterKind::Counter => {
+                let up = self.control(4);
+                let wraps = if up {
+                    self.value == mask
+                } else {
+                    self.value == 0
+                };
+                let carry = wraps && self.control(3);
+                write_bus(&mut self.pins, &self.output[..self.width], self.value);
+                write_bus(&mut self.pins, &self.output[self.width..], carry as u64);
+            }
+            RegisterKind::ShiftPiso => {
+                let msb = self.value >> (self.width - 1) & 1;
+                write_bus(&mut self.pins, &self.output, msb);
+            }
+        }
+        self.value != 0
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(kind: RegisterKind, width: usize) -> RegisterGate {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        RegisterGate::new(kind, width, id, &mut ids)
+    }
+
+    /// Sets a control input by its position from the end, as `control`
+    /// reads it.
+    fn set(r: &mut RegisterGate, from_end: usize, val: PinValue) {
+        let pin = r.input[r.input.len() - from_end];
+        r.set_pin(&pin, Some(val));
+    }
+
+    const CLOCK: usize = 1;
+    const RESET: usize = 2;
+    const ENABLE: usize = 3;
+    /// `load`, `up` or `serial_in`, depending on the kind.
+    const MODE: usize = 4;
+
+    /// Drives the clock low, then high, and returns the outputs.
+    fn pulse(r: &mut RegisterGate) -> u64 {
+        set(r, CLOCK, 0);
+        r.evaluate();
+        set(r, CLOCK, 1);
+        r.evaluate();
+        read_bus(&r.pins, &r.output)
+    }
+
+    fn set_data(r: &mut RegisterGate, value: u64) {
+        let data = r.input[..r.width].to_vec();
+        write_bus(&mut r.pins, &data, value);
+    }
+
+    #[test]
+    fn register_loads_when_enabled() {
+        let mut r = register(RegisterKind::Register, 4);
+        set_data(&mut r, 0b1010);
+        set(&mut r, MODE, 1);
+        set(&mut r, ENABLE, 0);
+        assert_eq!(pulse(&mut r), 0);
+        set(&mut r, ENABLE, 1);
+        assert_eq!(pulse(&mut r), 0b1010);
+
+        // Holding the clock high does not load again.
+        set_data(&mut r, 0b0101);
+        r.evaluate();
+        assert_eq!(r.value, 0b1010);
+        set(&mut r, MODE, 0);
+        assert_eq!(pulse(&mut r), 0b1010);
+
+        // Reset clears without a clock edge.
+        set(&mut r, RESET, 1);
+        r.evaluate();
+        assert_eq!(read_bus(&r.pins, &r.output), 0);
+        set(&mut r, MODE, 1);
+        assert_eq!(pulse(&mut r), 0);
+    }
+
+    #[test]
+    fn counter_wraps_with_carry() {
+        let mut c = register(RegisterKind::Counter, 2);
+        set(&mut c, ENABLE, 1);
+        set(&mut c, MODE, 1);
+        // Outputs are q0, q1, carry.
+        assert_eq!(pulse(&mut c), 1);
+        assert_eq!(pulse(&mut c), 2);
+        assert_eq!(pulse(&mut c), 3 | 0b100);
+        assert_eq!(pulse(&mut c), 0);
+
+        set(&mut c, MODE, 0);
+        c.evaluate();
+        assert_eq!(read_bus(&c.pins, &c.output), 0b100);
+        assert_eq!(pulse(&mut c), 3);
+        set(&mut c, ENABLE, 0);
+        assert_eq!(pulse(&mut c), 3);
+    }
+
+    #[test]
+    fn shift_registers_move_towards_the_msb() {
+        let mut sipo = register(RegisterKind::ShiftSipo, 4);
+        set(&mut sipo, ENABLE, 1);
+        for bit in [1, 0, 1, 1] {
+            set(&mut sipo, MODE, bit);
+            pulse(&mut sipo);
+        }
+        assert_eq!(read_bus(&sipo.pins, &sipo.output), 0b1011);
+        // The oldest bit falls off the end.
+        set(&mut sipo, MODE, 0);
+        assert_eq!(pulse(&mut sipo), 0b0110);
+
+        let mut piso = register(RegisterKind::ShiftPiso, 4);
+        set(&mut piso, ENABLE, 1);
+        set_data(&mut piso, 0b1001);
+        set(&mut piso, MODE, 1);
+        assert_eq!(pulse(&mut piso), 1);
+        set(&mut piso, MODE, 0);
+        let out: Vec<u64> = (0..4).map(|_| pulse(&mut piso)).collect();
+        assert_eq!(out, [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn widths_are_checked() {
+        assert!(check_width(1).is_ok());
+        assert!(check_width(MAX_REGISTER_BITS).is_ok());
+        assert_eq!(
+            check_width(0),
+            Err(LgsimError::InvalidWidth {
+                width: 0,
+                max: MAX_REGISTER_BITS,
+            })
+        );
+        assert_eq!(
+            register(RegisterKind::Register, 100).width,
+            MAX_REGISTER_BITS
+        );
+        assert_eq!(
+            RegisterKind::from_keyword("sipo"),
+            Some(RegisterKind::ShiftSipo)
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Wire geometry on the canvas: orthogonal polylines through the user's
+//! bend points, hit testing against them, junction dots where a net fans
+//! out, and an A* auto-router that keeps wires off gate bodies.
+
+use crate::camera::{GRID_SIZE, snap_to_grid};
+use eframe::egui::{Pos2, Rect, Vec2};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Extra cost of a turn in the auto-router, in grid steps.
+const BEND_PENALTY: u32 = 4;
+/// How far (in grid cells) the router may wander outside the box spanned by
+/// the two pins.
+const SEARCH_MARGIN: i32 = 20;
+const MAX_EXPANSIONS: usize = 50_000;
+
+/// Builds a Manhattan polyline from `src` to `dest` through the user's bend
+/// points. Wires leave `src` and enter `dest` horizontally.
+pub fn manhattan_path(src: Pos2, dest: Pos2, bends: &[Pos2]) -> Vec<Pos2> {
+    let mut points = vec![src];
+    if bends.is_empty() {
+        let mid = (src.x + dest.x) / 2.0;
+        points.push(Pos2::new(mid, src.y));
+        points.push(Pos2::new(mid, dest.y));
+    } else {
+        let mut prev = src;
+        for &bend in bends {
+            points.push(Pos2::new(bend.x, prev.y));
+            points.push(bend);
+            prev = bend;
+        }
+        points.push(Pos2::new(prev.x, dest.y));
+    }
+    points.push(dest);
+    simplify(points)
+}
+
+/// Drops repeated points and the middle of three collinear points.
+fn simplify(points: Vec<Pos2>) -> Vec<Pos2> {
+    let mut out: Vec<Pos2> = Vec::with_capacity(points.len());
+    for p in points {
+        if out.last() == Some(&p) {
+            continue;
+        }
+        if out.len() >= 2 {
+            let a = out[out.len() - 2];
+            let b = out[out.len() - 1];
+            if (a.x == b.x && b.x == p.x) || (a.y == b.y && b.y == p.y) {
+                out.pop();
+            }
+        }
+        out.push(p);
+    }
+    out
+}
+
+pub fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let len_sq = ab.length_sq();
+    if len_sq == 0.0 {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+pub fn distance_to_path(p: Pos2, path: &[Pos2]) -> f32 {
+    path.windows(2)
+        .map(|w| distance_to_segment(p, w[0], w[1]))
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Index at which a new bend at `p` disturbs the route `src -> bends -> dest`
+/// the least.
+pub fn bend_insert_index(src: Pos2, dest: Pos2, bends: &[Pos2], p: Pos2) -> usize {
+    let manhattan = |a: Pos2, b: Pos2| (a.x - b.x).abs() + (a.y - b.y).abs();
+    let controls: Vec<Pos2> = std::iter::once(src)
+        .chain(bends.iter().copied())
+        .chain(std::iter::once(dest))
+        .collect();
+    (0..controls.len() - 1)
+        .min_by(|&i, &j| {
+            let cost = |k: usize| {
+                manhattan(controls[k], p) + manhattan(p, controls[k + 1])
+                    - manhattan(controls[k], controls[k + 1])
+            };
+            cost(i).total_cmp(&cost(j))
+        })
+        .unwrap_or(0)
+}
+
+/// Points where the wires of one net split. `paths` must all start at the
+/// net's driver.
+pub fn junctions(paths: &[Vec<Pos2>]) -> Vec<Pos2> {
+    let mut out: Vec<Pos2> = Vec::new();
+    for (i, a) in paths.iter().enumerate() {
+        let inner = a.iter().skip(1).take(a.len().saturating_sub(2));
+        for &v in inner {
+            let shared = paths
+                .iter()
+                .enumerate()
+                .any(|(j, b)| j != i && distance_to_path(v, b) < 0.5);
+            if shared && !out.iter().any(|o| o.distance(v) < 0.5) {
+                out.push(v);
+            }
+        }
+    }
+    out
+}
+
+type Cell = (i32, i32);
+
+const DIRS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+fn to_cell(p: Pos2) -> Cell {
+    let p = snap_to_grid(p);
+    (
+        (p.x / GRID_SIZE).round() as i32,
+        (p.y / GRID_SIZE).round() as i32,
+    )
+}
+
+fn to_world(c: Cell) -> Pos2 {
+    Pos2::new(c.0 as f32 * GRID_SIZE, c.1 as f32 * GRID_SIZE)
+}
+
+/// Finds bend points for a wire from `src` to `dest` that keep it off
+/// `obstacles` (gate bodies), preferring short routes with few turns. Returns
+/// `None` when no route exists within the search area.
+pub fn auto_route(src: Pos2, dest: Pos2, obstacles: &[Rect]) -> Option<Vec<Pos2>> {
+    let start = to_cell(src + Vec2::new(GRID_SIZE, 0.0));
+    let goal = to_cell(dest - Vec2::new(GRID_SIZE, 0.0));
+    let min = (
+        start.0.min(goal.0) - SEARCH_MARGIN,
+        start.1.min(goal.1) - SEARCH_MARGIN,
+    );
+    let max = (
+        start.0.max(goal.0) + SEARCH_MARGIN,
+        start.1.max(goal.1) + SEARCH_MARGIN,
+    );
+    let blocked = |c: Cell| {
+        c != start
+            && c != goal
+            && obstacles
+                .iter()
+                .any(|r| r.expand(GRID_SIZE / 2.0).contains(to_world(c)))
+    };
+    let heuristic = |c: Cell| ((c.0 - goal.0).abs() + (c.1 - goal.1).abs()) as u32;
+
+    // State is (cell, index into DIRS of the direction we arrived in).
+    let mut best: HashMap<(Cell, usize), u32> = HashMap::new();
+    let mut came_from: HashMap<(Cell, usize), (Cell, usize)> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    // Wires leave the source heading right.
+    best.insert((start, 0), 0);
+    open.push(Reverse((heuristic(start), 0u32, start, 0usize)));
+
+    let mut expansions = 0;
+    while let Some(Reverse((_, cost, cell, dir))) = open.pop() {
+        if cell == goal {
+            return Some(corners(&came_from, (cell, dir), start));
+        }
+        if best.get(&(cell, dir)).is_some_and(|&c| c < cost) {
+            continue;
+        }
+        expansions += 1;
+        if expansions > MAX_EXPANSIONS {
+            return None;
+        }
+        for (next_dir, (dx, dy)) in DIRS.iter().enumerate() {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if next.0 < min.0 || next.0 > max.0 || next.1 < min.1 || next.1 > max.1 {
+                continue;
+            }
+            if blocked(next) {
+                continue;
+            }
+            let turn = if next_dir == dir { 0 } else { BEND_PENALTY };
+            let next_cost = cost + 1 + turn;
+            if best.get(&(next, next_dir)).is_none_or(|&c| next_cost < c) {
+                best.insert((next, next_dir), next_cost);
+                came_from.insert((next, next_dir), (cell, dir));
+                open.push(Reverse((
+                    next_cost + heuristic(next),
+                    next_cost,
+                    next,
+                    next_dir,
+                )));
+            }
+        }
+    }
+    None
+}
+
+/// Walks the A* result back to `start` and keeps only the cells where the
+/// route turns, plus both ends.
+fn corners(
+    came_from: &HashMap<(Cell, usize), (Cell, usize)>,
+    end: (Cell, usize),
+    start: Cell,
+) -> Vec<Pos2> {
+    let mut states = vec![end];
+    let mut cur = end;
+    while cur.0 != start {
+        cur = came_from[&cur];
+        states.push(cur);
+    }
+    states.reverse();
+    let mut points = vec![to_world(start)];
+    for w in states.windows(2) {
+        if w[0].1 != w[1].1 {
+            points.push(to_world(w[0].0));
+        }
+    }
+    points.push(to_world(end.0));
+    points.dedup();
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether any point of `path`, walked in small steps, is inside `rect`.
+    fn crosses(path: &[Pos2], rect: Rect) -> bool {
+        path.windows(2)
+            .any(|w| (0..=20).any(|k| rect.contains(w[0].lerp(w[1], k as f32 / 20.0))))
+    }
+
+    #[test]
+    fn straight_run_needs_no_turns() {
+        let (src, dest) = (Pos2::new(0.0, 40.0), Pos2::new(200.0, 40.0));
+        let bends = auto_route(src, dest, &[]).unwrap();
+        let path = manhattan_path(src, dest, &bends);
+        assert_eq!(path, vec![src, dest]);
+    }
+
+    #[test]
+    fn detours_around_a_blocked_cell() {
+        let (src, dest) = (Pos2::new(0.0, 0.0), Pos2::new(200.0, 0.0));
+        let gate = Rect::from_min_max(Pos2::new(80.0, -40.0), Pos2::new(120.0, 40.0));
+        let bends = auto_route(src, dest, &[gate]).unwrap();
+        let path = manhattan_path(src, dest, &bends);
+        assert_eq!(path.first(), Some(&src));
+        assert_eq!(path.last(), Some(&dest));
+        assert!(path.len() > 2);
+        assert!(!crosses(&path, gate));
+        for w in path.windows(2) {
+            assert!(w[0].x == w[1].x || w[0].y == w[1].y);
+        }
+    }
+
+    #[test]
+    fn walled_in_pin_is_unreachable() {
+        let (src, dest) = (Pos2::new(0.0, 0.0), Pos2::new(200.0, 0.0));
+        let walls = Rect::from_center_size(
+            dest - Vec2::new(GRID_SIZE, 0.0),
+            Vec2::splat(4.0 * GRID_SIZE),
+        );
+        assert_eq!(auto_route(src, dest, &[walls]), None);
+    }
+}
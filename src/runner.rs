@@ -0,0 +1,343 @@
+//! Runs a chip without the GUI: drives one of its inputs as a clock, feeds
+//! keyboards from a byte stream and collects what terminals print. This is
+//! what `lgsim run` uses. Test vector files, read and written here, list
+//! input bits and the outputs expected for them.
+
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::gate::Gate;
+use crate::library::{ChipLibrary, NAMESPACE_SEPARATOR};
+use crate::pin::read_bus;
+use crate::types::*;
+use std::collections::VecDeque;
+
+/// Loads chip `name` from a library file. Imported names are namespaced, so
+/// `name` may be given with or without its `lib::` prefix.
+pub fn load_chip(text: &str, name: &str) -> Result<Chip, LgsimError> {
+    let mut library = ChipLibrary::new();
+    let names = library.import(text)?;
+    let suffix = format!("{}{}", NAMESPACE_SEPARATOR, name);
+    let found = names
+        .iter()
+        .find(|n| *n == name || n.ends_with(&suffix))
+        .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))?;
+    library
+        .get(found)
+        .cloned()
+        .ok_or_else(|| LgsimError::UnknownTemplate(name.to_string()))
+}
+
+pub struct Runner {
+    pub chip: Chip,
+    /// Position of the input toggled once per cycle, if any.
+    clock: Option<usize>,
+    /// Bytes not yet handed to the keyboards.
+    pending: VecDeque<u8>,
+}
+
+impl Runner {
+    pub fn new(chip: Chip, clock: Option<usize>) -> Result<Self, LgsimError> {
+        if let Some(index) = clock {
+            check_input(&chip, index)?;
+        }
+        Ok(Self {
+            chip,
+            clock,
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn set_input(&mut self, index: usize, val: PinValue) -> Result<(), LgsimError> {
+        check_input(&self.chip, index)?;
+        let pin = self.chip.input[index];
+        self.chip.set_pin(&pin, Some(val));
+        Ok(())
+    }
+
+    /// The chip's outputs as a number, first output in bit 0.
+    pub fn outputs(&self) -> u64 {
+        read_bus(&self.chip.pins, &self.chip.output)
+    }
+
+    /// Sets every input from `vector`, simulates once and returns the
+    /// outputs.
+    pub fn apply(&mut self, vector: &TestVector) -> Result<Vec<Option<PinValue>>, LgsimError> {
+        for (index, &val) in vector.inputs.iter().enumerate() {
+            self.set_input(index, val)?;
+        }
+        self.chip.simulate()?;
+        Ok(self
+            .chip
+            .output
+            .iter()
+            .map(|p| self.chip.pins.get(p).and_then(|p| p.val))
+            .collect())
+    }
+
+    /// Queues bytes for the keyboards. They are typed one at a time, each
+    /// once every keyboard has consumed the one before.
+    pub fn type_bytes(&mut self, bytes: &[u8]) {
+        self.pending.extend(bytes);
+    }
+
+    fn keyboards_idle(&mut self) -> bool {
+        let mut idle = true;
+        self.chip.for_each_gate_mut(&mut |gate| {
+            if let Gate::Keyboard(k) = gate {
+                idle &= k.buffer.is_empty();
+            }
+        });
+        idle
+    }
+
+    /// Runs one clock cycle, low then high, and returns the bytes the
+    /// terminals printed. Without a clock input this simulates once.
+    pub fn cycle(&mut self) -> Result<Vec<u8>, LgsimError> {
+        if self.keyboards_idle()
+            && let Some(byte) = self.pending.pop_front()
+        {
+            self.chip.for_each_gate_mut(&mut |gate| {
+                if let Gate::Keyboard(k) = gate {
+                    k.type_bytes(&[byte]);
+                }
+            });
+        }
+        match self.clock {
+            Some(index) => {
+                self.set_input(index, 0)?;
+                self.chip.simulate()?;
+                self.set_input(index, 1)?;
+                self.chip.simulate()?;
+            }
+            None => {
+                self.chip.simulate()?;
+            }
+        }
+        let mut printed = Vec::new();
+        self.chip.for_each_gate_mut(&mut |gate| {
+            if let Gate::Terminal(t) = gate {
+                printed.extend(t.take_output());
+            }
+        });
+        Ok(printed)
+    }
+}
+
+/// One line of a test vector file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    /// A value for every input, first input first.
+    pub inputs: Vec<PinValue>,
+    /// Expected outputs, `None` where any value is accepted. Empty when the
+    /// line gives no outputs.
+    pub outputs: Vec<Option<PinValue>>,
+}
+
+/// Parses a test vector file for a chip with `inputs` inputs and `outputs`
+/// outputs. Each line holds the input bits in input order, then optionally
+/// whitespace and the expected output bits, `x` for an output that is not
+/// checked. `_` may separate bits and `#` starts a comment.
+pub fn parse_vectors(
+    text: &str,
+    inputs: usize,
+    outputs: usize,
+) -> Result<Vec<TestVector>, LgsimError> {
+    let mut vectors = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| LgsimError::Parse {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let Some(given) = fields.next() else {
+            continue;
+        };
+        let bits = |field: &str, dont_care: bool| -> Result<Vec<Option<PinValue>>, LgsimError> {
+            field
+                .chars()
+                .filter(|c| *c != '_')
+                .map(|c| match c {
+                    '0' => Ok(Some(0)),
+                    '1' => Ok(Some(1)),
+                    'x' | 'X' if dont_care => Ok(None),
+                    _ => Err(error(format!("bad bit '{}'", c))),
+                })
+                .collect()
+        };
+        let input = bits(given, false)?;
+        if input.len() != inputs {
+            return Err(error(format!(
+                "expected {} input bits, found {}",
+                inputs,
+                input.len()
+            )));
+        }
+        let expected = match fields.next() {
+            Some(field) => bits(field, true)?,
+            None => Vec::new(),
+        };
+        if !expected.is_empty() && expected.len() != outputs {
+            return Err(error(format!(
+                "expected {} output bits, found {}",
+                outputs,
+                expected.len()
+            )));
+        }
+        if fields.next().is_some() {
+            return Err(error("unexpected text after the outputs".to_string()));
+        }
+        vectors.push(TestVector {
+            inputs: input.into_iter().flatten().collect(),
+            outputs: expected,
+        });
+    }
+    Ok(vectors)
+}
+
+/// Writes vectors in the format `parse_vectors` reads.
+pub fn format_vectors(vectors: &[TestVector]) -> String {
+    let bit = |b: &Option<PinValue>| match b {
+        Some(v) => char::from(b'0' + v),
+        None => 'x',
+    };
+    let mut text = String::new();
+    for v in vectors {
+        text.extend(v.inputs.iter().map(|&b| bit(&Some(b))));
+        if !v.outputs.is_empty() {
+            text.push(' ');
+            text.extend(v.outputs.iter().map(bit));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn check_input(chip: &Chip, index: usize) -> Result<(), LgsimError> {
+    if index >= chip.input.len() {
+        return Err(LgsimError::NoSuchInput {
+            index,
+            count: chip.input.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+    use crate::gate::GateType;
+
+    fn nand() -> Chip {
+        crate::circuit! {
+            inputs: [a, b];
+            let y = nand(a, b);
+            outputs: [y];
+        }
+        .unwrap()
+    }
+
+    /// A keyboard wired straight to a terminal, clocked by input 0.
+    fn echo() -> Chip {
+        let mut b = ChipBuilder::new();
+        let clock = b.input();
+        let keyboard = b.gate(GateType::Keyboard);
+        let terminal = b.gate(GateType::Terminal);
+        for bit in 0..8 {
+            b.connect(keyboard.output(bit), terminal.input(bit));
+        }
+        let ready = keyboard.output(8);
+        b.connect(ready, keyboard.input(0));
+        b.connect(ready, terminal.input(8));
+        b.connect(clock, keyboard.input(1));
+        b.connect(clock, terminal.input(10));
+        b.build().unwrap()
+    }
+
+    #[test]
+    fn vectors_parse_and_format() {
+        let text = "# a b -> y\n00 1\n0_1 x\n\n11 0 # both high\n10\n";
+        let vectors = parse_vectors(text, 2, 1).unwrap();
+        assert_eq!(
+            vectors,
+            [
+                TestVector {
+                    inputs: vec![0, 0],
+                    outputs: vec![Some(1)],
+                },
+                TestVector {
+                    inputs: vec![0, 1],
+                    outputs: vec![None],
+                },
+                TestVector {
+                    inputs: vec![1, 1],
+                    outputs: vec![Some(0)],
+                },
+                TestVector {
+                    inputs: vec![1, 0],
+                    outputs: vec![],
+                },
+            ]
+        );
+        assert_eq!(format_vectors(&vectors), "00 1\n01 x\n11 0\n10\n");
+        assert_eq!(
+            parse_vectors(&format_vectors(&vectors), 2, 1).unwrap(),
+            vectors
+        );
+    }
+
+    #[test]
+    fn vector_errors_name_the_line() {
+        let line_of = |text: &str| match parse_vectors(text, 2, 1) {
+            Err(LgsimError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(line_of("00 1\n012 1\n"), 2);
+        assert_eq!(line_of("0x 1\n"), 1);
+        assert_eq!(line_of("\n00 11\n"), 2);
+        assert_eq!(line_of("00 1 1\n"), 1);
+        assert_eq!(line_of("00 2\n"), 1);
+    }
+
+    #[test]
+    fn applies_vectors() {
+        let mut runner = Runner::new(nand(), None).unwrap();
+        for v in parse_vectors("00 1\n01 1\n10 1\n11 0\n", 2, 1).unwrap() {
+            assert_eq!(runner.apply(&v).unwrap(), v.outputs);
+        }
+        assert_eq!(runner.outputs(), 0);
+        assert_eq!(
+            runner.set_input(2, 1),
+            Err(LgsimError::NoSuchInput { index: 2, count: 2 })
+        );
+        assert!(Runner::new(nand(), Some(5)).is_err());
+    }
+
+    #[test]
+    fn drives_inputs_and_reads_outputs() {
+        let mut runner = Runner::new(nand(), None).unwrap();
+        for (a, b, y) in [(0, 0, 1), (0, 1, 1), (1, 0, 1), (1, 1, 0)] {
+            runner.set_input(0, a).unwrap();
+            runner.set_input(1, b).unwrap();
+            assert!(runner.cycle().unwrap().is_empty());
+            assert_eq!(runner.outputs(), y);
+        }
+        assert_eq!(
+            runner.set_input(2, 1),
+            Err(LgsimError::NoSuchInput { index: 2, count: 2 })
+        );
+        assert!(Runner::new(nand(), Some(5)).is_err());
+    }
+
+    #[test]
+    fn echoes_typed_bytes() {
+        let mut runner = Runner::new(echo(), Some(0)).unwrap();
+        runner.type_bytes(b"hi\n");
+        let mut printed = Vec::new();
+        for _ in 0..6 {
+            printed.extend(runner.cycle().unwrap());
+        }
+        assert_eq!(printed, b"hi\n");
+    }
+}
@@ -0,0 +1,308 @@
+//! Size and cost figures for a board or template, counted through every
+//! nested chip: gates by type, an estimated transistor count, fan-in and
+//! fan-out, nets and hierarchy depth.
+//!
+//! The only primitives are AND and NOT, so a NAND is an AND whose single
+//! load is a NOT. Such pairs are costed as one NAND when the cost table has
+//! a `NAND` entry, which is how the gate would be built in CMOS.
+
+use crate::circuit::Chip;
+use crate::edit;
+use crate::error::LgsimError;
+use crate::gate::{Gate, GateType};
+use crate::types::*;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Transistors per gate, keyed by upper-case type name (`AND`, `NOT`,
+/// `RAM`, ...) plus `NAND` for AND-NOT pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostTable {
+    pub costs: BTreeMap<String, u64>,
+}
+
+impl Default for CostTable {
+    /// Static CMOS: NAND 4, NOT 2, AND as NAND plus NOT.
+    fn default() -> Self {
+        let costs = [("NAND", 4), ("AND", 6), ("NOT", 2)]
+            .into_iter()
+            .map(|(name, cost)| (name.to_string(), cost))
+            .collect();
+        Self { costs }
+    }
+}
+
+impl CostTable {
+    /// Reads `NAME=COST` lines; `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Self, LgsimError> {
+        let mut costs = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| LgsimError::Parse {
+                line: i + 1,
+                message: message.to_string(),
+            };
+            let (name, cost) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected NAME=COST"))?;
+            let cost = cost
+                .trim()
+                .parse()
+                .map_err(|_| error("the cost is not a number"))?;
+            costs.insert(name.trim().to_ascii_uppercase(), cost);
+        }
+        Ok(Self { costs })
+    }
+
+    pub fn to_text(&self) -> String {
+        self.costs
+            .iter()
+            .map(|(name, cost)| format!("{}={}\n", name, cost))
+            .collect()
+    }
+}
+
+fn type_name(kind: GateType) -> String {
+    format!("{:?}", kind).to_ascii_uppercase()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Gates by upper-case type name, with nested chips expanded. Board
+    /// inputs and outputs are not counted.
+    pub gates: BTreeMap<String, usize>,
+    /// Nested chip instances, at any depth.
+    pub instances: usize,
+    /// AND-NOT pairs costed as NANDs.
+    pub nands: usize,
+    pub transistors: u64,
+    /// Types with no entry in the cost table, left out of `transistors`.
+    pub uncosted: Vec<String>,
+    pub max_fan_in: usize,
+    pub max_fan_out: usize,
+    /// Driven nets, each a driver pin and the pins it is wired to.
+    pub nets: usize,
+    /// Levels of chips nested below the top; 0 when there are none.
+    pub depth: usize,
+}
+
+impl Stats {
+    pub fn total_gates(&self) -> usize {
+        self.gates.values().sum()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Gates: {}", self.total_gates())?;
+        for (name, count) in &self.gates {
+            writeln!(f, "  {:<16}{}", name, count)?;
+        }
+        writeln!(f, "Chip instances: {}", self.instances)?;
+        write!(f, "Transistors: {}", self.transistors)?;
+        if self.nands > 0 {
+            write!(f, " ({} AND-NOT pairs as NAND)", self.nands)?;
+        }
+        writeln!(f)?;
+        if !self.uncosted.is_empty() {
+            writeln!(f, "  not costed: {}", self.uncosted.join(", "))?;
+        }
+        writeln!(f, "Max fan-in: {}", self.max_fan_in)?;
+        writeln!(f, "Max fan-out: {}", self.max_fan_out)?;
+        writeln!(f, "Nets: {}", self.nets)?;
+        writeln!(f, "Hierarchy depth: {}", self.depth)
+    }
+}
+
+/// Counts `chip` with every nested chip expanded.
+pub fn stats(chip: &Chip, costs: &CostTable) -> Result<Stats, LgsimError> {
+    let flat = edit::flatten(chip)?;
+    let mut gates: BTreeMap<String, usize> = BTreeMap::new();
+    let mut max_fan_in = 0;
+    for gate in flat.gates.values() {
+        if matches!(gate, Gate::Source(_) | Gate::Output(_)) {
+            continue;
+        }
+        *gates.entry(type_name(gate.gate_type())).or_default() += 1;
+        max_fan_in = max_fan_in.max(gate.input().len());
+    }
+
+    let not_inputs: HashSet<PinId> = flat
+        .gates
+        .values()
+        .filter(|g| matches!(g, Gate::Not(_)))
+        .flat_map(|g| g.input().iter().copied())
+        .collect();
+    let nands = match costs.costs.contains_key("NAND") {
+        true => flat
+            .gates
+            .values()
+            .filter(|g| matches!(g, Gate::And(_)))
+            .filter(|g| {
+                let sinks = flat.connections.get(&g.output()[0]).map(Vec::as_slice);
+                matches!(sinks, Some([sink]) if not_inputs.contains(sink))
+            })
+            .count(),
+        false => 0,
+    };
+    let mut transistors = nands as u64 * costs.costs.get("NAND").copied().unwrap_or(0);
+    let mut uncosted = Vec::new();
+    for (name, &count) in &gates {
+        let count = match name.as_str() {
+            "AND" | "NOT" => count - nands,
+            _ => count,
+        };
+        match costs.costs.get(name) {
+            Some(cost) => transistors += count as u64 * cost,
+            None if count > 0 => uncosted.push(name.clone()),
+            None => {}
+        }
+    }
+
+    Ok(Stats {
+        gates,
+        instances: instances(chip),
+        nands,
+        transistors,
+        uncosted,
+        max_fan_in,
+        max_fan_out: flat.connections.values().map(Vec::len).max().unwrap_or(0),
+        nets: flat.connections.values().filter(|d| !d.is_empty()).count(),
+        depth: depth(chip),
+    })
+}
+
+fn instances(chip: &Chip) -> usize {
+    chip.gates
+        .values()
+        .map(|g| match g {
+            Gate::Chip(c) => 1 + instances(c),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn depth(chip: &Chip) -> usize {
+    chip.gates
+        .values()
+        .map(|g| match g {
+            Gate::Chip(c) => 1 + depth(c),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+
+    fn counts(pairs: &[(&str, usize)]) -> BTreeMap<String, usize> {
+        pairs.iter().map(|(n, c)| (n.to_string(), *c)).collect()
+    }
+
+    #[test]
+    fn and_not_pairs_cost_as_nand() {
+        let mut b = ChipBuilder::new();
+        let (x, y) = (b.input(), b.input());
+        let z = b.nand(x, y);
+        b.output(z);
+        let nand = b.build().unwrap();
+        let s = stats(&nand, &CostTable::default()).unwrap();
+        assert_eq!(s.gates, counts(&[("AND", 1), ("NOT", 1)]));
+        assert_eq!((s.nands, s.transistors), (1, 4));
+
+        // An AND that also drives something else is not a NAND.
+        let mut b = ChipBuilder::new();
+        let (x, y) = (b.input(), b.input());
+        let both = b.and(x, y);
+        let neither = b.not(both);
+        b.output(both);
+        b.output(neither);
+        let chip = b.build().unwrap();
+        let s = stats(&chip, &CostTable::default()).unwrap();
+        assert_eq!((s.nands, s.transistors), (0, 8));
+        assert_eq!((s.max_fan_in, s.max_fan_out, s.nets), (2, 2, 4));
+
+        // Without a NAND cost, pairs are costed gate by gate.
+        let costs = CostTable::parse("AND=6\nNOT=2\n").unwrap();
+        let s = stats(&nand, &costs).unwrap();
+        assert_eq!((s.nands, s.transistors), (0, 8));
+    }
+
+    #[test]
+    fn cost_tables_parse_and_print() {
+        let table =
+            CostTable::parse("# per gate\nand = 3\nNot=1 # inverter\n\nram=1000\n").unwrap();
+        assert_eq!(table.to_text(), "AND=3\nNOT=1\nRAM=1000\n");
+        assert_eq!(CostTable::parse(&table.to_text()).unwrap(), table);
+
+        let line_of = |text: &str| match CostTable::parse(text) {
+            Err(LgsimError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(line_of("AND 3\n"), 1);
+        assert_eq!(line_of("# costs\nNOT=two\n"), 2);
+        assert_eq!(line_of("\n\nAND=-1\n"), 3);
+    }
+
+    #[test]
+    fn counts_nested_instances_and_depth() {
+        let inverter = crate::circuit! {
+            inputs: [a];
+            let y = not(a);
+            outputs: [y];
+        }
+        .unwrap();
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let once = b.instance(&inverter, &[a]);
+        let twice = b.instance(&inverter, &once);
+        b.output(twice[0]);
+        let buffer = b.build().unwrap();
+
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let buffered = b.instance(&buffer, &[a]);
+        let inverted = b.instance(&inverter, &buffered);
+        b.output(inverted[0]);
+        let chip = b.build().unwrap();
+
+        let s = stats(&chip, &CostTable::default()).unwrap();
+        assert_eq!(s.gates, counts(&[("NOT", 3)]));
+        assert_eq!((s.instances, s.depth), (4, 2));
+        assert_eq!(s.transistors, 6);
+        assert_eq!(s.total_gates(), 3);
+
+        let s = stats(&inverter, &CostTable::default()).unwrap();
+        assert_eq!((s.instances, s.depth), (0, 0));
+    }
+
+    #[test]
+    fn lists_uncosted_types() {
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let r = b.gate(GateType::Register);
+        b.connect(a, r.input(0));
+        let ram = b.gate(GateType::Ram);
+        b.connect(r.output(0), ram.input(0));
+        let q = b.not(ram.output(0));
+        b.output(q);
+        let chip = b.build().unwrap();
+
+        let s = stats(&chip, &CostTable::default()).unwrap();
+        assert_eq!(s.uncosted, ["RAM", "REGISTER"]);
+        assert_eq!(s.transistors, 2);
+        assert!(s.to_string().contains("not costed: RAM, REGISTER"));
+        assert_eq!(s.max_fan_in, ram.inputs.len().max(r.inputs.len()));
+
+        let costs = CostTable::parse("register=100\nnot=2\n").unwrap();
+        let s = stats(&chip, &costs).unwrap();
+        assert_eq!(s.uncosted, ["RAM"]);
+        assert_eq!(s.transistors, 102);
+    }
+}
@@ -0,0 +1,444 @@
+//! Built-in parameterized components, built from AND/NOT gates as ordinary
+//! chip templates so they can be inlined and inspected like any other chip.
+//! The reference computer is the exception, wrapping native CPU and memory
+//! gates.
+//!
+//! Multi-bit inputs and outputs are listed least significant bit first.
+
+use crate::builder::{ChipBuilder, PinHandle};
+use crate::circuit::Chip;
+use crate::error::LgsimError;
+use crate::library::ChipLibrary;
+
+/// Folder the components are filed under in the chip library.
+pub const FOLDER: &str = "Standard";
+/// Largest select width of multiplexers, demultiplexers, decoders and
+/// encoders (64 data lines).
+pub const MAX_SELECT_BITS: usize = 6;
+pub const MAX_OPERAND_BITS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    /// Inputs `d0..d(2^n-1), s0..s(n-1)`; output `y`.
+    Mux,
+    /// Inputs `d, s0..s(n-1)`; outputs `y0..y(2^n-1)`.
+    Demux,
+    /// Inputs `s0..s(n-1)`; outputs `y0..y(2^n-1)`, exactly one high.
+    Decoder,
+    /// Inputs `i0..i(2^n-1)`; outputs `y0..y(n-1)`, the index of the highest
+    /// active input, then `valid`.
+    PriorityEncoder,
+    /// Inputs `a, b`; outputs `sum, carry`.
+    HalfAdder,
+    /// Inputs `a, b, carry_in`; outputs `sum, carry_out`.
+    FullAdder,
+    /// Inputs `a0..a(n-1), b0..b(n-1), carry_in`; outputs `s0..s(n-1),
+    /// carry_out`. Built from `FullAdder` instances.
+    RippleAdder,
+    /// Inputs `a0..a(n-1), b0..b(n-1)`; outputs `lt, eq, gt` for `a` compared
+    /// to `b` as unsigned numbers.
+    Comparator,
+    /// The reference CPU with its program ROM and data RAM; see
+    /// `cpu::reference_computer`.
+    ReferenceComputer,
+}
+
+impl Component {
+    pub const ALL: [Component; 9] = [
+        Component::Mux,
+        Component::Demux,
+        Component::Decoder,
+        Component::PriorityEncoder,
+        Component::HalfAdder,
+        Component::FullAdder,
+        Component::RippleAdder,
+        Component::Comparator,
+        Component::ReferenceComputer,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Component::Mux => "Multiplexer",
+            Component::Demux => "Demultiplexer",
+            Component::Decoder => "Decoder",
+            Component::PriorityEncoder => "Priority Encoder",
+            Component::HalfAdder => "Half Adder",
+            Component::FullAdder => "Full Adder",
+            Component::RippleAdder => "Ripple-Carry Adder",
+            Component::Comparator => "Comparator",
+            Component::ReferenceComputer => "Reference Computer",
+        }
+    }
+
+    /// Largest accepted width, or `None` if the component has no width.
+    /// The width is the number of select bits for the selector-style
+    /// components and the operand size for the arithmetic ones.
+    pub fn max_width(self) -> Option<usize> {
+        match self {
+            Component::Mux | Component::Demux | Component::Decoder | Component::PriorityEncoder => {
+                Some(MAX_SELECT_BITS)
+            }
+            Component::RippleAdder | Component::Comparator => Some(MAX_OPERAND_BITS),
+            Component::HalfAdder | Component::FullAdder | Component::ReferenceComputer => None,
+        }
+    }
+
+    /// Library name of the component at `width`, e.g. "Mux 4:1".
+    pub fn template_name(self, width: usize) -> String {
+        let lines = 1usize.checked_shl(width as u32).unwrap_or(0);
+        match self {
+            Component::Mux => format!("Mux {}:1", lines),
+            Component::Demux => format!("Demux 1:{}", lines),
+            Component::Decoder => format!("Decoder {}:{}", width, lines),
+            Component::PriorityEncoder => format!("Priority Encoder {}:{}", lines, width),
+            Component::HalfAdder | Component::FullAdder | Component::ReferenceComputer => {
+                self.label().to_string()
+            }
+            Component::RippleAdder => format!("Adder {}-bit", width),
+            Component::Comparator => format!("Comparator {}-bit", width),
+        }
+    }
+
+    /// Builds the component as an unnamed template. `width` is ignored by
+    /// components without one.
+    pub fn build(self, width: usize) -> Result<Chip, LgsimError> {
+        if let Some(max) = self.max_width()
+            && !(1..=max).contains(&width)
+        {
+            return Err(LgsimError::InvalidWidth { width, max });
+        }
+        match self {
+            Component::Mux => mux(width),
+            Component::Demux => demux(width),
+            Component::Decoder => decoder(width),
+            Component::PriorityEncoder => priority_encoder(width),
+            Component::HalfAdder => half_adder(),
+            Component::FullAdder => full_adder(),
+            Component::RippleAdder => ripple_adder(width),
+            Component::Comparator => comparator(width),
+            Component::ReferenceComputer => crate::cpu::reference_computer(),
+        }
+    }
+
+    /// Adds the component at `width`, and any component it is built from, to
+    /// `library` unless a template of that name is already there. Returns
+    /// the template name.
+    pub fn install(self, width: usize, library: &mut ChipLibrary) -> Result<String, LgsimError> {
+        let name = self.template_name(width);
+        if library.contains(&name) {
+            return Ok(name);
+        }
+        if self == Component::RippleAdder {
+            Component::FullAdder.install(0, library)?;
+        }
+        library.insert(&name, self.build(width)?, FOLDER)?;
+        Ok(name)
+    }
+}
+
+fn inputs(b: &mut ChipBuilder, count: usize) -> Vec<PinHandle> {
+    (0..count).map(|_| b.input()).collect()
+}
+
+/// AND of all `terms`, which must not be empty.
+fn and_all(b: &mut ChipBuilder, terms: &[PinHandle]) -> PinHandle {
+    terms[1..].iter().fold(terms[0], |acc, &t| b.and(acc, t))
+}
+
+/// OR of all `terms`, which must not be empty.
+fn or_all(b: &mut ChipBuilder, terms: &[PinHandle]) -> PinHandle {
+    terms[1..].iter().fold(terms[0], |acc, &t| b.or(acc, t))
+}
+
+/// One line per value of `select`: line `k` is high when `select` equals `k`.
+fn decode_lines(b: &mut ChipBuilder, select: &[PinHandle]) -> Vec<PinHandle> {
+    let inverted: Vec<PinHandle> = select.iter().map(|&s| b.not(s)).collect();
+    (0..1usize << select.len())
+        .map(|k| {
+            let literals: Vec<PinHandle> = (0..select.len())
+                .map(|bit| {
+                    if k >> bit & 1 == 1 {
+                        select[bit]
+                    } else {
+                        inverted[bit]
+                    }
+                })
+                .collect();
+            and_all(b, &literals)
+        })
+        .collect()
+}
+
+fn mux(select_bits: usize) -> Result<Chip, LgsimError> {
+    let mut b = ChipBuilder::new();
+    let mut level = inputs(&mut b, 1 << select_bits);
+    let select = inputs(&mut b, select_bits);
+    // A tree of 2:1 muxes, the first select bit choosing between neighbours.
+    for &s in &select {
+        let ns = b.not(s);
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let lo = b.and(pair[0], ns);
+                let hi = b.and(pair[1], s);
+                b.or(lo, hi)
+            })
+            .collect();
+    }
+    b.output(level[0]);
+    b.build()
+}
+
+fn demux(select_bits: usize) -> Result<Chip, LgsimError> {
+    let mut b = ChipBuilder::new();
+    let data = b.input();
+    let select = inputs(&mut b, select_bits);
+    for line in decode_lines(&mut b, &select) {
+        let y = b.and(data, line);
+        b.output(y);
+    }
+    b.build()
+}
+
+fn decoder(select_bits: usize) -> Result<Chip, LgsimError> {
+    let mut b = ChipBuilder::new();
+    let select = inputs(&mut b, select_bits);
+    for line in decode_lines(&mut b, &select) {
+        b.output(line);
+    }
+    b.build()
+}
+
+fn priority_encoder(bits: usize) -> Result<Chip, LgsimError> {
+    let mut b = ChipBuilder::new();
+    let lines = inputs(&mut b, 1 << bits);
+    // wins[k]: input k is active and no higher input is.
+    let mut wins = vec![lines[lines.len() - 1]; lines.len()];
+    let mut any_higher = lines[lines.len() - 1];
+    for k in (0..lines.len() - 1).rev() {
+        let none_higher = b.not(any_higher);
+        wins[k] = b.and(lines[k], none_higher);
+        any_higher = b.or(any_higher, lines[k]);
+    }
+    for bit in 0..bits {
+        let terms: Vec<PinHandle> = (0..lines.len())
+            .filter(|k| k >> bit & 1 == 1)
+            .map(|k| wins[k])
+            .collect();
+        let y = or_all(&mut b, &terms);
+        b.output(y);
+    }
+    b.output(any_higher);
+    b.build()
+}
+
+fn half_adder() -> Result<Chip, LgsimError> {
+    crate::circuit! {
+        inputs: [a, c];
+        let s = xor(a, c);
+        let carry = and(a, c);
+        outputs: [s, carry];
+    }
+}
+
+fn full_adder() -> Result<Chip, LgsimError> {
+    crate::circuit! {
+        inputs: [a, c, carry_in];
+        let partial = xor(a, c);
+        let s = xor(partial, carry_in);
+        let generate = and(a, c);
+        let propagate = and(partial, carry_in);
+        let carry_out = or(generate, propagate);
+        outputs: [s, carry_out];
+    }
+}
+
+fn ripple_adder(width: usize) -> Result<Chip, LgsimError> {
+    let mut stage = full_adder()?;
+    stage.name = Some(Component::FullAdder.template_name(0));
+    let mut b = ChipBuilder::new();
+    let a = inputs(&mut b, width);
+    let c = inputs(&mut b, width);
+    let mut carry = b.input();
+    let mut sums = Vec::with_capacity(width);
+    for bit in 0..width {
+        let out = b.instance(&stage, &[a[bit], c[bit], carry]);
+        sums.push(out[0]);
+        carry = out[1];
+    }
+    for s in sums {
+        b.output(s);
+    }
+    b.output(carry);
+    b.build()
+}
+
+fn comparator(width: usize) -> Result<Chip, LgsimError> {
+    let mut b = ChipBuilder::new();
+    let a = inputs(&mut b, width);
+    let c = inputs(&mut b, width);
+    // Walk up from the LSB; a higher bit that differs overrides the verdict
+    // of the lower ones.
+    let mut lt: Option<PinHandle> = None;
+    let mut gt: Option<PinHandle> = None;
+    let mut eq: Option<PinHandle> = None;
+    for bit in 0..width {
+        let na = b.not(a[bit]);
+        let nc = b.not(c[bit]);
+        let bit_lt = b.and(na, c[bit]);
+        let bit_gt = b.and(a[bit], nc);
+        let differs = b.or(bit_lt, bit_gt);
+        let bit_eq = b.not(differs);
+        lt = Some(match lt {
+            Some(prev) => {
+                let kept = b.and(bit_eq, prev);
+                b.or(bit_lt, kept)
+            }
+            None => bit_lt,
+        });
+        gt = Some(match gt {
+            Some(prev) => {
+                let kept = b.and(bit_eq, prev);
+                b.or(bit_gt, kept)
+            }
+            None => bit_gt,
+        });
+        eq = Some(match eq {
+            Some(prev) => b.and(prev, bit_eq),
+            None => bit_eq,
+        });
+    }
+    for result in [lt, eq, gt].into_iter().flatten() {
+        b.output(result);
+    }
+    b.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PinValue;
+    use std::cmp::Ordering;
+
+    /// Sets the inputs to `bits`, simulates and returns the outputs as a
+    /// number, first output in bit 0.
+    fn run(chip: &mut Chip, bits: &[PinValue]) -> u128 {
+        for (pin, &bit) in chip.input.clone().iter().zip(bits) {
+            chip.set_pin(pin, Some(bit));
+        }
+        assert!(chip.simulate().unwrap());
+        chip.output.iter().enumerate().fold(0, |acc, (k, p)| {
+            let val = chip.pins[p].val.expect("output left floating");
+            acc | (val as u128) << k
+        })
+    }
+
+    /// `bits` as a number, first bit least significant.
+    fn number(bits: &[PinValue]) -> u128 {
+        bits.iter().rev().fold(0, |acc, &b| acc << 1 | b as u128)
+    }
+
+    /// Every input vector for up to 10 inputs. Wider components get all
+    /// zeros, all ones and a fixed pseudo-random sample.
+    fn vectors(inputs: usize) -> Vec<Vec<PinValue>> {
+        if inputs <= 10 {
+            return (0..1u32 << inputs)
+                .map(|v| (0..inputs).map(|k| (v >> k & 1) as PinValue).collect())
+                .collect();
+        }
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 32 & 1) as PinValue
+        };
+        let mut vectors = vec![vec![0; inputs], vec![1; inputs]];
+        vectors.extend((0..8).map(|_| (0..inputs).map(|_| random()).collect()));
+        vectors
+    }
+
+    /// Checks `component` against `reference`, which maps input bits to the
+    /// outputs packed as `run` returns them, at every width it is built in.
+    fn check(component: Component, reference: impl Fn(usize, &[PinValue]) -> u128) {
+        let widths: Vec<usize> = match component.max_width() {
+            Some(max) => (1..=max).collect(),
+            None => vec![0],
+        };
+        for width in widths {
+            let mut chip = component.build(width).unwrap();
+            for v in vectors(chip.input.len()) {
+                assert_eq!(
+                    run(&mut chip, &v),
+                    reference(width, &v),
+                    "{} with inputs {:?}",
+                    component.template_name(width),
+                    v
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mux_selects_one_input() {
+        check(Component::Mux, |w, v| {
+            v[number(&v[1 << w..]) as usize] as u128
+        });
+    }
+
+    #[test]
+    fn demux_routes_to_one_output() {
+        check(Component::Demux, |_, v| (v[0] as u128) << number(&v[1..]));
+    }
+
+    #[test]
+    fn decoder_raises_one_line() {
+        check(Component::Decoder, |_, v| 1 << number(v));
+    }
+
+    #[test]
+    fn priority_encoder_finds_the_highest_input() {
+        check(Component::PriorityEncoder, |w, v| {
+            match v.iter().rposition(|&b| b == 1) {
+                Some(k) => k as u128 | 1 << w,
+                None => 0,
+            }
+        });
+    }
+
+    #[test]
+    fn adders_add() {
+        check(Component::HalfAdder, |_, v| {
+            number(&v[..1]) + number(&v[1..])
+        });
+        check(Component::FullAdder, |_, v| {
+            v.iter().map(|&b| b as u128).sum()
+        });
+        check(Component::RippleAdder, |w, v| {
+            number(&v[..w]) + number(&v[w..2 * w]) + v[2 * w] as u128
+        });
+    }
+
+    #[test]
+    fn comparator_orders_unsigned_numbers() {
+        check(Component::Comparator, |w, v| {
+            match number(&v[..w]).cmp(&number(&v[w..])) {
+                Ordering::Less => 0b001,
+                Ordering::Equal => 0b010,
+                Ordering::Greater => 0b100,
+            }
+        });
+    }
+
+    #[test]
+    fn widths_out_of_range_are_refused() {
+        assert_eq!(
+            Component::Mux.build(MAX_SELECT_BITS + 1).err(),
+            Some(LgsimError::InvalidWidth {
+                width: MAX_SELECT_BITS + 1,
+                max: MAX_SELECT_BITS,
+            })
+        );
+        assert!(Component::Comparator.build(0).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Input devices placed on the board: push buttons, toggle switches, DIP
+//! switch banks, a hex keypad and constant ties. They have outputs only and
+//! drive whatever state the user has set; `gate_ui` turns clicks into that
+//! state.
+
+use crate::error::LgsimError;
+use crate::pin::*;
+use crate::types::*;
+use std::collections::HashMap;
+
+pub const MAX_DIP_SWITCHES: usize = 16;
+pub const DEFAULT_DIP_SWITCHES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchKind {
+    /// Output `q`, high only while the button is held.
+    PushButton,
+    /// Output `q`, flipped by each click.
+    Toggle,
+    /// Outputs `q0..q(n-1)`, one latching switch each.
+    DipSwitch,
+    /// Outputs `d0..d3, pressed`. `d` holds the last key pressed and
+    /// `pressed` is high while a key is held.
+    HexKeypad,
+    /// Output `q`, tied to a fixed 0 or 1.
+    Constant,
+}
+
+impl SwitchKind {
+    pub const ALL: [SwitchKind; 5] = [
+        SwitchKind::PushButton,
+        SwitchKind::Toggle,
+        SwitchKind::DipSwitch,
+        SwitchKind::HexKeypad,
+        SwitchKind::Constant,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SwitchKind::PushButton => "Push Button",
+            SwitchKind::Toggle => "Toggle Switch",
+            SwitchKind::DipSwitch => "DIP Switch",
+            SwitchKind::HexKeypad => "Hex Keypad",
+            SwitchKind::Constant => "Constant",
+        }
+    }
+
+    /// Keyword used in the chip text format.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            SwitchKind::PushButton => "button",
+            SwitchKind::Toggle => "toggle",
+            SwitchKind::DipSwitch => "dip",
+            SwitchKind::HexKeypad => "keypad",
+            SwitchKind::Constant => "tie",
+        }
+    }
+
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.keyword() == keyword)
+    }
+}
+
+pub fn check_switches(count: usize) -> Result<(), LgsimError> {
+    if !(1..=MAX_DIP_SWITCHES).contains(&count) {
+        return Err(LgsimError::InvalidWidth {
+            width: count,
+            max: MAX_DIP_SWITCHES,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchGate {
+    pub id: GateId,
+    pub kind: SwitchKind,
+    pub pins: HashMap<PinId, Pin>,
+    pub output: Vec<PinId>,
+    /// Output values, bit `k` driving output `k`.
+    pub state: u64,
+}
+
+impl SwitchGate {
+    /// Creates a device with every output low. `switches` is the size of a
+    /// DIP switch bank and is clamped like the other widths; use
+    /// `check_switches` to report it instead.
+    pub fn new(kind: SwitchKind, switches: usize, id: GateId, ids: &mut IdAllocator) -> Self {
+        let outputs = match kind {
+            SwitchKind::DipSwitch => switches.clamp(1, MAX_DIP_SWITCHES),
+            SwitchKind::HexKeypad => 5,
+            _ => 1,
+        };
+        let (pins, _, output) = gate_pins(id, 0, outputs, ids);
+        let mut gate = Self {
+            id,
+            kind,
+            pins,
+            output,
+            state: 0,
+        };
+        gate.evaluate();
+        gate
+    }
+
+    /// Presses or releases a push button.
+    pub fn press(&mut self, held: bool) {
+        if self.kind == SwitchKind::PushButton {
+            self.state = held as u64;
+        }
+    }
+
+    /// Flips switch `bit` of a toggle switch or DIP switch bank.
+    pub fn toggle(&mut self, bit: usize) {
+        if matches!(self.kind, SwitchKind::Toggle | SwitchKind::DipSwitch)
+            && bit < self.output.len()
+        {
+            self.state ^= 1 << bit;
+        }
+    }
+
+    /// Holds down keypad key `key`, or releases the keypad with `None`.
+    pub fn press_key(&mut self, key: Option<u8>) {
+        if self.kind == SwitchKind::HexKeypad {
+            self.state = match key {
+                Some(key) => (key & 0xF) as u64 | 1 << 4,
+                None => self.state & 0xF,
+            };
+        }
+    }
+
+    pub fn evaluate(&mut self) -> bool {
+        write_bus(&mut self.pins, &self.output, self.state);
+        self.state != 0
+    }
+
+    pub fn set_pin(&mut self, id: &PinId, val: Option<PinValue>) {
+        if let Some(p) = self.pins.get_mut(id) {
+            p.val = val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn switch(kind: SwitchKind, switches: usize) -> SwitchGate {
+        let mut ids = IdAllocator::default();
+        let id = ids.gate();
+        SwitchGate::new(kind, switches, id, &mut ids)
+    }
+
+    fn outputs(w: &mut SwitchGate) -> u64 {
+        w.evaluate();
+        read_bus(&w.pins, &w.output)
+    }
+
+    #[test]
+    fn buttons_and_toggles() {
+        let mut button = switch(SwitchKind::PushButton, 0);
+        assert_eq!(button.pins[&button.output[0]].val, Some(0));
+        button.press(true);
+        assert_eq!(outputs(&mut button), 1);
+        button.toggle(0);
+        assert_eq!(outputs(&mut button), 1);
+        button.press(false);
+        assert_eq!(outputs(&mut button), 0);
+
+        let mut toggle = switch(SwitchKind::Toggle, 0);
+        toggle.toggle(0);
+        assert_eq!(outputs(&mut toggle), 1);
+        toggle.press(false);
+        assert_eq!(outputs(&mut toggle), 1);
+        toggle.toggle(0);
+        assert_eq!(outputs(&mut toggle), 0);
+    }
+
+    #[test]
+    fn dip_switches_latch_each_bit() {
+        let mut dip = switch(SwitchKind::DipSwitch, 4);
+        assert_eq!(dip.output.len(), 4);
+        dip.toggle(0);
+        dip.toggle(3);
+        dip.toggle(4);
+        assert_eq!(outputs(&mut dip), 0b1001);
+        dip.toggle(0);
+        assert_eq!(outputs(&mut dip), 0b1000);
+
+        assert_eq!(
+            switch(SwitchKind::DipSwitch, 99).output.len(),
+            MAX_DIP_SWITCHES
+        );
+        assert!(check_switches(MAX_DIP_SWITCHES).is_ok());
+        assert_eq!(
+            check_switches(0),
+            Err(LgsimError::InvalidWidth {
+                width: 0,
+                max: MAX_DIP_SWITCHES,
+            })
+        );
+    }
+
+    #[test]
+    fn keypad_keeps_the_last_key() {
+        let mut keypad = switch(SwitchKind::HexKeypad, 0);
+        // d0..d3, then pressed.
+        keypad.press_key(Some(0xb));
+        assert_eq!(outputs(&mut keypad), 0x1b);
+        keypad.press_key(None);
+        assert_eq!(outputs(&mut keypad), 0xb);
+        keypad.press_key(Some(0x13));
+        assert_eq!(outputs(&mut keypad), 0x13);
+    }
+
+    #[test]
+    fn constants_ignore_the_user() {
+        let mut tie = switch(SwitchKind::Constant, 0);
+        tie.state = 1;
+        tie.press(false);
+        tie.toggle(0);
+        tie.press_key(None);
+        assert_eq!(outputs(&mut tie), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Static timing: logic depth and the longest paths through a chip. Nested
+//! chips are flattened, AND and NOT gates add their delay, and anything else
+//! (memories, registers, devices) breaks paths: its inputs are where paths
+//! end and its outputs are where new ones start, like the chip's own inputs.
+
+use crate::circuit::Chip;
+use crate::edit;
+use crate::error::LgsimError;
+use crate::fault::Ports;
+use crate::gate::Gate;
+use crate::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Delay of each primitive gate, in arbitrary units. The default is one unit
+/// each, so delays count gates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delays {
+    pub and: u32,
+    pub not: u32,
+}
+
+impl Default for Delays {
+    fn default() -> Self {
+        Self { and: 1, not: 1 }
+    }
+}
+
+/// The slowest path found to a pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrival {
+    pub delay: u32,
+    /// Gates on the path.
+    pub gates: u32,
+    /// The pin before this one on the path.
+    from: Option<PinId>,
+}
+
+/// How a pin gets its value.
+#[derive(Debug, Clone)]
+enum Def {
+    Gate(Vec<PinId>, u32),
+    Wire(PinId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timing {
+    /// Slowest path to each output, `None` for outputs no path reaches.
+    pub outputs: Vec<Option<Arrival>>,
+    /// `paths[i][o]`: delay of the longest path from input `i` to output `o`.
+    pub paths: Vec<Vec<Option<u32>>>,
+    /// Pins along the slowest path to any output, start first.
+    pub critical_path: Vec<PinId>,
+}
+
+impl Timing {
+    pub fn critical_delay(&self) -> Option<u32> {
+        self.outputs.iter().flatten().map(|a| a.delay).max()
+    }
+}
+
+/// Analyses `chip`, with paths starting at `ports.inputs` and ending at
+/// `ports.outputs`.
+pub fn analyze(chip: &Chip, ports: &Ports, delays: Delays) -> Result<Timing, LgsimError> {
+    let flat = edit::flatten(chip)?;
+    let mut defs: HashMap<PinId, Def> = HashMap::new();
+    let mut starts: HashSet<PinId> = ports.inputs.iter().copied().collect();
+    for gate in flat.gates.values() {
+        match gate {
+            Gate::And(g) => {
+                defs.insert(g.output[0], Def::Gate(g.input.clone(), delays.and));
+            }
+            Gate::Not(g) => {
+                defs.insert(g.output[0], Def::Gate(g.input.clone(), delays.not));
+            }
+            other => starts.extend(other.output()),
+        }
+        for pin in gate.input() {
+            if let Some(driver) = flat.driver_of(pin) {
+                defs.insert(*pin, Def::Wire(driver));
+            }
+        }
+    }
+    for pin in &ports.outputs {
+        if !defs.contains_key(pin)
+            && let Some(driver) = flat.driver_of(pin)
+        {
+            defs.insert(*pin, Def::Wire(driver));
+        }
+    }
+    for pin in &starts {
+        defs.remove(pin);
+    }
+    let order = topological_order(&defs)?;
+
+    let all = arrivals(&order, &defs, &starts);
+    let outputs: Vec<Option<Arrival>> = ports.outputs.iter().map(|p| all.get(p).copied()).collect();
+    let paths = ports
+        .inputs
+        .iter()
+        .map(|input| {
+            let from_input = arrivals(&order, &defs, &HashSet::from([*input]));
+            ports
+                .outputs
+                .iter()
+                .map(|p| from_input.get(p).map(|a| a.delay))
+                .collect()
+        })
+        .collect();
+
+    let mut critical_path = Vec::new();
+    let slowest = ports
+        .outputs
+        .iter()
+        .filter_map(|p| Some((*p, all.get(p)?.delay)))
+        .max_by_key(|(_, delay)| *delay);
+    let mut pin = slowest.map(|(p, _)| p);
+    while let Some(p) = pin {
+        critical_path.push(p);
+        pin = all.get(&p).and_then(|a| a.from);
+    }
+    critical_path.reverse();
+    Ok(Timing {
+        outputs,
+        paths,
+        critical_path,
+    })
+}
+
+/// Every defined pin after the pins it reads.
+fn topological_order(defs: &HashMap<PinId, Def>) -> Result<Vec<PinId>, LgsimError> {
+    fn visit(
+        pin: PinId,
+        defs: &HashMap<PinId, Def>,
+        done: &mut HashSet<PinId>,
+        visiting: &mut HashSet<PinId>,
+        order: &mut Vec<PinId>,
+    ) -> Result<(), LgsimError> {
+        if done.contains(&pin) {
+            return Ok(());
+        }
+        if !visiting.insert(pin) {
+            return Err(LgsimError::CombinationalLoop(pin));
+        }
+        match defs.get(&pin) {
+            Some(Def::Gate(inputs, _)) => {
+                for input in inputs {
+                    visit(*input, defs, done, visiting, order)?;
+                }
+            }
+            Some(Def::Wire(driver)) => visit(*driver, defs, done, visiting, order)?,
+            None => {}
+        }
+        visiting.remove(&pin);
+        done.insert(pin);
+        order.push(pin);
+        Ok(())
+    }
+
+    let mut pins: Vec<PinId> = defs.keys().copied().collect();
+    pins.sort();
+    let mut order = Vec::new();
+    let (mut done, mut visiting) = (HashSet::new(), HashSet::new());
+    for pin in pins {
+        visit(pin, defs, &mut done, &mut visiting, &mut order)?;
+    }
+    Ok(order)
+}
+
+/// The slowest path from any of `starts` to each pin they reach.
+fn arrivals(
+    order: &[PinId],
+    defs: &HashMap<PinId, Def>,
+    starts: &HashSet<PinId>,
+) -> HashMap<PinId, Arrival> {
+    let mut arrival: HashMap<PinId, Arrival> = starts
+        .iter()
+        .map(|p| {
+            let start = Arrival {
+                delay: 0,
+                gates: 0,
+                from: None,
+            };
+            (*p, start)
+        })
+        .collect();
+    for pin in order {
+        let reached = match defs.get(pin) {
+            Some(Def::Wire(driver)) => arrival.get(driver).map(|a| Arrival {
+                from: Some(*driver),
+                ..*a
+            }),
+            Some(Def::Gate(inputs, delay)) => inputs
+                .iter()
+                .filter_map(|input| Some((*input, arrival.get(input)?)))
+                .max_by_key(|(_, a)| (a.delay, a.gates))
+                .map(|(input, a)| Arrival {
+                    delay: a.delay + delay,
+                    gates: a.gates + 1,
+                    from: Some(input),
+                }),
+            None => None,
+        };
+        if let Some(a) = reached {
+            arrival.insert(*pin, a);
+        }
+    }
+    arrival
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ChipBuilder;
+    use crate::gate::GateType;
+
+    /// `y = NOT(NOT a) AND b`.
+    fn chain() -> Chip {
+        let mut b = ChipBuilder::new();
+        let (a, c) = (b.input(), b.input());
+        let n1 = b.not(a);
+        let n2 = b.not(n1);
+        let y = b.and(n2, c);
+        b.output(y);
+        b.build().unwrap()
+    }
+
+    fn timing(chip: &Chip, delays: Delays) -> Timing {
+        analyze(chip, &Ports::of_chip(chip), delays).unwrap()
+    }
+
+    #[test]
+    fn finds_the_critical_path() {
+        let chip = chain();
+        let t = timing(&chip, Delays::default());
+        let y = t.outputs[0].unwrap();
+        assert_eq!((y.delay, y.gates), (3, 3));
+        assert_eq!(t.paths, [[Some(3)], [Some(1)]]);
+        assert_eq!(t.critical_delay(), Some(3));
+
+        // Input, then each gate's input and output, then the chip output.
+        assert_eq!(t.critical_path.len(), 8);
+        assert_eq!(t.critical_path.first(), Some(&chip.input[0]));
+        assert_eq!(t.critical_path.last(), Some(&chip.output[0]));
+        let not_pins: HashSet<PinId> = chip
+            .gates
+            .values()
+            .filter(|g| g.gate_type() == GateType::Not)
+            .flat_map(|g| g.input().iter().chain(g.output()).copied())
+            .collect();
+        assert!(not_pins.iter().all(|p| t.critical_path.contains(p)));
+    }
+
+    #[test]
+    fn delays_weight_the_gates() {
+        let chip = chain();
+        let t = timing(&chip, Delays { and: 10, not: 2 });
+        assert_eq!(t.paths, [[Some(14)], [Some(10)]]);
+        let y = t.outputs[0].unwrap();
+        assert_eq!((y.delay, y.gates), (14, 3));
+    }
+
+    #[test]
+    fn nested_chips_are_flattened() {
+        let inverter = crate::circuit! {
+            inputs: [a];
+            let y = not(a);
+            outputs: [y];
+        }
+        .unwrap();
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let once = b.instance(&inverter, &[a]);
+        let twice = b.instance(&inverter, &once);
+        b.output(twice[0]);
+        // An output wired straight to an input has no delay.
+        b.output(a);
+        let chip = b.build().unwrap();
+        let t = timing(&chip, Delays::default());
+        assert_eq!(t.paths, [[Some(2), Some(0)]]);
+        assert_eq!(t.critical_delay(), Some(2));
+    }
+
+    #[test]
+    fn clocked_gates_break_paths() {
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let r = b.gate(GateType::Register);
+        b.connect(a, r.input(0));
+        let q = b.not(r.output(0));
+        b.output(q);
+        // A second input that reaches nothing.
+        b.input();
+        let chip = b.build().unwrap();
+        let t = timing(&chip, Delays::default());
+        // The path starts again at the register's output.
+        assert_eq!(t.outputs[0].map(|a| a.delay), Some(1));
+        assert_eq!(t.paths, [[None], [None]]);
+        assert_eq!(t.critical_path.first(), Some(&r.output(0).0));
+    }
+
+    #[test]
+    fn feedback_is_an_error() {
+        let mut b = ChipBuilder::new();
+        let a = b.input();
+        let g = b.gate(GateType::And);
+        b.connect(a, g.input(0));
+        b.connect(g.output(0), g.input(1));
+        b.output(g.output(0));
+        let chip = b.build().unwrap();
+        assert!(matches!(
+            analyze(&chip, &Ports::of_chip(&chip), Delays::default()),
+            Err(LgsimError::CombinationalLoop(_))
+        ));
+    }
+}
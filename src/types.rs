@@ -1,6 +1,28 @@
-use std::collections::HashMap;
 use crate::gate::Gate;
+use std::collections::HashMap;
 
 pub type PinValue = u8;
-pub type Gates = HashMap<usize, Gate>;
-pub type Connections = HashMap<usize, Vec<usize>>;
+pub type Gates = HashMap<GateId, Gate>;
+pub type Connections = HashMap<PinId, Vec<PinId>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PinId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GateId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChipId(pub usize);
+
+// A chip instance placed inside another chip is addressed as a gate of its parent.
+impl From<ChipId> for GateId {
+    fn from(id: ChipId) -> Self {
+        GateId(id.0)
+    }
+}
+
+impl From<GateId> for ChipId {
+    fn from(id: GateId) -> Self {
+        ChipId(id.0)
+    }
+}